@@ -13,11 +13,19 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, Expr, ExprArray, Lit};
+use syn::parse::{Parse, ParseStream};
+use syn::{parse_macro_input, DeriveInput, Expr, ExprArray, Lit, Token, Type};
+
+mod state_columns;
+mod state_derive;
 
 /// Validates SQL migration strings at compile time and re-emits them as a
 /// `&[&'static str]` slice literal.
 ///
+/// Given a state deriving `StateFields` first, it also checks that the columns
+/// of the CREATE TABLE migration, as altered by any ALTER TABLE migrations after
+/// it, match the state's fields, and the other way round.
+///
 /// # Example
 /// ```
 /// use chaindexing_macros::state_migrations;
@@ -26,10 +34,19 @@ use syn::{parse_macro_input, Expr, ExprArray, Lit};
 ///     r#"CREATE INDEX IF NOT EXISTS idx_id ON foo(id)"#
 /// ]);
 /// ```
+///
+/// ```ignore
+/// const NFT_MIGRATIONS: &[&str] = state_migrations!(Nft, [
+///     "CREATE TABLE IF NOT EXISTS nfts (token_id BIGINT NOT NULL)"
+/// ]);
+/// ```
 #[proc_macro]
 pub fn state_migrations(input: TokenStream) -> TokenStream {
-    // Expect an array literal like ["SQL1", "SQL2", ...]
-    let ExprArray { elems, .. } = parse_macro_input!(input as ExprArray);
+    // Expect an array literal like ["SQL1", "SQL2", ...], optionally preceded by the state
+    let StateMigrationsInput {
+        state,
+        migrations: ExprArray { elems, .. },
+    } = parse_macro_input!(input as StateMigrationsInput);
 
     let dialect = sqlparser::dialect::PostgreSqlDialect {};
 
@@ -59,10 +76,118 @@ pub fn state_migrations(input: TokenStream) -> TokenStream {
         }
     }
 
+    let assertions = match state {
+        Some(state) => {
+            let migrations: Vec<_> = elems
+                .iter()
+                .map(|expr| match expr {
+                    Expr::Lit(expr_lit) => match &expr_lit.lit {
+                        Lit::Str(s) => s.value(),
+                        _ => unreachable!(),
+                    },
+                    _ => unreachable!(),
+                })
+                .collect();
+
+            match state_columns::get_assertions(&state, &migrations) {
+                Ok(assertions) => assertions,
+                Err(msg) => return quote! { compile_error!(#msg); }.into(),
+            }
+        }
+        None => quote! {},
+    };
+
     // All good – turn the string literals into a slice reference.
     let elems_iter = elems.iter();
     quote! {
-        &[ #( #elems_iter ),* ]
+        {
+            #assertions
+
+            &[ #( #elems_iter ),* ]
+        }
     }
     .into()
 }
+
+struct StateMigrationsInput {
+    state: Option<Type>,
+    migrations: ExprArray,
+}
+
+impl Parse for StateMigrationsInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let state = if input.peek(syn::token::Bracket) {
+            None
+        } else {
+            let state = input.parse()?;
+            input.parse::<Token![,]>()?;
+            Some(state)
+        };
+
+        Ok(Self {
+            state,
+            migrations: input.parse()?,
+        })
+    }
+}
+
+/// Derives `ContractState` along with a `{Name}Migrations` struct whose
+/// `StateMigrations` create the state's table from its fields.
+///
+/// The table is named after the struct, snake-cased and pluralized, unless
+/// overridden with `#[state(table_name = "...")]`. Fields marked `#[index]`
+/// or `#[unique]` get an index or a unique index. Fields marked `#[identity]`
+/// identify the state for updates and deletes, and get indexed together. `Option` fields are nullable,
+/// `U256`/`I256` ones are `NUMERIC(78, 0)` and other non-primitive ones are JSONB.
+/// `I256` fields have to be serialized signed, with `#[serde(with = "i256")]`.
+/// SQL reserved words such as `from` cannot name tables or columns, so such
+/// fields have to be renamed with `#[serde(rename = "...")]`.
+///
+/// # Example
+/// ```ignore
+/// use chaindexing::prelude::*;
+///
+/// #[derive(Clone, Debug, Serialize, Deserialize, ContractState)]
+/// struct Nft {
+///     #[identity]
+///     token_id: u32,
+///     #[index]
+///     owner_address: String,
+/// }
+///
+/// let contract = Contract::new("BoredApeYachtClub").add_state_migrations(NftMigrations);
+/// ```
+#[proc_macro_derive(ContractState, attributes(state, identity, index, unique))]
+pub fn derive_contract_state(input: TokenStream) -> TokenStream {
+    derive_state(input, "ContractState")
+}
+
+/// Derives `StateFields`, for `state_migrations!` to check the state's columns against
+#[proc_macro_derive(StateFields)]
+pub fn derive_state_fields(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    state_derive::expand_state_fields(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Derives `ChainState` the same way `ContractState` gets derived
+#[proc_macro_derive(ChainState, attributes(state, identity, index, unique))]
+pub fn derive_chain_state(input: TokenStream) -> TokenStream {
+    derive_state(input, "ChainState")
+}
+
+/// Derives `MultiChainState` the same way `ContractState` gets derived
+#[proc_macro_derive(MultiChainState, attributes(state, identity, index, unique))]
+pub fn derive_multi_chain_state(input: TokenStream) -> TokenStream {
+    derive_state(input, "MultiChainState")
+}
+
+fn derive_state(input: TokenStream, state_trait: &str) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    state_derive::expand(input, state_trait)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
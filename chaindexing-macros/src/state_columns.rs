@@ -0,0 +1,180 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use sqlparser::ast::{AlterTableOperation, ColumnDef, ColumnOption, Statement};
+use syn::Type;
+
+/// Columns Chaindexing fills in from the event getting handled
+const CHAINDEXING_COLUMNS: [&str; 11] = [
+    "state_version_group_id",
+    "contract_address",
+    "chain_id",
+    "block_hash",
+    "block_number",
+    "transaction_hash",
+    "transaction_index",
+    "log_index",
+    "block_timestamp",
+    "created_at_block",
+    "created_at_timestamp",
+];
+
+/// Const assertions checking the table's columns, as created and then altered by
+/// the migrations, against the state's `StateFields`, and the other way round.
+pub fn get_assertions(state: &Type, migrations: &[String]) -> Result<TokenStream, String> {
+    let dialect = sqlparser::dialect::PostgreSqlDialect {};
+    let statements: Vec<_> = migrations
+        .iter()
+        .flat_map(|migration| sqlparser::parser::Parser::parse_sql(&dialect, migration).unwrap())
+        .collect();
+    let create_table = statements
+        .iter()
+        .find_map(|statement| match statement {
+            Statement::CreateTable(create_table) => Some(create_table),
+            _ => None,
+        })
+        .ok_or("state_migrations! expects a CREATE TABLE migration for the state")?;
+
+    let state_name = quote!(#state).to_string();
+    let table_name = create_table.name.to_string();
+    let columns = get_columns(&create_table.columns, &table_name, &statements);
+
+    let column_assertions = columns.iter().map(|column| {
+        let column_name = column.name.value.as_str();
+        let kind = format_ident!("{}", to_column_kind(column));
+        let is_nullable = is_nullable(column);
+        let is_required = is_required(column);
+        let message = format!(
+            "column `{column_name}` of `{table_name}` has no compatible field in `{state_name}`"
+        );
+
+        quote! {
+            assert!(
+                ::chaindexing::states::has_compatible_field(
+                    <#state as ::chaindexing::states::StateFields>::FIELDS,
+                    #column_name,
+                    ::chaindexing::states::StateFieldKind::#kind,
+                    #is_nullable,
+                    #is_required,
+                ),
+                #message
+            );
+        }
+    });
+
+    let column_names = columns.iter().map(|column| column.name.value.as_str());
+    let message = format!("every field of `{state_name}` needs a column in `{table_name}`");
+
+    Ok(quote! {
+        const _: () = {
+            #( #column_assertions )*
+
+            assert!(
+                ::chaindexing::states::has_column_for_every_field(
+                    <#state as ::chaindexing::states::StateFields>::FIELDS,
+                    &[ #( #column_names ),* ],
+                ),
+                #message
+            );
+        };
+    })
+}
+
+/// Columns of the table after the ALTER TABLE migrations adding, dropping or renaming them
+fn get_columns(
+    created_columns: &[ColumnDef],
+    table_name: &str,
+    statements: &[Statement],
+) -> Vec<ColumnDef> {
+    let mut columns = created_columns.to_vec();
+
+    for statement in statements {
+        let Statement::AlterTable {
+            name, operations, ..
+        } = statement
+        else {
+            continue;
+        };
+        if name.to_string() != table_name {
+            continue;
+        }
+
+        for operation in operations {
+            match operation {
+                AlterTableOperation::AddColumn { column_def, .. } => {
+                    columns.push(column_def.clone());
+                }
+                AlterTableOperation::DropColumn { column_names, .. } => {
+                    columns.retain(|column| !column_names.contains(&column.name));
+                }
+                AlterTableOperation::RenameColumn {
+                    old_column_name,
+                    new_column_name,
+                } => {
+                    for column in columns.iter_mut().filter(|c| c.name == *old_column_name) {
+                        column.name = new_column_name.clone();
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    columns
+}
+
+/// Variant of `StateFieldKind` the column stores
+fn to_column_kind(column: &ColumnDef) -> &'static str {
+    let data_type = column.data_type.to_string().to_uppercase();
+
+    if data_type.ends_with("[]") {
+        "Other"
+    } else if data_type.starts_with("BOOL") {
+        "Bool"
+    } else if [
+        "SMALLINT",
+        "INT",
+        "BIGINT",
+        "SERIAL",
+        "SMALLSERIAL",
+        "BIGSERIAL",
+    ]
+    .iter()
+    .any(|prefix| data_type.starts_with(prefix))
+    {
+        "Integer"
+    } else if data_type.starts_with("NUMERIC") || data_type.starts_with("DECIMAL") {
+        "Numeric"
+    } else if ["REAL", "DOUBLE", "FLOAT"].iter().any(|prefix| data_type.starts_with(prefix)) {
+        "Float"
+    } else if ["TEXT", "VARCHAR", "CHAR"].iter().any(|prefix| data_type.starts_with(prefix)) {
+        "Text"
+    } else if data_type.starts_with("JSON") {
+        "Json"
+    } else {
+        "Other"
+    }
+}
+
+fn is_nullable(column: &ColumnDef) -> bool {
+    !column.options.iter().any(|option| {
+        matches!(
+            option.option,
+            ColumnOption::NotNull
+                | ColumnOption::Unique {
+                    is_primary: true,
+                    ..
+                }
+        )
+    })
+}
+
+/// Whether inserting states fails without the column getting a value
+fn is_required(column: &ColumnDef) -> bool {
+    let is_serial = column.data_type.to_string().to_uppercase().ends_with("SERIAL");
+    let has_default = column
+        .options
+        .iter()
+        .any(|option| matches!(option.option, ColumnOption::Default(_)));
+
+    !is_serial && !has_default && !CHAINDEXING_COLUMNS.contains(&column.name.value.as_str())
+}
@@ -0,0 +1,395 @@
+use proc_macro2::TokenStream;
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Field, Fields, GenericArgument, LitStr, PathArguments, Type};
+
+/// Expands `#[derive(ContractState)]` and its siblings into the state trait's impl,
+/// plus a `{Name}Migrations` struct creating the state's table.
+pub fn expand(input: DeriveInput, state_trait: &str) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let visibility = &input.vis;
+    let state_trait = format_ident!("{state_trait}");
+    let migrations_name = format_ident!("{name}Migrations");
+
+    let table_name = match get_table_name_attribute(&input)? {
+        Some(table_name) => table_name,
+        None => to_table_name(&name.to_string()),
+    };
+    check_not_reserved(&table_name, name)?;
+
+    let fields = get_fields(&input)?;
+
+    let mut columns = vec![];
+    let mut identity_fields = vec![];
+    let mut index_migrations = vec![];
+    for field in fields {
+        check_i256_serde(field)?;
+        let column_name = get_column_name(field)?;
+        columns.push(format!("{column_name} {}", to_column_type(&field.ty)));
+
+        for attribute in &field.attrs {
+            if attribute.path().is_ident("identity") {
+                identity_fields.push(column_name.clone());
+            } else if attribute.path().is_ident("index") {
+                index_migrations.push(format!(
+                    "CREATE INDEX IF NOT EXISTS {table_name}_{column_name}_index ON {table_name}({column_name})"
+                ));
+            } else if attribute.path().is_ident("unique") {
+                index_migrations.push(format!(
+                    "CREATE UNIQUE INDEX IF NOT EXISTS {table_name}_{column_name}_unique_index ON {table_name}({column_name})"
+                ));
+            }
+        }
+    }
+
+    if !identity_fields.is_empty() {
+        index_migrations.push(format!(
+            "CREATE INDEX IF NOT EXISTS {table_name}_identity_index ON {table_name}({})",
+            identity_fields.join(", ")
+        ));
+    }
+
+    let create_table_migration = format!(
+        "CREATE TABLE IF NOT EXISTS {table_name} ({})",
+        columns.join(", ")
+    );
+    let migrations = std::iter::once(create_table_migration).chain(index_migrations);
+
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    Ok(quote! {
+        impl #impl_generics ::chaindexing::states::#state_trait for #name #type_generics #where_clause {
+            fn table_name() -> &'static str {
+                #table_name
+            }
+
+            fn identity_fields() -> &'static [&'static str] {
+                &[ #( #identity_fields ),* ]
+            }
+        }
+
+        /// Migrations creating the state's table, derived from its fields
+        #visibility struct #migrations_name;
+
+        impl ::chaindexing::states::StateMigrations for #migrations_name {
+            fn migrations(&self) -> &'static [&'static str] {
+                &[ #( #migrations ),* ]
+            }
+        }
+    })
+}
+
+/// Expands `#[derive(StateFields)]`, listing the fields `state_migrations!` checks columns against
+pub fn expand_state_fields(input: DeriveInput) -> syn::Result<TokenStream> {
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+
+    let fields = get_fields(&input)?
+        .into_iter()
+        .map(|field| {
+            check_i256_serde(field)?;
+            let column_name = get_column_name(field)?;
+            let (ty, is_nullable) = match get_option_inner_type(&field.ty) {
+                Some(inner_type) => (inner_type, true),
+                None => (&field.ty, false),
+            };
+            let kind = format_ident!("{}", to_field_kind(ty));
+
+            Ok(quote! {
+                ::chaindexing::states::StateField {
+                    name: #column_name,
+                    kind: ::chaindexing::states::StateFieldKind::#kind,
+                    is_nullable: #is_nullable,
+                }
+            })
+        })
+        .collect::<syn::Result<Vec<_>>>()?;
+
+    Ok(quote! {
+        impl #impl_generics ::chaindexing::states::StateFields for #name #type_generics #where_clause {
+            const FIELDS: &'static [::chaindexing::states::StateField] = &[ #( #fields ),* ];
+        }
+    })
+}
+
+fn get_fields(input: &DeriveInput) -> syn::Result<Vec<&Field>> {
+    match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => Ok(fields.named.iter().collect()),
+            _ => Err(syn::Error::new_spanned(
+                &input.ident,
+                "states must have named fields",
+            )),
+        },
+        _ => Err(syn::Error::new_spanned(
+            &input.ident,
+            "states must be structs",
+        )),
+    }
+}
+
+/// `#[state(table_name = "...")]`, to override the table name derived from the struct's
+fn get_table_name_attribute(input: &DeriveInput) -> syn::Result<Option<String>> {
+    let mut table_name = None;
+
+    for attribute in input.attrs.iter().filter(|a| a.path().is_ident("state")) {
+        attribute.parse_nested_meta(|meta| {
+            if meta.path.is_ident("table_name") {
+                table_name = Some(meta.value()?.parse::<LitStr>()?.value());
+                Ok(())
+            } else {
+                Err(meta.error("unsupported state attribute"))
+            }
+        })?;
+    }
+
+    Ok(table_name)
+}
+
+/// Columns are named after the fields, or what serde renames them to
+fn get_column_name(field: &Field) -> syn::Result<String> {
+    let column_name = get_serde_value(field, &["rename"])?
+        .unwrap_or_else(|| field.ident.as_ref().unwrap().to_string());
+    check_not_reserved(&column_name, field)?;
+
+    Ok(column_name)
+}
+
+/// PostgreSQL key words that cannot name tables or columns unless quoted
+const RESERVED_WORDS: &str =
+    "all analyse analyze and any array as asc asymmetric authorization binary both case \
+    cast check collate collation column concurrently constraint create cross \
+    current_catalog current_date current_role current_schema current_time \
+    current_timestamp current_user default deferrable desc distinct do else end except \
+    false fetch for foreign freeze from full grant group having ilike in initially inner \
+    intersect into is isnull join lateral leading left like limit localtime localtimestamp \
+    natural not notnull null offset on only or order outer overlaps placing primary \
+    references returning right select session_user similar some symmetric system_user \
+    table tablesample then to trailing true union unique user using variadic verbose when \
+    where window with";
+
+/// States' queries use table and column names unquoted
+fn check_not_reserved(identifier: &str, tokens: impl quote::ToTokens) -> syn::Result<()> {
+    let identifier_lowercase = identifier.to_lowercase();
+
+    if RESERVED_WORDS.split_whitespace().any(|word| word == identifier_lowercase) {
+        Err(syn::Error::new_spanned(
+            tokens,
+            format!(
+                "{identifier} is an SQL reserved word, so it cannot name states' tables or columns"
+            ),
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// I256 fields serialize as their two's complement by default, which NUMERIC(78, 0)
+/// columns would store as huge positive numbers
+fn check_i256_serde(field: &Field) -> syn::Result<()> {
+    let ty = get_option_inner_type(&field.ty).unwrap_or(&field.ty);
+    let is_i256 = matches!(ty, Type::Path(type_path)
+        if type_path.path.segments.last().unwrap().ident == "I256");
+
+    if is_i256 && get_serde_value(field, &["with", "serialize_with"])?.is_none() {
+        Err(syn::Error::new_spanned(
+            &field.ty,
+            "I256 fields need #[serde(with = \"i256\")] to be stored signed",
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Value of the first of the keys found in the field's `#[serde(...)]` attributes
+fn get_serde_value(field: &Field, keys: &[&str]) -> syn::Result<Option<String>> {
+    let mut value = None;
+
+    for attribute in field.attrs.iter().filter(|a| a.path().is_ident("serde")) {
+        attribute.parse_nested_meta(|meta| {
+            if keys.iter().any(|key| meta.path.is_ident(key)) {
+                let key_value = meta.value()?.parse::<LitStr>()?.value();
+                value.get_or_insert(key_value);
+            } else if meta.input.peek(syn::Token![=]) {
+                meta.value()?.parse::<syn::Expr>()?;
+            } else if meta.input.peek(syn::token::Paren) {
+                meta.input.parse::<proc_macro2::Group>()?;
+            }
+
+            Ok(())
+        })?;
+    }
+
+    Ok(value)
+}
+
+/// `TokenBalance` gets stored in `token_balances`, `Entry` in `entries`.
+/// Irregular plurals need `#[state(table_name = "...")]`
+fn to_table_name(struct_name: &str) -> String {
+    let mut table_name = String::new();
+
+    for (index, character) in struct_name.chars().enumerate() {
+        if character.is_uppercase() && index != 0 {
+            table_name.push('_');
+        }
+        table_name.push(character.to_ascii_lowercase());
+    }
+
+    pluralize(&table_name)
+}
+
+fn pluralize(word: &str) -> String {
+    if let Some(stem) = word.strip_suffix('y') {
+        if !stem.ends_with(['a', 'e', 'i', 'o', 'u']) {
+            return format!("{stem}ies");
+        }
+    }
+
+    if ["s", "x", "z", "ch", "sh"].iter().any(|suffix| word.ends_with(suffix)) {
+        format!("{word}es")
+    } else {
+        format!("{word}s")
+    }
+}
+
+fn to_column_type(ty: &Type) -> String {
+    match get_option_inner_type(ty) {
+        Some(inner_type) => to_sql_type(inner_type),
+        None => format!("{} NOT NULL", to_sql_type(ty)),
+    }
+}
+
+fn to_sql_type(ty: &Type) -> String {
+    let type_name = match ty {
+        Type::Path(type_path) => type_path.path.segments.last().unwrap().ident.to_string(),
+        _ => return "JSONB".to_string(),
+    };
+
+    match type_name.as_str() {
+        "bool" => "BOOLEAN",
+        "i8" | "i16" | "u8" => "SMALLINT",
+        "i32" | "u16" => "INTEGER",
+        "i64" | "u32" | "isize" | "usize" => "BIGINT",
+        "u64" => "NUMERIC(20, 0)",
+        "i128" | "u128" => "NUMERIC(39, 0)",
+        "U256" | "I256" => "NUMERIC(78, 0)",
+        "f32" => "REAL",
+        "f64" => "DOUBLE PRECISION",
+        "String" | "char" | "Address" | "H160" | "H256" => "TEXT",
+        _ => "JSONB",
+    }
+    .to_string()
+}
+
+/// Variant of `StateFieldKind` the field serializes as
+fn to_field_kind(ty: &Type) -> &'static str {
+    let type_name = match ty {
+        Type::Path(type_path) => type_path.path.segments.last().unwrap().ident.to_string(),
+        Type::Reference(_) => return "Text",
+        _ => return "Json",
+    };
+
+    match type_name.as_str() {
+        "bool" => "Bool",
+        "i8" | "i16" | "i32" | "i64" | "isize" | "u8" | "u16" | "u32" | "u64" | "usize" => {
+            "Integer"
+        }
+        "i128" | "u128" | "U256" | "I256" => "Numeric",
+        "f32" | "f64" => "Float",
+        "String" | "char" | "Address" | "H160" | "H256" => "Text",
+        "Vec" | "HashMap" | "BTreeMap" | "Value" => "Json",
+        _ => "Other",
+    }
+}
+
+fn get_option_inner_type(ty: &Type) -> Option<&Type> {
+    let Type::Path(type_path) = ty else {
+        return None;
+    };
+    let segment = type_path.path.segments.last()?;
+
+    match &segment.arguments {
+        PathArguments::AngleBracketed(arguments) if segment.ident == "Option" => {
+            match arguments.args.first()? {
+                GenericArgument::Type(inner_type) => Some(inner_type),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn derives_table_names_from_struct_names() {
+        assert_eq!(to_table_name("Nft"), "nfts");
+        assert_eq!(to_table_name("TokenBalance"), "token_balances");
+        assert_eq!(to_table_name("Entry"), "entries");
+        assert_eq!(to_table_name("Status"), "statuses");
+        assert_eq!(to_table_name("TokenSupply"), "token_supplies");
+        assert_eq!(to_table_name("Box"), "boxes");
+        assert_eq!(to_table_name("Match"), "matches");
+        assert_eq!(to_table_name("Key"), "keys");
+    }
+
+    #[test]
+    fn maps_field_types_to_column_types() {
+        let column_type = |ty: &str| to_column_type(&syn::parse_str(ty).unwrap());
+
+        assert_eq!(column_type("u32"), "BIGINT NOT NULL");
+        assert_eq!(
+            column_type("ethers::types::U256"),
+            "NUMERIC(78, 0) NOT NULL"
+        );
+        assert_eq!(column_type("Option<String>"), "TEXT");
+        assert_eq!(column_type("char"), "TEXT NOT NULL");
+        assert_eq!(column_type("Vec<u32>"), "JSONB NOT NULL");
+    }
+
+    #[test]
+    fn maps_field_types_to_field_kinds() {
+        let field_kind = |ty: &str| to_field_kind(&syn::parse_str(ty).unwrap());
+
+        assert_eq!(field_kind("u64"), "Integer");
+        assert_eq!(field_kind("I256"), "Numeric");
+        assert_eq!(field_kind("serde_json::Value"), "Json");
+        assert_eq!(field_kind("NftKind"), "Other");
+    }
+
+    #[test]
+    fn rejects_sql_reserved_words_as_table_and_column_names() {
+        let state = |fields: &str| -> DeriveInput {
+            syn::parse_str(&format!("struct Transfer {{ {fields} }}")).unwrap()
+        };
+
+        assert!(expand(state("from: String"), "ContractState").is_err());
+        assert!(expand_state_fields(state("from: String")).is_err());
+        assert!(expand(
+            syn::parse_str("#[state(table_name = \"order\")] struct Order { id: u32 }").unwrap(),
+            "ContractState"
+        )
+        .is_err());
+        assert!(expand(
+            state("#[serde(rename = \"from_address\")] from: String"),
+            "ContractState"
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn rejects_i256_fields_not_serialized_signed() {
+        let state = |fields: &str| -> DeriveInput {
+            syn::parse_str(&format!("struct Position {{ {fields} }}")).unwrap()
+        };
+
+        assert!(expand(state("liquidity_delta: I256"), "ContractState").is_err());
+        assert!(expand_state_fields(state("liquidity_delta: Option<I256>")).is_err());
+        assert!(expand(
+            state("#[serde(with = \"i256\")] liquidity_delta: I256"),
+            "ContractState"
+        )
+        .is_ok());
+    }
+}
@@ -2,8 +2,20 @@ mod contracts;
 mod events;
 mod handlers;
 mod providers;
+mod states;
 
-pub use contracts::{bayc_contract, BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER};
-pub use events::{transfer_event_with_contract, unique_transfer_event_with_contract};
+pub use contracts::{
+    bayc_contract, unsaved_contract_address, BAYC_CONTRACT_ADDRESS,
+    BAYC_CONTRACT_START_BLOCK_NUMBER,
+};
+pub use events::{
+    transfer_event_with_contract, unique_transfer_event_with_contract,
+    unique_transfer_events_in_transaction, unique_transfer_events_with_contract_at,
+};
 pub use handlers::{ApprovalForAllTestHandler, TransferTestHandler};
 pub use providers::{empty_provider, transfer_log};
+pub use states::{
+    generate_unique_token_id, transfer_rollup, Listing, ListingMigrations, Nft, NftMigrations,
+    Offer, OfferMigrations, TokenBalance, TokenBalanceMigrations, TokenSupply,
+    TokenSupplyMigrations, TransferRollup,
+};
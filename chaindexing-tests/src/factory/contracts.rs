@@ -1,4 +1,4 @@
-use chaindexing::{ChainId, Contract};
+use chaindexing::{ChainId, Contract, UnsavedContractAddress};
 
 use super::{ApprovalForAllTestHandler, TransferTestHandler};
 
@@ -14,3 +14,7 @@ pub fn bayc_contract(name: &str, two_digit_nonce: &str) -> Contract<()> {
             17773490,
         )
 }
+
+pub fn unsaved_contract_address(contract_name: &str, address: &str) -> UnsavedContractAddress {
+    UnsavedContractAddress::new(contract_name, address, &ChainId::Mainnet, 17773490)
+}
@@ -39,6 +39,64 @@ pub fn unique_transfer_event_with_contract(contract: Contract<()>) -> Event {
     )
 }
 
+/// Generate unique events in consecutive blocks with the given block timestamps
+pub fn unique_transfer_events_with_contract_at(
+    contract: Contract<()>,
+    block_timestamps: &[i64],
+) -> Vec<Event> {
+    let contract_address = contract.addresses.first().unwrap().address.as_str();
+    let first_block_number =
+        unique_transfer_log_with_contract_name(contract_address, &contract.name).block_number;
+
+    block_timestamps
+        .iter()
+        .enumerate()
+        .map(|(index, block_timestamp)| {
+            let mut transfer_log =
+                unique_transfer_log_with_contract_name(contract_address, &contract.name);
+            transfer_log.block_number = first_block_number.map(|number| number + index);
+
+            Event::new(
+                &transfer_log,
+                &ContractEvent::new(
+                    "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)",
+                ),
+                &ChainId::Mainnet,
+                &contract.name,
+                *block_timestamp,
+            )
+        })
+        .collect()
+}
+
+/// Generate unique events emitted together in a single transaction, in log order
+pub fn unique_transfer_events_in_transaction(contract: Contract<()>, count: usize) -> Vec<Event> {
+    let contract_address = contract.addresses.first().unwrap().address.as_str();
+    let first_transfer_log =
+        unique_transfer_log_with_contract_name(contract_address, &contract.name);
+
+    (0..count)
+        .map(|index| {
+            let log_index = first_transfer_log.log_index.map(|log_index| log_index + index);
+            let transfer_log = Log {
+                log_index,
+                transaction_log_index: log_index,
+                ..first_transfer_log.clone()
+            };
+
+            Event::new(
+                &transfer_log,
+                &ContractEvent::new(
+                    "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)",
+                ),
+                &ChainId::Mainnet,
+                &contract.name,
+                1_i64,
+            )
+        })
+        .collect()
+}
+
 /// Generate a unique log that's guaranteed to be unique across parallel test execution
 pub fn unique_transfer_log_with_contract_name(contract_address: &str, contract_name: &str) -> Log {
     use std::process;
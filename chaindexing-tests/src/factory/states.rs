@@ -0,0 +1,177 @@
+use chaindexing::augmenting_std::serde::{Deserialize, Serialize};
+use chaindexing::{
+    state_migrations,
+    states::{i256, ContractState, MultiChainState, Rollup, StateFields, StateMigrations},
+};
+use ethers::types::{I256, U256};
+
+/// Generates a unique token ID for each test to avoid conflicts
+pub fn generate_unique_token_id() -> i32 {
+    use std::sync::atomic::{AtomicI32, Ordering};
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    static TOKEN_COUNTER: AtomicI32 = AtomicI32::new(0);
+
+    let counter = TOKEN_COUNTER.fetch_add(1, Ordering::SeqCst);
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i32;
+    let thread_id = format!("{:?}", std::thread::current().id())
+        .chars()
+        .filter(|c| c.is_ascii_digit())
+        .collect::<String>()
+        .parse::<i32>()
+        .unwrap_or(1);
+
+    // Generate a unique token ID that's very unlikely to collide
+    // Using a large range to minimize collision probability
+    100_000 + (timestamp % 10_000) + (counter % 1_000) + (thread_id % 100)
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+pub struct Nft {
+    pub token_id: i32,
+}
+impl ContractState for Nft {
+    fn table_name() -> &'static str {
+        "nfts"
+    }
+}
+pub struct NftMigrations;
+impl StateMigrations for NftMigrations {
+    fn migrations(&self) -> &'static [&'static str] {
+        &["CREATE TABLE IF NOT EXISTS nfts (
+            token_id INTEGER NOT NULL)"]
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, StateFields)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+pub struct TokenBalance {
+    pub token_id: i32,
+    pub amount: U256,
+    #[serde(with = "i256")]
+    pub delta: I256,
+    pub is_locked: bool,
+    pub memo: Option<String>,
+    pub metadata: serde_json::Value,
+}
+impl ContractState for TokenBalance {
+    fn table_name() -> &'static str {
+        "token_balances"
+    }
+
+    fn identity_fields() -> &'static [&'static str] {
+        &["token_id"]
+    }
+}
+pub struct TokenBalanceMigrations;
+impl StateMigrations for TokenBalanceMigrations {
+    fn migrations(&self) -> &'static [&'static str] {
+        state_migrations!(
+            TokenBalance,
+            ["CREATE TABLE IF NOT EXISTS token_balances (
+                token_id INTEGER NOT NULL,
+                amount NUMERIC(78, 0) NOT NULL,
+                delta NUMERIC(78, 0) NOT NULL,
+                is_locked BOOLEAN NOT NULL,
+                memo TEXT,
+                metadata JSONB NOT NULL)"]
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, ContractState)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+pub struct Listing {
+    #[identity]
+    #[unique]
+    pub token_id: i32,
+    #[index]
+    pub seller: String,
+    pub price: U256,
+    pub note: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, StateFields)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+pub struct Offer {
+    pub token_id: i32,
+    pub buyer: String,
+    pub amount: Option<U256>,
+}
+impl ContractState for Offer {
+    fn table_name() -> &'static str {
+        "offers"
+    }
+}
+pub struct OfferMigrations;
+impl StateMigrations for OfferMigrations {
+    fn migrations(&self) -> &'static [&'static str] {
+        state_migrations!(
+            Offer,
+            [
+                "CREATE TABLE IF NOT EXISTS offers (
+                    token_id INTEGER NOT NULL,
+                    bidder TEXT NOT NULL)",
+                "ALTER TABLE offers ADD COLUMN amount NUMERIC(78, 0)",
+                "ALTER TABLE offers RENAME COLUMN bidder TO buyer",
+                "CREATE INDEX IF NOT EXISTS offers_buyer_index ON offers(buyer)"
+            ]
+        )
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, MultiChainState)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+#[state(table_name = "token_supplies")]
+pub struct TokenSupply {
+    #[identity]
+    #[unique]
+    pub token_id: i32,
+    pub amount: U256,
+}
+
+pub fn transfer_rollup() -> Rollup {
+    Rollup::new("transfer_rollups", 3600)
+        .group_by("token_id", "INTEGER")
+        .count()
+        .sum("amount", "NUMERIC(78, 0)")
+        .ohlc("price", "NUMERIC(78, 0)")
+        .count_distinct("buyer")
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(crate = "chaindexing::augmenting_std::serde")]
+pub struct TransferRollup {
+    pub token_id: i32,
+    pub bucket_start: i64,
+    pub count: i64,
+    pub amount_sum: U256,
+    pub price_open: U256,
+    pub price_high: U256,
+    pub price_low: U256,
+    pub price_close: U256,
+    pub buyer_distinct_count: i64,
+}
+impl TransferRollup {
+    pub fn new(
+        token_id: i32,
+        bucket_start: i64,
+        count: i64,
+        amount_sum: u64,
+        [open, high, low, close]: [u64; 4],
+        buyer_distinct_count: i64,
+    ) -> Self {
+        Self {
+            token_id,
+            bucket_start,
+            count,
+            amount_sum: U256::from(amount_sum),
+            price_open: U256::from(open),
+            price_high: U256::from(high),
+            price_low: U256::from(low),
+            price_close: U256::from(close),
+            buyer_distinct_count,
+        }
+    }
+}
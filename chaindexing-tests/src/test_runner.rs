@@ -1,7 +1,7 @@
 use crate::db;
 use chaindexing::{
     ChaindexingRepo, ChaindexingRepoAsyncConnection, ChaindexingRepoClient, ChaindexingRepoConn,
-    ChaindexingRepoPool, HasRawQueryClient, Repo,
+    ChaindexingRepoPool, ExecutesWithRawQuery, HasRawQueryClient, Repo,
 };
 use dotenvy::dotenv;
 use std::env;
@@ -98,6 +98,28 @@ pub fn new_repo() -> ChaindexingRepo {
     ChaindexingRepo::new(db::database_url().as_str())
 }
 
+/// Repo whose connections resolve tables from a fresh schema, isolating
+/// tests that need chaindexing's tables all to themselves
+pub async fn new_repo_in_schema(schema: &str) -> ChaindexingRepo {
+    let repo_client = new_repo().get_client().await;
+    ChaindexingRepo::execute(
+        &repo_client,
+        &format!("DROP SCHEMA IF EXISTS {schema} CASCADE"),
+    )
+    .await;
+    ChaindexingRepo::execute(&repo_client, &format!("CREATE SCHEMA {schema}")).await;
+
+    let repo = ChaindexingRepo::new(&format!(
+        "{}?options=-csearch_path%3D{schema}",
+        db::database_url()
+    ));
+    let repo_client = repo.get_client().await;
+    chaindexing::booting::setup_root(&repo_client).await;
+    chaindexing::booting::run_internal_migrations(&repo_client).await;
+
+    repo
+}
+
 fn should_setup_test_db() -> bool {
     dotenv().ok();
 
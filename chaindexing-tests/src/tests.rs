@@ -1,6 +1,19 @@
+mod booting;
+mod graphql;
+mod handlers;
 mod ingester;
+mod invariants;
+mod multi_chain_states;
+mod query_client;
+mod reindexing;
 mod repos;
+mod rest;
+mod rewinding;
+mod rollups;
+mod state_changes;
+mod state_history;
 mod states;
+mod transaction_handlers;
 
 pub async fn setup() {
     states::setup().await;
@@ -0,0 +1,173 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::StateMigrations;
+    use chaindexing::{
+        ChainId, ChaindexingRepo, Config, Contract, EventContext, EventHandler,
+        ExecutesWithRawQuery, HasRawQueryClient,
+    };
+
+    use crate::factory::{TransferTestHandler, BAYC_CONTRACT_START_BLOCK_NUMBER};
+    use crate::test_runner;
+
+    const SCHEMA: &str = "chaindexing_booting_tests";
+    const ADDED_TABLES_SCHEMA: &str = "chaindexing_booting_added_tables_tests";
+    const HANDLED_BLOCK_COUNT: i64 = 10;
+
+    struct VersionedTransferTestHandler(u64);
+
+    #[chaindexing::augmenting_std::async_trait]
+    impl EventHandler for VersionedTransferTestHandler {
+        fn abi(&self) -> &'static str {
+            TransferTestHandler.abi()
+        }
+        fn version(&self) -> u64 {
+            self.0
+        }
+        async fn handle_event<'a, 'b>(&self, _context: EventContext<'a, 'b>) {}
+    }
+
+    struct TransfersMigrations;
+
+    impl StateMigrations for TransfersMigrations {
+        fn migrations(&self) -> &'static [&'static str] {
+            &["CREATE TABLE IF NOT EXISTS transfers (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct ApprovalsMigrations;
+
+    impl StateMigrations for ApprovalsMigrations {
+        fn migrations(&self) -> &'static [&'static str] {
+            &["CREATE TABLE IF NOT EXISTS approvals (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    fn contracts(handler_version: u64) -> [Contract<()>; 3] {
+        let contract = |name: &str, address: &str| {
+            Contract::new(name).add_address(
+                address,
+                &ChainId::Mainnet,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64,
+            )
+        };
+
+        [
+            contract(
+                "BoredApeYachtClub-33",
+                "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f33D",
+            )
+            .add_event_handler(VersionedTransferTestHandler(handler_version))
+            .add_state_migrations(TransfersMigrations),
+            contract(
+                "BoredApeYachtClub-34",
+                "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f34D",
+            )
+            .add_event_handler(TransferTestHandler)
+            .add_state_migrations(TransfersMigrations),
+            contract(
+                "CryptoPunks-33",
+                "0xb47e3cd837dDF8e4c57F05d70Ab865de6e193B33",
+            )
+            .add_event_handler(TransferTestHandler),
+        ]
+    }
+
+    #[tokio::test]
+    pub async fn replays_only_contracts_with_changed_versions_and_those_sharing_their_tables() {
+        // Isolates the root states and contract addresses of these boots
+        let repo = test_runner::new_repo_in_schema(SCHEMA).await;
+        let repo_client = repo.get_client().await;
+        let boot = |handler_version| {
+            let config = contracts(handler_version)
+                .into_iter()
+                .fold(Config::new(repo.clone()), |config, contract| {
+                    config.add_contract(contract)
+                });
+
+            async move {
+                let repo_client = config.repo.get_client().await;
+                chaindexing::booting::setup(&config, &repo_client).await.unwrap();
+            }
+        };
+
+        boot(1).await;
+        ChaindexingRepo::execute(
+            &repo_client,
+            &format!(
+                "UPDATE chaindexing_contract_addresses
+                SET next_block_number_to_handle_from = start_block_number + {HANDLED_BLOCK_COUNT}"
+            ),
+        )
+        .await;
+
+        boot(1).await;
+        assert_eq!(
+            load_handled_block_counts(&repo_client).await,
+            vec![HANDLED_BLOCK_COUNT; 3]
+        );
+
+        // The unchanged contract sharing the changed one's table gets replayed too
+        boot(2).await;
+        assert_eq!(
+            load_handled_block_counts(&repo_client).await,
+            vec![0, 0, HANDLED_BLOCK_COUNT]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn replays_contracts_with_added_state_tables() {
+        let repo = test_runner::new_repo_in_schema(ADDED_TABLES_SCHEMA).await;
+        let repo_client = repo.get_client().await;
+        let boot = |adds_approvals: bool| {
+            let contract = Contract::<()>::new("BoredApeYachtClub")
+                .add_address(
+                    "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D",
+                    &ChainId::Mainnet,
+                    BAYC_CONTRACT_START_BLOCK_NUMBER as u64,
+                )
+                .add_event_handler(TransferTestHandler)
+                .add_state_migrations(TransfersMigrations);
+            let contract = if adds_approvals {
+                contract.add_state_migrations(ApprovalsMigrations)
+            } else {
+                contract
+            };
+            let config = Config::new(repo.clone()).add_contract(contract);
+
+            async move {
+                let repo_client = config.repo.get_client().await;
+                chaindexing::booting::setup(&config, &repo_client).await.unwrap();
+            }
+        };
+
+        boot(false).await;
+        ChaindexingRepo::execute(
+            &repo_client,
+            &format!(
+                "UPDATE chaindexing_contract_addresses
+                SET next_block_number_to_handle_from = start_block_number + {HANDLED_BLOCK_COUNT}"
+            ),
+        )
+        .await;
+
+        boot(true).await;
+        assert_eq!(load_handled_block_counts(&repo_client).await, vec![0]);
+    }
+
+    async fn load_handled_block_counts(
+        repo_client: &chaindexing::ChaindexingRepoClient,
+    ) -> Vec<i64> {
+        repo_client
+            .query(
+                "SELECT next_block_number_to_handle_from - start_block_number
+                FROM chaindexing_contract_addresses
+                ORDER BY contract_name",
+                &[],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect()
+    }
+}
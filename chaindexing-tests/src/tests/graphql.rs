@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::ContractState;
+    use chaindexing::{
+        graphql, ChaindexingRepo, Config, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+    use ethers::types::{I256, U256};
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, TokenBalance,
+        TokenBalanceMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn queries_states_through_graphql() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-28", "28")
+            .add_state_migrations(TokenBalanceMigrations);
+        let config = Config::new(test_runner::new_repo()).add_contract(bayc_contract.clone());
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let event = unique_transfer_event_with_contract(bayc_contract);
+
+        let token_ids = [generate_unique_token_id(), generate_unique_token_id() + 1];
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        for (token_id, amount) in token_ids.iter().zip([10, 20]) {
+            TokenBalance {
+                token_id: *token_id,
+                amount: U256::from(amount),
+                delta: I256::from(-amount),
+                is_locked: false,
+                memo: None,
+                metadata: serde_json::json!({"tier": amount}),
+            }
+            .create(&event_context)
+            .await;
+        }
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let schema = graphql::schema(&config).await;
+        let query = |arguments: String| {
+            format!(
+                "{{ token_balances({arguments}) {{ token_id amount is_locked memo metadata }} }}"
+            )
+        };
+
+        let response = schema
+            .execute(query(format!(
+                "where: {{ token_id_in: [{}, {}], amount_gte: \"15\" }}",
+                token_ids[0], token_ids[1]
+            )))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({"token_balances": [{
+                "token_id": token_ids[1],
+                "amount": "0x14",
+                "is_locked": false,
+                "memo": null,
+                "metadata": {"tier": 20},
+            }]})
+        );
+
+        let response = schema
+            .execute(query(format!(
+                "where: {{ token_id: {}, or: [{{ token_id: {} }}] }}, \
+                order_by: [{{ field: token_id, order: DESC }}], limit: 1, offset: 1",
+                token_ids[0], token_ids[1]
+            )))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap()["token_balances"][0]["token_id"],
+            token_ids[0]
+        );
+
+        let response = schema
+            .execute(query(format!(
+                "where: {{ token_id: {} }}, at_block: {}",
+                token_ids[0],
+                event.get_block_number() - 1
+            )))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap(),
+            serde_json::json!({"token_balances": []})
+        );
+
+        let response = schema.execute(query("where: { amount_gte: \"abc\" }".to_string())).await;
+        assert!(!response.errors.is_empty());
+
+        let response = schema
+            .execute(query(format!(
+                "where: {{ token_id: {}, delta_lt: \"-15\" }}",
+                token_ids[1]
+            )))
+            .await;
+        assert!(response.errors.is_empty(), "{:?}", response.errors);
+        assert_eq!(
+            response.data.into_json().unwrap()["token_balances"][0]["token_id"],
+            token_ids[1]
+        );
+    }
+}
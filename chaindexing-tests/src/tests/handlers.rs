@@ -1 +1,171 @@
-// TODO: Event Handlers Test here
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use chaindexing::invariants::InvariantChecker;
+    use chaindexing::states::ContractState;
+    use chaindexing::{
+        handle_events, ingester, ChainId, ChaindexingRepo, Config, Contract, EventAbi,
+        EventContext, EventHandler, ExecutesWithRawQuery, HasRawQueryClient, IngesterProvider,
+        Repo,
+    };
+    use ethers::providers::ProviderError;
+    use ethers::types::{Block, Filter, Log, TxHash, H256, U64};
+    use tokio::sync::Mutex;
+
+    use crate::factory::{
+        transfer_log, ApprovalForAllTestHandler, Nft, NftMigrations, TransferTestHandler,
+        BAYC_CONTRACT_ADDRESS, BAYC_CONTRACT_START_BLOCK_NUMBER,
+    };
+    use crate::test_runner;
+
+    const SCHEMA: &str = "chaindexing_handlers_tests";
+    const HANDLED_BLOCK_COUNT: u64 = 3;
+    const CURRENT_BLOCK_COUNT: u64 = 100;
+    const START_BLOCK_NUMBER: u64 = BAYC_CONTRACT_START_BLOCK_NUMBER as u64;
+
+    struct NftMintingHandler;
+
+    #[chaindexing::augmenting_std::async_trait]
+    impl EventHandler for NftMintingHandler {
+        fn abi(&self) -> &'static str {
+            TransferTestHandler.abi()
+        }
+        async fn handle_event<'a, 'b>(&self, context: EventContext<'a, 'b>) {
+            let token_id = context.get_event_params().get_u32("tokenId") as i32;
+
+            Nft { token_id }.create(&context).await;
+        }
+    }
+
+    /// Serves the given logs that fall in the filtered blocks
+    #[derive(Clone)]
+    struct Provider {
+        logs: Vec<Log>,
+    }
+
+    #[chaindexing::augmenting_std::async_trait]
+    impl IngesterProvider for Provider {
+        async fn get_block_number(&self) -> Result<U64, ProviderError> {
+            Ok(U64::from(START_BLOCK_NUMBER + CURRENT_BLOCK_COUNT))
+        }
+
+        async fn get_logs(&self, filter: &Filter) -> Result<Vec<Log>, ProviderError> {
+            let from_block_number = filter.get_from_block().unwrap();
+            let to_block_number = filter.get_to_block().unwrap();
+
+            Ok(self
+                .logs
+                .iter()
+                .filter(|log| {
+                    (from_block_number..=to_block_number).contains(&log.block_number.unwrap())
+                })
+                .cloned()
+                .collect())
+        }
+
+        async fn get_block(&self, block_number: U64) -> Result<Block<TxHash>, ProviderError> {
+            Ok(Block {
+                number: Some(block_number),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn nft_transfer_log(block_number: u64, log_index: u64, token_id: u64) -> Log {
+        let mut log = transfer_log(BAYC_CONTRACT_ADDRESS);
+        log.block_number = Some(block_number.into());
+        log.log_index = Some(log_index.into());
+        log.topics[3] = H256::from_low_u64_be(token_id);
+
+        log
+    }
+
+    fn bayc_config(repo: &ChaindexingRepo, adds_nft_minting_handler: bool) -> Config<()> {
+        let contract = Contract::new("BoredApeYachtClub")
+            .add_address(BAYC_CONTRACT_ADDRESS, &ChainId::Mainnet, START_BLOCK_NUMBER)
+            .add_event_handler(ApprovalForAllTestHandler)
+            .add_state_migrations(NftMigrations);
+        let contract = if adds_nft_minting_handler {
+            contract.add_event_handler(NftMintingHandler)
+        } else {
+            contract
+        };
+
+        // Batches a single block at a time to split handled events across batches
+        Config::new(repo.clone()).add_contract(contract).with_blocks_per_batch(1)
+    }
+
+    #[tokio::test]
+    pub async fn backfills_states_of_handlers_added_to_indexed_contracts() {
+        let repo = test_runner::new_repo_in_schema(SCHEMA).await;
+        let repo_client = repo.get_client().await;
+
+        let config = bayc_config(&repo, false);
+        chaindexing::booting::setup(&config, &repo_client).await.unwrap();
+        ChaindexingRepo::execute(
+            &repo_client,
+            &format!(
+                "UPDATE chaindexing_contract_addresses
+                SET next_block_number_to_ingest_from = start_block_number + {HANDLED_BLOCK_COUNT},
+                next_block_number_to_handle_from = start_block_number + {HANDLED_BLOCK_COUNT}"
+            ),
+        )
+        .await;
+
+        let config = bayc_config(&repo, true);
+        chaindexing::booting::setup(&config, &repo_client).await.unwrap();
+
+        // Two of the transfers share a block
+        let provider = Arc::new(Provider {
+            logs: vec![
+                nft_transfer_log(START_BLOCK_NUMBER + 1, 0, 1),
+                nft_transfer_log(START_BLOCK_NUMBER + 1, 1, 2),
+                nft_transfer_log(START_BLOCK_NUMBER + 2, 0, 3),
+            ],
+        });
+        let pool = repo.get_pool(1).await;
+        let conn = Arc::new(Mutex::new(ChaindexingRepo::get_conn(&pool).await));
+        let repo_client = Arc::new(Mutex::new(repo_client));
+        for _ in 0..HANDLED_BLOCK_COUNT {
+            ingester::ingest_for_chain(
+                &ChainId::Mainnet,
+                provider.clone(),
+                conn.clone(),
+                &repo_client,
+                &config,
+                &mut HashMap::new(),
+            )
+            .await
+            .unwrap();
+        }
+
+        let pure_handlers: HashMap<EventAbi, Arc<dyn EventHandler>> =
+            HashMap::from([(NftMintingHandler.abi(), Arc::new(NftMintingHandler) as _)]);
+        let invariant_checker = InvariantChecker::new(&config).await;
+        for _ in 0..HANDLED_BLOCK_COUNT {
+            handle_events::run::<()>(
+                &pure_handlers,
+                &HashMap::new(),
+                &HashMap::new(),
+                (&[ChainId::Mainnet as u64], config.blocks_per_batch),
+                &repo_client,
+                &None,
+                &invariant_checker,
+            )
+            .await;
+        }
+
+        let token_ids: Vec<i32> = repo_client
+            .lock()
+            .await
+            .query("SELECT token_id FROM nfts ORDER BY token_id", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(token_ids, vec![1, 2, 3]);
+    }
+}
@@ -0,0 +1,143 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::invariants::{self, Invariant, InvariantAction, InvariantChecker};
+    use chaindexing::states::ContractState;
+    use chaindexing::{
+        ChainId, ChaindexingRepo, Config, ContractAddress, EventContext, ExecutesWithRawQuery,
+        HasRawQueryClient,
+    };
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, Nft,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn checks_invariants_after_handled_batches() {
+        let token_id = generate_unique_token_id();
+        let bayc_contract = bayc_contract("BoredApeYachtClub-31", "31").add_invariant(
+            Invariant::sql(
+                "no_banned_token",
+                &format!("SELECT token_id FROM nfts WHERE token_id = {token_id}"),
+            )
+            .on_violation(InvariantAction::Halt),
+        );
+        let config = Config::new(test_runner::new_repo())
+            .add_contract(bayc_contract.clone())
+            .add_invariant(Invariant::check("holds", |_context| {
+                Box::pin(async { Ok(()) })
+            }))
+            .add_invariant(
+                Invariant::check("covers_blocks", |context| {
+                    Box::pin(async move {
+                        Err(format!(
+                            "blocks {} to {}",
+                            context.from_block_number, context.to_block_number
+                        ))
+                    })
+                })
+                .on_violation(InvariantAction::Record),
+            );
+        let mut repo_client = test_runner::new_repo().get_client().await;
+
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        Nft { token_id }
+            .create(&EventContext::for_event(&event, &repo_txn_client))
+            .await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let contract_address = ContractAddress {
+            id: 0,
+            chain_id: ChainId::Mainnet as i64,
+            next_block_number_to_ingest_from: 0,
+            next_block_number_to_handle_from: 0,
+            next_block_number_for_side_effects: 0,
+            start_block_number: 0,
+            address: event.contract_address.clone(),
+            contract_name: bayc_contract.name.clone(),
+        };
+        let invariant_checker = InvariantChecker::new(&config).await;
+        invariant_checker.run(&contract_address, (10, 20), &repo_client).await;
+
+        let violations: Vec<(String, i64, i64, String, bool)> = repo_client
+            .query(
+                "SELECT invariant_name, from_block_number, to_block_number, details, halts
+                FROM chaindexing_invariant_violations
+                WHERE contract_address = $1 AND resolved_at IS NULL ORDER BY id",
+                &[&event.contract_address],
+            )
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| (row.get(0), row.get(1), row.get(2), row.get(3), row.get(4)))
+            .collect();
+        assert_eq!(
+            violations,
+            vec![
+                (
+                    "no_banned_token".to_string(),
+                    10,
+                    20,
+                    format!("[{{\"token_id\":{token_id}}}]"),
+                    true
+                ),
+                (
+                    "covers_blocks".to_string(),
+                    10,
+                    20,
+                    "blocks 10 to 20".to_string(),
+                    false
+                )
+            ]
+        );
+
+        let halted_contract_addresses =
+            invariants::load_halted_contract_addresses(&repo_client, ChainId::Mainnet as u64).await;
+        assert!(halted_contract_addresses.contains(&event.contract_address));
+
+        invariants::resume(&repo_client, &ChainId::Mainnet, &event.contract_address).await;
+
+        let halted_contract_addresses =
+            invariants::load_halted_contract_addresses(&repo_client, ChainId::Mainnet as u64).await;
+        assert!(!halted_contract_addresses.contains(&event.contract_address));
+    }
+
+    #[tokio::test]
+    pub async fn records_failing_invariant_queries_as_violations() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-42", "42").add_invariant(
+            Invariant::sql("invalid_query", "SELECT * FROM missing_invariant_states")
+                .on_violation(InvariantAction::Record),
+        );
+        let config = Config::new(test_runner::new_repo()).add_contract(bayc_contract.clone());
+        let repo_client = test_runner::new_repo().get_client().await;
+
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let contract_address = ContractAddress {
+            id: 0,
+            chain_id: ChainId::Mainnet as i64,
+            next_block_number_to_ingest_from: 0,
+            next_block_number_to_handle_from: 0,
+            next_block_number_for_side_effects: 0,
+            start_block_number: 0,
+            address: event.contract_address.clone(),
+            contract_name: bayc_contract.name.clone(),
+        };
+        let invariant_checker = InvariantChecker::new(&config).await;
+        invariant_checker.run(&contract_address, (10, 20), &repo_client).await;
+
+        let details: String = repo_client
+            .query_one(
+                "SELECT details FROM chaindexing_invariant_violations
+                WHERE contract_address = $1 AND invariant_name = 'invalid_query'
+                AND resolved_at IS NULL",
+                &[&event.contract_address],
+            )
+            .await
+            .unwrap()
+            .get(0);
+        assert!(details.contains("missing_invariant_states"));
+
+        invariants::resume(&repo_client, &ChainId::Mainnet, &event.contract_address).await;
+    }
+}
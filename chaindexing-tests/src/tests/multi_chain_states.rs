@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::{Filters, MultiChainState, StateCache, Updates};
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+    use ethers::types::U256;
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, TokenSupply,
+        TokenSupplyMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn applies_queued_multi_chain_state_mutations_once_committed() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-22", "22").add_state_migrations(TokenSupplyMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let token_id = generate_unique_token_id();
+        let new_state = TokenSupply {
+            token_id,
+            amount: U256::from(5),
+        };
+        let filters = Filters::new("token_id", token_id);
+
+        {
+            let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+            let event = unique_transfer_event_with_contract(bayc_contract.clone());
+            let event_context: EventContext<'_, '_> =
+                EventContext::for_event(&event, &repo_txn_client);
+
+            new_state
+                .create_or_update(&Updates::increment("amount", 100), &event_context)
+                .await;
+            // Rolled back, along with its queued mutation
+        }
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        for _ in 0..2 {
+            let event = unique_transfer_event_with_contract(bayc_contract.clone());
+            let event_context: EventContext<'_, '_> =
+                EventContext::for_event(&event, &repo_txn_client);
+
+            new_state
+                .create_or_update(&Updates::increment("amount", 5), &event_context)
+                .await;
+        }
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        assert_eq!(TokenSupply::read_one(&filters, &event_context).await, None);
+        drop(repo_txn_client);
+
+        chaindexing::states::apply_mcs_mutations(&mut repo_client).await;
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        assert_eq!(
+            TokenSupply::read_one(&filters, &event_context).await,
+            Some(TokenSupply {
+                token_id,
+                amount: U256::from(10),
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn updates_multi_chain_states_created_in_the_same_batch() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-41", "41").add_state_migrations(TokenSupplyMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let state_cache = StateCache::new();
+        let token_id = generate_unique_token_id();
+        let new_state = TokenSupply {
+            token_id,
+            amount: U256::from(5),
+        };
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let event_context: EventContext<'_, '_> =
+            EventContext::for_event(&event, &repo_txn_client).with_state_cache(&state_cache);
+        new_state.create(&event_context).await;
+
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let event_context: EventContext<'_, '_> =
+            EventContext::for_event(&event, &repo_txn_client).with_state_cache(&state_cache);
+        new_state.update(&Updates::increment("amount", 10), &event_context).await;
+
+        state_cache.flush(&repo_txn_client).await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        chaindexing::states::apply_mcs_mutations(&mut repo_client).await;
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        assert_eq!(
+            TokenSupply::read_one(&Filters::new("token_id", token_id), &event_context).await,
+            Some(TokenSupply {
+                token_id,
+                amount: U256::from(15),
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn keeps_failed_multi_chain_state_mutations_queued_with_their_errors() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-23", "23");
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+
+        let queued_mutation = repo_client
+            .query_one(
+                "INSERT INTO chaindexing_mcs_mutations
+                (schema_name, table_name, chain_id, contract_address, block_number, mutation, event)
+                VALUES (current_schema(), 'missing_states', $1, $2, $3, $4, $5)
+                RETURNING id",
+                &[
+                    &(ChainId::Mainnet as i64),
+                    &event.contract_address,
+                    &(event.get_block_number() as i64),
+                    &serde_json::json!({ "Delete": { "state_view": {} } }),
+                    &serde_json::to_value(&event).unwrap(),
+                ],
+            )
+            .await
+            .unwrap();
+        let id: i64 = queued_mutation.get(0);
+
+        chaindexing::states::apply_mcs_mutations(&mut repo_client).await;
+
+        let queued_mutation = repo_client
+            .query_one(
+                "SELECT attempts, last_error FROM chaindexing_mcs_mutations WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .unwrap();
+        let attempts: i32 = queued_mutation.get(0);
+        let last_error: String = queued_mutation.get(1);
+        assert!(attempts >= 1);
+        assert!(last_error.contains("chaindexing_state_versions_for_missing_states"));
+
+        repo_client
+            .execute(
+                "DELETE FROM chaindexing_mcs_mutations WHERE id = $1",
+                &[&id],
+            )
+            .await
+            .unwrap();
+    }
+}
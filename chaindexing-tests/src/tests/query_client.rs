@@ -0,0 +1,44 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::{ContractState, Filters, StateQueryClient};
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, Nft,
+        NftMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn reads_states_outside_handlers_with_query_client() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-25", "25").add_state_migrations(NftMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let token_id = generate_unique_token_id();
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        Nft { token_id }.create(&event_context).await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let query_client = StateQueryClient::new(&test_runner::new_repo()).await;
+        let filters = Filters::new("token_id", token_id);
+        let contract_filters =
+            filters.clone().for_contract_address(&ChainId::Mainnet, &event.contract_address);
+
+        assert_eq!(
+            query_client.read_one("nfts", &contract_filters).await,
+            Some(Nft { token_id })
+        );
+        assert_eq!(
+            query_client
+                .read_many::<Nft>("nfts", &filters.for_chain(&ChainId::Polygon))
+                .await,
+            vec![]
+        );
+        assert_eq!(query_client.count("nfts", &contract_filters).await, 1);
+    }
+}
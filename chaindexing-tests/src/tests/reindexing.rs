@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use chaindexing::augmenting_std::serde::{Deserialize, Serialize};
+    use chaindexing::states::{ContractState, StateMigrations};
+    use chaindexing::{
+        reindexing, Chain, ChainId, ChaindexingRepo, Config, Contract, EventContext, EventHandler,
+        ExecutesWithRawQuery, HasRawQueryClient, Repo,
+    };
+    use tokio::sync::{Mutex, RwLock};
+
+    use crate::factory::{
+        unique_transfer_event_with_contract, TransferTestHandler, BAYC_CONTRACT_START_BLOCK_NUMBER,
+    };
+    use crate::test_runner;
+
+    const LIVE_SCHEMA: &str = "chaindexing_reindexing_tests";
+    const REBUILT_TOKEN_ID: i32 = 7;
+
+    #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(crate = "chaindexing::augmenting_std::serde")]
+    struct Mint {
+        token_id: i32,
+    }
+
+    impl ContractState for Mint {
+        fn table_name() -> &'static str {
+            "mints"
+        }
+    }
+
+    struct MintMigrations;
+
+    impl StateMigrations for MintMigrations {
+        fn migrations(&self) -> &'static [&'static str] {
+            &["CREATE TABLE IF NOT EXISTS mints (token_id INTEGER NOT NULL)"]
+        }
+    }
+
+    struct MintTestHandler;
+
+    #[chaindexing::augmenting_std::async_trait]
+    impl EventHandler for MintTestHandler {
+        fn abi(&self) -> &'static str {
+            TransferTestHandler.abi()
+        }
+        async fn handle_event<'a, 'b>(&self, context: EventContext<'a, 'b>) {
+            Mint {
+                token_id: REBUILT_TOKEN_ID,
+            }
+            .create(&context)
+            .await;
+        }
+    }
+
+    #[tokio::test]
+    pub async fn swaps_rebuilt_states_into_the_current_schema() {
+        let repo = test_runner::new_repo_in_schema(LIVE_SCHEMA).await;
+        let repo_client = repo.get_client().await;
+
+        let contract = Contract::new("BoredApeYachtClub-32")
+            .add_event_handler(MintTestHandler)
+            .add_address(
+                "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f32D",
+                &ChainId::Mainnet,
+                BAYC_CONTRACT_START_BLOCK_NUMBER as u64,
+            )
+            .add_state_migrations(MintMigrations);
+        chaindexing::booting::run_user_migrations(&repo_client, std::slice::from_ref(&contract))
+            .await;
+        ChaindexingRepo::create_contract_addresses(&repo_client, &contract.addresses).await;
+
+        let event = unique_transfer_event_with_contract(contract.clone());
+        let pool = repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        ChaindexingRepo::create_events(&mut conn, std::slice::from_ref(&event)).await;
+        ChaindexingRepo::execute(
+            &repo_client,
+            &format!(
+                "UPDATE chaindexing_contract_addresses SET next_block_number_to_handle_from = {}",
+                event.get_block_number() + 1
+            ),
+        )
+        .await;
+
+        reindexing::start(&repo_client, &contract.state_migrations).await;
+
+        let config = Config::new(repo.clone())
+            .add_chain(Chain::new(ChainId::Mainnet, ""))
+            .add_contract(contract);
+        let pure_handlers = HashMap::from([(
+            TransferTestHandler.abi(),
+            Arc::new(MintTestHandler) as Arc<dyn EventHandler>,
+        )]);
+        let repo_client_for_mcs = repo.get_client().await;
+        reindexing::set_shadow_search_path(&repo_client_for_mcs).await;
+        reindexing::run(
+            &config,
+            (&pure_handlers, &HashMap::new()),
+            &RwLock::new(()),
+            (
+                &Arc::new(Mutex::new(repo.get_client().await)),
+                &Arc::new(Mutex::new(repo_client_for_mcs)),
+            ),
+        )
+        .await;
+
+        assert!(!reindexing::is_in_progress(&repo_client).await);
+
+        let token_ids: Vec<i32> = repo_client
+            .query("SELECT token_id FROM mints", &[])
+            .await
+            .unwrap()
+            .iter()
+            .map(|row| row.get(0))
+            .collect();
+        assert_eq!(token_ids, vec![REBUILT_TOKEN_ID]);
+
+        let live_tables = repo_client
+            .query(
+                "SELECT table_schema::TEXT FROM information_schema.tables
+                WHERE table_name IN ('mints', 'chaindexing_state_versions_for_mints')",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert!(live_tables.iter().any(|row| row.get::<_, String>(0) == LIVE_SCHEMA));
+        assert!(live_tables
+            .iter()
+            .all(|row| row.get::<_, String>(0) != reindexing::SHADOW_SCHEMA));
+
+        let applied_state_migrations_schema_names = repo_client
+            .query(
+                "SELECT DISTINCT schema_name FROM chaindexing_applied_state_migrations",
+                &[],
+            )
+            .await
+            .unwrap();
+        let applied_state_migrations_schema_names: Vec<String> =
+            applied_state_migrations_schema_names.iter().map(|row| row.get(0)).collect();
+        assert_eq!(applied_state_migrations_schema_names, vec![LIVE_SCHEMA]);
+    }
+}
@@ -178,3 +178,146 @@ mod create_contract_addresses {
         .await;
     }
 }
+
+#[cfg(test)]
+mod create_handler_cursors {
+    use chaindexing::{
+        ChainId, ChaindexingRepo, ExecutesWithRawQuery, HasRawQueryClient, Repo,
+        UnsavedContractAddress,
+    };
+
+    use crate::factory::{bayc_contract, unique_transfer_event_with_contract};
+    use crate::test_runner;
+
+    const TRANSFER_ABI: &str =
+        "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)";
+
+    #[tokio::test]
+    pub async fn creates_cursors_for_contract_addresses_with_ingested_events() {
+        test_runner::run_test_new(|repo_client| async move {
+            let contract_name = "contract-name-5";
+            let contract_address_value = "0x8a90cab2b38dba80c64b7734e58ee1db38b8505e";
+            let chain_id = ChainId::Arbitrum;
+
+            let contract_addresses = vec![UnsavedContractAddress::new(
+                contract_name,
+                contract_address_value,
+                &chain_id,
+                30,
+            )];
+            ChaindexingRepo::create_contract_addresses(&repo_client, &contract_addresses).await;
+            ChaindexingRepo::execute(
+                &repo_client,
+                &format!(
+                    "UPDATE chaindexing_contract_addresses
+                    SET next_block_number_to_ingest_from = 90, next_block_number_to_handle_from = 80
+                    WHERE address = '{contract_address_value}'"
+                ),
+            )
+            .await;
+
+            ChaindexingRepo::create_handler_cursors(&repo_client, contract_name, TRANSFER_ABI)
+                .await;
+
+            let handler_cursor = repo_client
+                .query_one(
+                    &format!(
+                        "SELECT * FROM chaindexing_handler_cursors
+                        WHERE contract_address = '{contract_address_value}'"
+                    ),
+                    &[],
+                )
+                .await
+                .unwrap();
+
+            assert_eq!(handler_cursor.get::<_, String>("abi"), TRANSFER_ABI);
+            assert_eq!(
+                handler_cursor.get::<_, i64>("next_block_number_to_handle_from"),
+                30
+            );
+            assert_eq!(
+                handler_cursor.get::<_, i64>("next_block_number_to_ingest_from"),
+                30
+            );
+            assert_eq!(
+                handler_cursor.get::<_, i64>("ingest_until_block_number"),
+                90
+            );
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    pub async fn backfills_whole_ranges_of_contract_addresses_with_events_of_the_abi() {
+        // Keeps its events away from tests expecting none
+        let repo = test_runner::new_repo_in_schema("chaindexing_handler_cursors_tests").await;
+        let repo_client = repo.get_client().await;
+        let contract_name = "BoredApeYachtClub-39";
+        let event = unique_transfer_event_with_contract(bayc_contract(contract_name, "39"));
+
+        let contract_addresses = vec![UnsavedContractAddress::new(
+            contract_name,
+            &event.contract_address,
+            &ChainId::Mainnet,
+            30,
+        )];
+        ChaindexingRepo::create_contract_addresses(&repo_client, &contract_addresses).await;
+        ChaindexingRepo::execute(
+            &repo_client,
+            &format!(
+                "UPDATE chaindexing_contract_addresses
+                SET next_block_number_to_ingest_from = {}",
+                event.get_block_number() + 1
+            ),
+        )
+        .await;
+        let pool = repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        ChaindexingRepo::create_events(&mut conn, &[event]).await;
+
+        ChaindexingRepo::create_handler_cursors(&repo_client, contract_name, TRANSFER_ABI).await;
+
+        let handler_cursor = repo_client
+            .query_one("SELECT * FROM chaindexing_handler_cursors", &[])
+            .await
+            .unwrap();
+        assert_eq!(
+            handler_cursor.get::<_, i64>("next_block_number_to_ingest_from"),
+            30
+        );
+    }
+
+    #[tokio::test]
+    pub async fn does_not_create_cursors_for_contract_addresses_yet_to_ingest() {
+        test_runner::run_test_new(|repo_client| async move {
+            let contract_name = "contract-name-6";
+            let contract_address_value = "0x8a90cab2b38dba80c64b7734e58ee1db38b8606e";
+            let chain_id = ChainId::Arbitrum;
+
+            let contract_addresses = vec![UnsavedContractAddress::new(
+                contract_name,
+                contract_address_value,
+                &chain_id,
+                30,
+            )];
+            ChaindexingRepo::create_contract_addresses(&repo_client, &contract_addresses).await;
+
+            ChaindexingRepo::create_handler_cursors(&repo_client, contract_name, TRANSFER_ABI)
+                .await;
+
+            let handler_cursors = repo_client
+                .query(
+                    &format!(
+                        "SELECT * FROM chaindexing_handler_cursors
+                        WHERE contract_address = '{contract_address_value}'"
+                    ),
+                    &[],
+                )
+                .await
+                .unwrap();
+
+            assert!(handler_cursors.is_empty());
+        })
+        .await;
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::ContractState;
+    use chaindexing::{
+        rest, ChainId, ChaindexingRepo, Config, EventContext, ExecutesWithRawQuery,
+        HasRawQueryClient, UnsavedContractAddress,
+    };
+    use ethers::types::{I256, U256};
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, TokenBalance,
+        TokenBalanceMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn serves_states_and_indexing_status_over_http() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-29", "29")
+            .add_state_migrations(TokenBalanceMigrations);
+        let config = Config::new(test_runner::new_repo()).add_contract(bayc_contract.clone());
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let token_id = generate_unique_token_id();
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+        TokenBalance {
+            token_id,
+            amount: U256::from(30),
+            delta: I256::from(0),
+            is_locked: true,
+            memo: Some("rest".to_string()),
+            metadata: serde_json::json!({}),
+        }
+        .create(&event_context)
+        .await;
+        let contract_address = UnsavedContractAddress::new(
+            "BoredApeYachtClub-29",
+            "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f29D",
+            &ChainId::Fantom,
+            300,
+        );
+        ChaindexingRepo::create_contract_address(&repo_txn_client, &contract_address).await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let addr = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap();
+        tokio::spawn(async move { rest::serve(&config, addr).await });
+
+        let (status, body) = http_get(
+            addr,
+            &format!(
+                "/states/token_balances?token_id={token_id}&amount_gte=0x1e&order_by=token_id.desc"
+            ),
+        )
+        .await;
+        assert_eq!(status, 200);
+        assert_eq!(body["limit"], 100);
+        assert_eq!(body["offset"], 0);
+        assert_eq!(body["data"].as_array().unwrap().len(), 1);
+        assert_eq!(body["data"][0]["token_id"], token_id);
+        assert_eq!(body["data"][0]["amount"], "0x1e");
+        assert_eq!(body["data"][0]["memo"], "rest");
+        assert_eq!(body["data"][0]["metadata"], serde_json::json!({}));
+
+        let (_, body) = http_get(
+            addr,
+            &format!("/states/token_balances?token_id={token_id}&amount_gt=30"),
+        )
+        .await;
+        assert_eq!(body["data"], serde_json::json!([]));
+
+        let (status, body) = http_get(addr, "/states/token_balances?balance=1").await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "Unknown column: balance");
+
+        let (status, body) = http_get(addr, "/states/token_balances?token_id=abc").await;
+        assert_eq!(status, 400);
+        assert_eq!(body["error"], "Invalid token_id: abc");
+
+        let (status, _) = http_get(addr, "/states/unknown_states").await;
+        assert_eq!(status, 404);
+
+        let (status, body) = http_get(addr, "/status").await;
+        assert_eq!(status, 200);
+        let fantom_status = body["chains"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|chain| chain["chain_id"] == ChainId::Fantom as i64)
+            .unwrap();
+        assert!(
+            fantom_status["contract_addresses"].as_array().unwrap().iter().any(
+                |contract_address| {
+                    contract_address["address"] == "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f29d"
+                        && contract_address["next_block_number_to_ingest_from"] == 300
+                }
+            )
+        );
+        assert!(fantom_status["last_ingested_block_number"].as_i64().unwrap() >= 299);
+    }
+
+    /// Sends a GET request to the server, once it accepts connections
+    async fn http_get(addr: std::net::SocketAddr, path: &str) -> (u16, serde_json::Value) {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let mut stream = loop {
+            match tokio::net::TcpStream::connect(addr).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(50)).await,
+            }
+        };
+        let request = format!("GET {path} HTTP/1.1\r\nHost: {addr}\r\nConnection: close\r\n\r\n");
+        stream.write_all(request.as_bytes()).await.unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+
+        (status, serde_json::from_str(body).unwrap())
+    }
+}
@@ -0,0 +1,136 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::rewinding::{self, Rewind, RewindError};
+    use chaindexing::states::{ContractState, Filters, Updates};
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+    use ethers::types::{I256, U256};
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract,
+        unique_transfer_events_with_contract_at, unsaved_contract_address, Nft, NftMigrations,
+        TokenBalance, TokenBalanceMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn rewinds_states_from_block() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-4", "04").add_state_migrations(NftMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+
+        let token_id = generate_unique_token_id();
+        Nft { token_id }.create(&event_context).await;
+
+        ChaindexingRepo::create_contract_address(
+            &repo_txn_client,
+            &unsaved_contract_address(&bayc_contract.name, &event.contract_address),
+        )
+        .await;
+        let rewind = Rewind::new(&ChainId::Mainnet, event.get_block_number())
+            .for_contract_address(&event.contract_address);
+        rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await.unwrap();
+
+        let state = Nft::read_one(&Filters::new("token_id", token_id), &event_context).await;
+        assert_eq!(state, None);
+    }
+
+    #[tokio::test]
+    pub async fn does_not_rewind_states_of_other_contract_addresses() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-5", "03").add_state_migrations(NftMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+
+        let new_state = Nft {
+            token_id: generate_unique_token_id(),
+        };
+        new_state.create(&event_context).await;
+
+        let other_contract_address = "0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f02D";
+        ChaindexingRepo::create_contract_address(
+            &repo_txn_client,
+            &unsaved_contract_address(&bayc_contract.name, other_contract_address),
+        )
+        .await;
+        let rewind = Rewind::new(&ChainId::Mainnet, event.get_block_number())
+            .for_contract_address(other_contract_address);
+        rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await.unwrap();
+
+        let state = Nft::read_one(
+            &Filters::new("token_id", new_state.token_id),
+            &event_context,
+        )
+        .await;
+        assert_eq!(state, Some(new_state));
+    }
+
+    #[tokio::test]
+    pub async fn restores_last_versions_of_states_updated_by_the_event_creating_them() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-36", "36")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let [create_event, update_event]: [_; 2] =
+            unique_transfer_events_with_contract_at(bayc_contract.clone(), &[0, 0])
+                .try_into()
+                .unwrap();
+        let create_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&create_event, &repo_txn_client);
+        let update_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&update_event, &repo_txn_client);
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+        new_state.create(&create_event_context).await;
+        let filters = Filters::new("token_id", token_id);
+        let created_state = TokenBalance::read_one(&filters, &create_event_context).await.unwrap();
+        created_state.update(&Updates::new("amount", 20), &create_event_context).await;
+        let updated_state = TokenBalance::read_one(&filters, &create_event_context).await.unwrap();
+        updated_state.update(&Updates::new("amount", 30), &update_event_context).await;
+
+        ChaindexingRepo::create_contract_address(
+            &repo_txn_client,
+            &unsaved_contract_address(&bayc_contract.name, &update_event.contract_address),
+        )
+        .await;
+        let rewind = Rewind::new(&ChainId::Mainnet, update_event.get_block_number())
+            .for_contract_address(&update_event.contract_address);
+        rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await.unwrap();
+
+        let state = TokenBalance::read_one(&filters, &update_event_context).await;
+        assert_eq!(state.map(|s| s.amount), Some(U256::from(20)));
+    }
+
+    #[tokio::test]
+    pub async fn rejects_rewinding_contract_addresses_not_indexed() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-35", "35").add_state_migrations(NftMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+
+        let rewind = Rewind::new(&ChainId::Mainnet, event.get_block_number())
+            .for_contract_address(&event.contract_address);
+        let result = rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await;
+
+        assert!(matches!(
+            result,
+            Err(RewindError::UnknownContractAddress(address))
+                if address == event.contract_address.to_lowercase()
+        ));
+    }
+}
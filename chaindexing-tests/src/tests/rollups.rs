@@ -0,0 +1,62 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::rewinding::{self, Rewind};
+    use chaindexing::states::{Filters, Order, RollupEntry};
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, transfer_rollup,
+        unique_transfer_events_with_contract_at, unsaved_contract_address, TransferRollup,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn records_events_into_rollup_buckets() {
+        let rollup = transfer_rollup();
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-30", "30").add_state_migrations(rollup.clone());
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+
+        let events =
+            unique_transfer_events_with_contract_at(bayc_contract.clone(), &[100, 200, 3700]);
+        let token_id = generate_unique_token_id();
+        for (event, (price, buyer)) in events.iter().zip([(5, "0x1"), (9, "0x1"), (3, "0x2")]) {
+            let entry = RollupEntry::new()
+                .group("token_id", token_id)
+                .value("amount", price)
+                .value("price", price)
+                .value("buyer", buyer);
+            rollup.record(&entry, &EventContext::for_event(event, &repo_txn_client)).await;
+        }
+
+        let event_context: EventContext<'_, '_> =
+            EventContext::for_event(&events[0], &repo_txn_client);
+        let filters = Filters::new("token_id", token_id).order_by("bucket_start", Order::Asc);
+        let buckets: Vec<TransferRollup> = rollup.read_many(&filters, &event_context).await;
+        assert_eq!(
+            buckets,
+            vec![
+                TransferRollup::new(token_id, 0, 2, 14, [5, 9, 5, 9], 1),
+                TransferRollup::new(token_id, 3600, 1, 3, [3, 3, 3, 3], 1)
+            ]
+        );
+
+        ChaindexingRepo::create_contract_address(
+            &repo_txn_client,
+            &unsaved_contract_address(&bayc_contract.name, &events[1].contract_address),
+        )
+        .await;
+        let rewind = Rewind::new(&ChainId::Mainnet, events[1].get_block_number())
+            .for_contract_address(&events[1].contract_address);
+        rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await.unwrap();
+
+        let buckets: Vec<TransferRollup> = rollup.read_many(&filters, &event_context).await;
+        assert_eq!(
+            buckets,
+            vec![TransferRollup::new(token_id, 0, 1, 5, [5, 5, 5, 5], 1)]
+        );
+    }
+}
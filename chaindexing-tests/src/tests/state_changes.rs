@@ -0,0 +1,185 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::rewinding::{self, Rewind};
+    use chaindexing::states::{
+        ContractState, StateChange, StateChangeOp, StateChanges, Updates, STATE_CHANGES_CHANNEL,
+    };
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventContext, ExecutesWithRawQuery, HasRawQueryClient,
+    };
+    use ethers::types::{I256, U256};
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract,
+        unsaved_contract_address, Nft, NftMigrations, TokenBalance, TokenBalanceMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn streams_committed_state_changes() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-26", "26")
+            .add_state_migrations(TokenBalanceMigrations);
+        let state_changes = StateChanges::listen(&test_runner::new_repo()).await;
+        let mut subscription = state_changes.subscribe();
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        ChaindexingRepo::execute(&repo_client, "SET chaindexing.state_changes TO 'on'").await;
+
+        let new_state = TokenBalance {
+            token_id: generate_unique_token_id(),
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+
+        let create_event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        new_state
+            .create(&EventContext::for_event(&create_event, &repo_txn_client))
+            .await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let change = recv_state_change(&mut subscription).await;
+        assert_eq!(change.op, StateChangeOp::Create);
+        assert_eq!(change.table_name, "token_balances");
+        assert_eq!(change.block_number, create_event.get_block_number());
+        assert_eq!(
+            change.transaction_hash,
+            Some(create_event.transaction_hash.clone())
+        );
+        assert_eq!(change.old_state::<TokenBalance>(), None);
+        assert_eq!(change.new_state(), Some(new_state.clone()));
+
+        let update_event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        new_state
+            .update(
+                &Updates::new("amount", 20),
+                &EventContext::for_event(&update_event, &repo_txn_client),
+            )
+            .await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let updated_state = TokenBalance {
+            amount: U256::from(20),
+            ..new_state.clone()
+        };
+        let change = recv_state_change(&mut subscription).await;
+        assert_eq!(change.op, StateChangeOp::Update);
+        assert_eq!(change.old_state(), Some(new_state.clone()));
+        assert_eq!(change.new_state(), Some(updated_state.clone()));
+
+        // Rolled back changes don't get notified
+        {
+            let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+            updated_state
+                .delete(&EventContext::for_event(&update_event, &repo_txn_client))
+                .await;
+        }
+
+        let delete_event = unique_transfer_event_with_contract(bayc_contract);
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        updated_state
+            .delete(&EventContext::for_event(&delete_event, &repo_txn_client))
+            .await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let change = recv_state_change(&mut subscription).await;
+        assert_eq!(change.op, StateChangeOp::Delete);
+        assert_eq!(change.block_number, delete_event.get_block_number());
+        assert_eq!(change.old_state(), Some(updated_state));
+        assert_eq!(change.new_state::<TokenBalance>(), None);
+    }
+
+    #[tokio::test]
+    pub async fn streams_retractions_of_backtracked_states() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-27", "27").add_state_migrations(NftMigrations);
+        let state_changes = StateChanges::listen(&test_runner::new_repo()).await;
+        let mut subscription = state_changes.subscribe();
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        ChaindexingRepo::execute(&repo_client, "SET chaindexing.state_changes TO 'on'").await;
+
+        let event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let new_state = Nft {
+            token_id: generate_unique_token_id(),
+        };
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        new_state.create(&EventContext::for_event(&event, &repo_txn_client)).await;
+        ChaindexingRepo::create_contract_address(
+            &repo_txn_client,
+            &unsaved_contract_address(&bayc_contract.name, &event.contract_address),
+        )
+        .await;
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+        assert_eq!(
+            recv_state_change(&mut subscription).await.op,
+            StateChangeOp::Create
+        );
+
+        let rewind = Rewind::new(&ChainId::Mainnet, event.get_block_number())
+            .for_contract_address(&event.contract_address);
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        rewinding::run(&repo_txn_client, &rewind, &[bayc_contract]).await.unwrap();
+        ChaindexingRepo::commit_txns(repo_txn_client).await;
+
+        let change = recv_state_change(&mut subscription).await;
+        assert_eq!(change.op, StateChangeOp::Retract);
+        assert_eq!(change.block_number, event.get_block_number());
+        assert_eq!(change.transaction_hash, None);
+        assert_eq!(change.old_state(), Some(new_state));
+        assert_eq!(change.new_state::<Nft>(), None);
+    }
+
+    #[tokio::test]
+    pub async fn skips_undecodable_state_change_payloads() {
+        let state_changes = StateChanges::listen(&test_runner::new_repo()).await;
+        let mut subscription = state_changes.subscribe();
+        let repo_client = test_runner::new_repo().get_client().await;
+
+        let change = StateChange {
+            table_name: "undecodable_payloads_tests".to_string(),
+            op: StateChangeOp::Create,
+            old_view: None,
+            new_view: Some(serde_json::json!({})),
+            chain_id: ChainId::Mainnet as u64,
+            block_number: 1,
+            contract_address: None,
+            block_hash: None,
+            transaction_hash: None,
+            log_index: None,
+            is_truncated: false,
+        };
+        for payload in [
+            "not a state change".to_string(),
+            serde_json::to_string(&change).unwrap(),
+        ] {
+            repo_client
+                .execute(
+                    "SELECT pg_notify($1, $2)",
+                    &[&STATE_CHANGES_CHANNEL, &payload],
+                )
+                .await
+                .unwrap();
+        }
+
+        // Changes committed by other tests get notified as well
+        loop {
+            let received_change = recv_state_change(&mut subscription).await;
+            if received_change.table_name == change.table_name {
+                assert_eq!(received_change, change);
+                break;
+            }
+        }
+    }
+
+    async fn recv_state_change(
+        subscription: &mut tokio::sync::broadcast::Receiver<StateChange>,
+    ) -> StateChange {
+        tokio::time::timeout(std::time::Duration::from_secs(10), subscription.recv())
+            .await
+            .unwrap()
+            .unwrap()
+    }
+}
@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use chaindexing::states::{AtBlock, ContractState, Filters, Updates};
+    use chaindexing::{ChaindexingRepo, EventContext, HasRawQueryClient};
+    use ethers::types::{I256, U256};
+
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, TokenBalance,
+        TokenBalanceMigrations,
+    };
+    use crate::test_runner;
+
+    #[tokio::test]
+    pub async fn reads_states_at_past_blocks() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-18", "18")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+
+        let mut events = [
+            unique_transfer_event_with_contract(bayc_contract.clone()),
+            unique_transfer_event_with_contract(bayc_contract),
+        ];
+        events.sort_by_key(|event| event.get_block_number());
+        let [create_event, update_event] = events;
+        let create_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&create_event, &repo_txn_client);
+        let update_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&update_event, &repo_txn_client);
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+        new_state.create(&create_event_context).await;
+        new_state.update(&Updates::new("amount", 20), &update_event_context).await;
+
+        let filters = Filters::new("token_id", token_id);
+        let create_block_number = create_event.get_block_number();
+        let update_block_number = update_event.get_block_number();
+
+        let state_before_creation = TokenBalance::read_one_at(
+            &filters,
+            &AtBlock::new(create_block_number - 1),
+            &update_event_context,
+        )
+        .await;
+        assert_eq!(state_before_creation, None);
+
+        let created_state = TokenBalance::read_one_at(
+            &filters,
+            &AtBlock::new(create_block_number),
+            &update_event_context,
+        )
+        .await;
+        assert_eq!(created_state.map(|s| s.amount), Some(U256::from(10)));
+
+        let updated_state = TokenBalance::read_one_at(
+            &filters,
+            &AtBlock::new(update_block_number).log_index(update_event.get_log_index()),
+            &update_event_context,
+        )
+        .await;
+        assert_eq!(updated_state.map(|s| s.amount), Some(U256::from(20)));
+    }
+
+    #[tokio::test]
+    pub async fn reads_states_updated_by_the_event_creating_them_at_its_block() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-40", "40")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+        new_state.create(&event_context).await;
+        for amount in [20, 30, 40] {
+            new_state.update(&Updates::new("amount", amount), &event_context).await;
+        }
+
+        let states = TokenBalance::read_many_at(
+            &Filters::new("token_id", token_id),
+            &AtBlock::new(event.get_block_number()),
+            &event_context,
+        )
+        .await;
+        let amounts: Vec<_> = states.iter().map(|state| state.amount).collect();
+        assert_eq!(amounts, [U256::from(40)]);
+    }
+
+    #[tokio::test]
+    pub async fn reads_history_of_states() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-19", "19")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+
+        let mut events = [
+            unique_transfer_event_with_contract(bayc_contract.clone()),
+            unique_transfer_event_with_contract(bayc_contract.clone()),
+            unique_transfer_event_with_contract(bayc_contract),
+        ];
+        events.sort_by_key(|event| (event.get_block_number(), event.get_log_index()));
+        let event_contexts: Vec<EventContext<'_, '_>> = events
+            .iter()
+            .map(|event| EventContext::for_event(event, &repo_txn_client))
+            .collect();
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+        new_state.create(&event_contexts[0]).await;
+        new_state.update(&Updates::new("amount", 20), &event_contexts[1]).await;
+        new_state.delete(&event_contexts[2]).await;
+
+        let history =
+            TokenBalance::read_history(&Filters::new("token_id", token_id), &event_contexts[2])
+                .await;
+
+        let amounts: Vec<_> = history.iter().map(|version| version.state.amount).collect();
+        assert_eq!(amounts, [10, 20, 20].map(U256::from));
+
+        let is_deleted_flags: Vec<_> = history.iter().map(|version| version.is_deleted).collect();
+        assert_eq!(is_deleted_flags, [false, false, true]);
+
+        let transaction_hashes: Vec<_> =
+            history.iter().map(|version| version.transaction_hash.clone()).collect();
+        let event_transaction_hashes: Vec<_> =
+            events.iter().map(|event| event.transaction_hash.clone()).collect();
+        assert_eq!(transaction_hashes, event_transaction_hashes);
+    }
+
+    #[tokio::test]
+    pub async fn tracks_block_timestamps_of_states() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-20", "20")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+
+        let create_event = unique_transfer_event_with_contract(bayc_contract.clone());
+        let update_event = unique_transfer_event_with_contract(bayc_contract);
+        let create_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&create_event, &repo_txn_client);
+        let update_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&update_event, &repo_txn_client);
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+        new_state.create(&create_event_context).await;
+        new_state.update(&Updates::new("amount", 20), &update_event_context).await;
+
+        let state_row = repo_txn_client
+            .query_one(
+                "SELECT block_timestamp, created_at_block, created_at_timestamp
+                FROM token_balances WHERE token_id = $1",
+                &[&token_id],
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            state_row.get::<_, i64>(0),
+            update_event.get_block_timestamp() as i64
+        );
+        assert_eq!(
+            state_row.get::<_, i64>(1),
+            create_event.get_block_number() as i64
+        );
+        assert_eq!(
+            state_row.get::<_, i64>(2),
+            create_event.get_block_timestamp() as i64
+        );
+
+        let history =
+            TokenBalance::read_history(&Filters::new("token_id", token_id), &update_event_context)
+                .await;
+        assert!(history.iter().all(|version| version.block_timestamp.is_some()));
+    }
+}
@@ -1,48 +1,45 @@
+use chaindexing::HasRawQueryClient;
+
+use crate::factory::{
+    bayc_contract, transfer_rollup, ListingMigrations, NftMigrations, OfferMigrations,
+    TokenBalanceMigrations, TokenSupplyMigrations,
+};
+use crate::test_runner;
+
+pub async fn setup() {
+    let bayc_contract = bayc_contract("BoredApeYachtClub", "06")
+        .add_state_migrations(NftMigrations)
+        .add_state_migrations(TokenBalanceMigrations)
+        .add_state_migrations(ListingMigrations)
+        .add_state_migrations(OfferMigrations)
+        .add_state_migrations(TokenSupplyMigrations)
+        .add_state_migrations(transfer_rollup());
+    let repo_client = test_runner::new_repo().get_client().await;
+    chaindexing::booting::run_user_migrations(&repo_client, &[bayc_contract]).await;
+}
+
 #[cfg(test)]
 mod tests {
-    use std::sync::Arc;
-
-    use chaindexing::deferred_futures::DeferredFutures;
-    use chaindexing::states::{Filters, Updates};
+    use chaindexing::states::{Aggregate, ContractState, Filters, Order, StateCache, Updates};
     use chaindexing::{ChaindexingRepo, EventContext, HasRawQueryClient};
-    use tokio::sync::Mutex;
+    use ethers::types::{I256, U256};
 
-    use super::*;
-    use crate::factory::{bayc_contract, unique_transfer_event_with_contract};
+    use crate::factory::{
+        bayc_contract, generate_unique_token_id, unique_transfer_event_with_contract, Listing,
+        ListingMigrations, Nft, NftMigrations, Offer, OfferMigrations, TokenBalance,
+        TokenBalanceMigrations,
+    };
     use crate::test_runner;
 
-    /// Generate a unique token ID for this test to avoid conflicts
-    fn generate_unique_token_id() -> i32 {
-        use std::sync::atomic::{AtomicI32, Ordering};
-        use std::time::{SystemTime, UNIX_EPOCH};
-
-        static TOKEN_COUNTER: AtomicI32 = AtomicI32::new(0);
-
-        let counter = TOKEN_COUNTER.fetch_add(1, Ordering::SeqCst);
-        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs() as i32;
-        let thread_id = format!("{:?}", std::thread::current().id())
-            .chars()
-            .filter(|c| c.is_ascii_digit())
-            .collect::<String>()
-            .parse::<i32>()
-            .unwrap_or(1);
-
-        // Generate a unique token ID that's very unlikely to collide
-        // Using a large range to minimize collision probability
-        100_000 + (timestamp % 10_000) + (counter % 1_000) + (thread_id % 100)
-    }
-
     #[tokio::test]
     pub async fn creates_state() {
         let bayc_contract =
             bayc_contract("BoredApeYachtClub-1", "09").add_state_migrations(NftMigrations);
         let mut repo_client = test_runner::new_repo().get_client().await;
         let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
-        let event_context: EventContext<'_, '_> = EventContext::new(
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
             &unique_transfer_event_with_contract(bayc_contract),
             &repo_txn_client,
-            &Arc::new(Mutex::new(test_runner::new_repo().get_client().await)),
-            &DeferredFutures::new(),
         );
 
         let token_id = generate_unique_token_id();
@@ -57,6 +54,342 @@ mod tests {
         assert_eq!(new_state, returned_state);
     }
 
+    #[tokio::test]
+    pub async fn reads_states_with_rich_filters() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-12", "12").add_state_migrations(NftMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+
+        let token_id = generate_unique_token_id();
+        let token_ids = [token_id, token_id + 1, token_id + 2, token_id + 3];
+        for token_id in token_ids {
+            Nft { token_id }.create(&event_context).await;
+        }
+
+        let states = Nft::read_many(
+            &Filters::all()
+                .is_in("token_id", token_ids)
+                .gt("token_id", token_id)
+                .or(Filters::new("token_id", token_id))
+                .not_eq("token_id", token_id + 2)
+                .order_by("token_id", Order::Desc)
+                .limit(2),
+            &event_context,
+        )
+        .await;
+
+        assert_eq!(
+            states,
+            vec![
+                Nft {
+                    token_id: token_id + 3
+                },
+                Nft {
+                    token_id: token_id + 1
+                }
+            ]
+        );
+    }
+
+    #[tokio::test]
+    pub async fn round_trips_typed_columns() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-13", "13")
+            .add_state_migrations(TokenBalanceMigrations)
+            .add_state_migrations(ListingMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let create_event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract.clone()),
+            &repo_txn_client,
+        );
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::MAX,
+            delta: I256::from(-5),
+            is_locked: true,
+            memo: None,
+            metadata: serde_json::json!({"tags": ["rare"], "rank": 3}),
+        };
+        new_state.create(&create_event_context).await;
+
+        let returned_state =
+            TokenBalance::read_one(&Filters::new("token_id", token_id), &create_event_context)
+                .await;
+        assert_eq!(returned_state, Some(new_state.clone()));
+
+        // Updates copy the other values from the previous state version exactly
+        let update_event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+        new_state.update(&Updates::new("is_locked", false), &update_event_context).await;
+
+        let updated_state = TokenBalance::read_one(
+            &Filters::new("token_id", token_id).lt("delta", 0),
+            &create_event_context,
+        )
+        .await;
+        assert_eq!(
+            updated_state,
+            Some(TokenBalance {
+                is_locked: false,
+                ..new_state
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn creates_or_increments_state() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-17", "17")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+
+        // Created by the first, then incremented and decremented in the database
+        for updates in [
+            Updates::increment("amount", 10),
+            Updates::increment("amount", U256::exp10(30)),
+            Updates::decrement("amount", 5).add("memo", "decremented"),
+        ] {
+            let event = unique_transfer_event_with_contract(bayc_contract.clone());
+            let event_context: EventContext<'_, '_> =
+                EventContext::for_event(&event, &repo_txn_client);
+            new_state.create_or_update(&updates, &event_context).await;
+        }
+
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+        let returned_state =
+            TokenBalance::read_one(&Filters::new("token_id", token_id), &event_context).await;
+        assert_eq!(
+            returned_state,
+            Some(TokenBalance {
+                amount: U256::exp10(30) + 5,
+                memo: Some("decremented".to_string()),
+                ..new_state
+            })
+        );
+    }
+
+    #[tokio::test]
+    pub async fn coalesces_state_views_in_the_batch_cache() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-21", "21")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let state_cache = StateCache::new();
+
+        let token_id = generate_unique_token_id();
+        let new_state = TokenBalance {
+            token_id,
+            amount: U256::from(10),
+            delta: I256::from(0),
+            is_locked: false,
+            memo: None,
+            metadata: serde_json::json!({}),
+        };
+
+        for updates in [
+            Updates::increment("amount", 10),
+            Updates::increment("amount", 20),
+            Updates::decrement("amount", 5),
+        ] {
+            let event = unique_transfer_event_with_contract(bayc_contract.clone());
+            let event_context: EventContext<'_, '_> =
+                EventContext::for_event(&event, &repo_txn_client).with_state_cache(&state_cache);
+            new_state.create_or_update(&updates, &event_context).await;
+        }
+
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let uncached_event_context: EventContext<'_, '_> =
+            EventContext::for_event(&event, &repo_txn_client);
+        let filters = Filters::new("token_id", token_id);
+
+        // Only versions got written so far
+        assert_eq!(
+            TokenBalance::read_one(&filters, &uncached_event_context).await,
+            None
+        );
+        assert_eq!(
+            TokenBalance::read_history(&filters, &uncached_event_context).await.len(),
+            3
+        );
+
+        let event_context = uncached_event_context.clone().with_state_cache(&state_cache);
+        let expected_state = Some(TokenBalance {
+            amount: U256::from(25),
+            ..new_state
+        });
+        assert_eq!(
+            TokenBalance::read_one(&filters, &event_context).await,
+            expected_state
+        );
+
+        state_cache.flush(&repo_txn_client).await;
+        assert_eq!(
+            TokenBalance::read_one(&filters, &uncached_event_context).await,
+            expected_state
+        );
+    }
+
+    #[tokio::test]
+    pub async fn creates_state_with_derived_migrations() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-14", "14").add_state_migrations(ListingMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+
+        let token_id = generate_unique_token_id();
+        let new_state = Listing {
+            token_id,
+            seller: "0xb518b3136e491101f22b77f385fe22269c515188".to_string(),
+            price: U256::exp10(20),
+            note: None,
+        };
+        new_state.create(&event_context).await;
+
+        let returned_state =
+            Listing::read_one(&Filters::new("token_id", token_id), &event_context).await;
+        assert_eq!(returned_state, Some(new_state));
+
+        let repo_client = test_runner::new_repo().get_client().await;
+        let unique_index_count = repo_client
+            .query_one(
+                "SELECT COUNT(*) FROM pg_indexes WHERE indexname = 'listings_token_id_unique_index'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(unique_index_count.get::<_, i64>(0), 1);
+    }
+
+    #[tokio::test]
+    pub async fn updates_and_deletes_state_by_identity() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-16", "16").add_state_migrations(ListingMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract.clone()),
+            &repo_txn_client,
+        );
+
+        let token_id = generate_unique_token_id();
+        let stale_state = Listing {
+            token_id,
+            seller: "0xb518b3136e491101f22b77f385fe22269c515188".to_string(),
+            price: U256::exp10(20),
+            note: None,
+        };
+        stale_state.create(&event_context).await;
+        stale_state.update(&Updates::new("note", "first"), &event_context).await;
+
+        // Still found by its token_id, despite the stale note
+        stale_state
+            .update(&Updates::new("price", U256::exp10(21)), &event_context)
+            .await;
+
+        let updated_state =
+            Listing::read_one(&Filters::new("token_id", token_id), &event_context).await;
+        assert_eq!(
+            updated_state,
+            Some(Listing {
+                price: U256::exp10(21),
+                note: Some("first".to_string()),
+                ..stale_state.clone()
+            })
+        );
+
+        let next_event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+        stale_state.delete(&next_event_context).await;
+
+        let deleted_state =
+            Listing::read_one(&Filters::new("token_id", token_id), &next_event_context).await;
+        assert_eq!(deleted_state, None);
+    }
+
+    #[tokio::test]
+    pub async fn evolves_state_with_alter_table_migrations() {
+        let bayc_contract =
+            bayc_contract("BoredApeYachtClub-15", "15").add_state_migrations(OfferMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+
+        // Already applied migrations are skipped, including the non-idempotent ALTERs
+        chaindexing::booting::run_user_migrations(
+            &repo_client,
+            std::slice::from_ref(&bayc_contract),
+        )
+        .await;
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event_context: EventContext<'_, '_> = EventContext::for_event(
+            &unique_transfer_event_with_contract(bayc_contract),
+            &repo_txn_client,
+        );
+
+        let token_id = generate_unique_token_id();
+        let new_state = Offer {
+            token_id,
+            buyer: "0xb518b3136e491101f22b77f385fe22269c515188".to_string(),
+            amount: Some(U256::exp10(18)),
+        };
+        new_state.create(&event_context).await;
+
+        let returned_state =
+            Offer::read_one(&Filters::new("buyer", &new_state.buyer), &event_context).await;
+        assert_eq!(returned_state, Some(new_state));
+
+        let repo_client = test_runner::new_repo().get_client().await;
+        let state_versions_columns = repo_client
+            .query(
+                "SELECT column_name::TEXT FROM information_schema.columns
+                WHERE table_name = 'chaindexing_state_versions_for_offers'
+                AND column_name IN ('bidder', 'buyer', 'amount')",
+                &[],
+            )
+            .await
+            .unwrap();
+        let mut state_versions_columns: Vec<String> =
+            state_versions_columns.iter().map(|row| row.get(0)).collect();
+        state_versions_columns.sort();
+        assert_eq!(state_versions_columns, vec!["amount", "buyer"]);
+
+        let index_count = repo_client
+            .query_one(
+                "SELECT COUNT(*) FROM pg_indexes WHERE indexname = 'offers_buyer_index_versions'",
+                &[],
+            )
+            .await
+            .unwrap();
+        assert_eq!(index_count.get::<_, i64>(0), 1);
+    }
+
     #[tokio::test]
     pub async fn updates_state() {
         let bayc_contract =
@@ -65,11 +398,9 @@ mod tests {
         let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
 
         // Create first event context for the create operation
-        let create_event_context: EventContext<'_, '_> = EventContext::new(
+        let create_event_context: EventContext<'_, '_> = EventContext::for_event(
             &unique_transfer_event_with_contract(bayc_contract.clone()),
             &repo_txn_client,
-            &Arc::new(Mutex::new(test_runner::new_repo().get_client().await)),
-            &DeferredFutures::new(),
         );
 
         let initial_token_id = generate_unique_token_id();
@@ -81,11 +412,9 @@ mod tests {
         new_state.create(&create_event_context).await;
 
         // Create second event context for the update operation with different blockchain metadata
-        let update_event_context: EventContext<'_, '_> = EventContext::new(
+        let update_event_context: EventContext<'_, '_> = EventContext::for_event(
             &unique_transfer_event_with_contract(bayc_contract),
             &repo_txn_client,
-            &Arc::new(Mutex::new(test_runner::new_repo().get_client().await)),
-            &DeferredFutures::new(),
         );
 
         new_state
@@ -118,11 +447,9 @@ mod tests {
         let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
 
         // Create first event context for the create operation
-        let create_event_context: EventContext<'_, '_> = EventContext::new(
+        let create_event_context: EventContext<'_, '_> = EventContext::for_event(
             &unique_transfer_event_with_contract(bayc_contract.clone()),
             &repo_txn_client,
-            &Arc::new(Mutex::new(test_runner::new_repo().get_client().await)),
-            &DeferredFutures::new(),
         );
 
         let token_id = generate_unique_token_id();
@@ -130,11 +457,9 @@ mod tests {
         new_state.create(&create_event_context).await;
 
         // Create second event context for the delete operation with different blockchain metadata
-        let delete_event_context: EventContext<'_, '_> = EventContext::new(
+        let delete_event_context: EventContext<'_, '_> = EventContext::for_event(
             &unique_transfer_event_with_contract(bayc_contract),
             &repo_txn_client,
-            &Arc::new(Mutex::new(test_runner::new_repo().get_client().await)),
-            &DeferredFutures::new(),
         );
 
         new_state.delete(&delete_event_context).await;
@@ -142,37 +467,88 @@ mod tests {
         let state = Nft::read_one(&Filters::new("token_id", token_id), &create_event_context).await;
         assert_eq!(state, None);
     }
-}
 
-use chaindexing::augmenting_std::serde::{Deserialize, Serialize};
-use chaindexing::{
-    states::{ContractState, StateMigrations},
-    HasRawQueryClient,
-};
+    #[tokio::test]
+    pub async fn aggregates_states_matching_filters() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-24", "24")
+            .add_state_migrations(TokenBalanceMigrations);
+        let mut repo_client = test_runner::new_repo().get_client().await;
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let event = unique_transfer_event_with_contract(bayc_contract);
+        let event_context: EventContext<'_, '_> = EventContext::for_event(&event, &repo_txn_client);
 
-use crate::{factory::bayc_contract, test_runner};
+        let token_id = generate_unique_token_id();
+        for (token_id, amount, is_locked) in [
+            (token_id, 10, true),
+            (token_id + 1, 20, true),
+            (token_id + 2, 40, false),
+        ] {
+            TokenBalance {
+                token_id,
+                amount: U256::from(amount),
+                delta: I256::from(0),
+                is_locked,
+                memo: None,
+                metadata: serde_json::json!({}),
+            }
+            .create(&event_context)
+            .await;
+        }
+        let filters = Filters::all();
 
-#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(crate = "chaindexing::augmenting_std::serde")]
-struct Nft {
-    token_id: i32,
-}
-impl ContractState for Nft {
-    fn table_name() -> &'static str {
-        "nfts"
-    }
-}
-struct NftMigrations;
-impl StateMigrations for NftMigrations {
-    fn migrations(&self) -> &'static [&'static str] {
-        &["CREATE TABLE IF NOT EXISTS nfts (
-            token_id INTEGER NOT NULL)"]
-    }
-}
+        assert_eq!(TokenBalance::count(&filters, &event_context).await, 3);
+        assert_eq!(
+            TokenBalance::aggregate::<_, U256>(&Aggregate::sum("amount"), &filters, &event_context)
+                .await,
+            U256::from(70)
+        );
+        assert_eq!(
+            TokenBalance::aggregate::<_, Option<i32>>(
+                &Aggregate::max("token_id"),
+                &filters,
+                &event_context
+            )
+            .await,
+            Some(token_id + 2)
+        );
+        assert_eq!(
+            TokenBalance::aggregate::<_, Option<f64>>(
+                &Aggregate::avg("amount"),
+                &filters.clone().add("is_locked", true),
+                &event_context
+            )
+            .await,
+            Some(15.0)
+        );
+        assert_eq!(
+            TokenBalance::aggregate::<_, Option<U256>>(
+                &Aggregate::min("amount"),
+                &filters.clone().lt("token_id", 0),
+                &event_context
+            )
+            .await,
+            None
+        );
 
-pub async fn setup() {
-    let bayc_contract =
-        bayc_contract("BoredApeYachtClub", "06").add_state_migrations(NftMigrations);
-    let repo_client = test_runner::new_repo().get_client().await;
-    chaindexing::booting::run_user_migrations(&repo_client, &[bayc_contract]).await;
+        assert_eq!(
+            TokenBalance::aggregate_by::<_, bool, U256>(
+                "is_locked",
+                &Aggregate::sum("amount"),
+                &filters,
+                &event_context
+            )
+            .await,
+            vec![(false, U256::from(40)), (true, U256::from(30))]
+        );
+        assert_eq!(
+            TokenBalance::aggregate_by::<_, bool, i64>(
+                "is_locked",
+                &Aggregate::count(),
+                &filters.clone().order_by("value", Order::Desc).limit(1),
+                &event_context
+            )
+            .await,
+            vec![(true, 2)]
+        );
+    }
 }
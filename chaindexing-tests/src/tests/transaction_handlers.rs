@@ -0,0 +1,106 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::sync::{Arc, Mutex};
+
+    use chaindexing::states::StateCache;
+    use chaindexing::{
+        ChainId, ChaindexingRepo, EventAbi, EventHandler, HasRawQueryClient, Repo,
+        TransactionContext, TransactionHandler,
+    };
+
+    use crate::factory::{
+        bayc_contract, unique_transfer_event_with_contract, unique_transfer_events_in_transaction,
+        TransferTestHandler,
+    };
+    use crate::test_runner;
+
+    type HandledTransaction = (String, Vec<u32>);
+
+    /// Records the transaction hash and log indexes of each call
+    struct RecordingTransactionHandler {
+        handled_transactions: Arc<Mutex<Vec<HandledTransaction>>>,
+    }
+
+    #[chaindexing::augmenting_std::async_trait]
+    impl TransactionHandler for RecordingTransactionHandler {
+        fn abis(&self) -> Vec<EventAbi> {
+            vec![TransferTestHandler.abi()]
+        }
+        async fn handle_transaction<'a, 'b>(&self, context: TransactionContext<'a, 'b>) {
+            let log_indexes = context.events.iter().map(|event| event.get_log_index()).collect();
+
+            self.handled_transactions
+                .lock()
+                .unwrap()
+                .push((context.get_transaction_hash().to_string(), log_indexes));
+        }
+    }
+
+    #[tokio::test]
+    pub async fn handles_events_of_each_transaction_together_once_in_log_order() {
+        let bayc_contract = bayc_contract("BoredApeYachtClub-36", "36");
+        let transaction_events = unique_transfer_events_in_transaction(bayc_contract.clone(), 3);
+        let other_event = unique_transfer_event_with_contract(bayc_contract);
+
+        // Keeps its events away from tests expecting none
+        let repo = test_runner::new_repo_in_schema("chaindexing_transaction_handlers_tests").await;
+        let pool = repo.get_pool(1).await;
+        let mut conn = ChaindexingRepo::get_conn(&pool).await;
+        let mut stored_events: Vec<_> = transaction_events.iter().rev().cloned().collect();
+        stored_events.push(other_event.clone());
+        ChaindexingRepo::create_events(&mut conn, &stored_events).await;
+
+        let handled_transactions = Arc::new(Mutex::new(vec![]));
+        let transaction_handlers: Vec<Arc<dyn TransactionHandler>> =
+            vec![Arc::new(RecordingTransactionHandler {
+                handled_transactions: handled_transactions.clone(),
+            })];
+
+        // The first event of the transaction falls outside of the handled batch
+        let events = [
+            transaction_events[2].clone(),
+            other_event.clone(),
+            transaction_events[1].clone(),
+        ];
+        let mut repo_client = repo.get_client().await;
+        let events_by_transaction_hash = chaindexing::load_transaction_events(
+            &repo_client,
+            ChainId::Mainnet as u64,
+            &events,
+            &transaction_handlers,
+        )
+        .await;
+
+        let repo_txn_client = ChaindexingRepo::get_txn_client(&mut repo_client).await;
+        let state_cache = StateCache::new();
+        let mut handled_transaction_keys = HashSet::new();
+        for event in &events {
+            chaindexing::handle_transaction(
+                event,
+                &transaction_handlers,
+                &events_by_transaction_hash,
+                &mut handled_transaction_keys,
+                &repo_txn_client,
+                &state_cache,
+            )
+            .await;
+        }
+
+        let transaction_log_indexes =
+            transaction_events.iter().map(|event| event.get_log_index()).collect();
+        assert_eq!(
+            *handled_transactions.lock().unwrap(),
+            vec![
+                (
+                    transaction_events[0].transaction_hash.clone(),
+                    transaction_log_indexes
+                ),
+                (
+                    other_event.transaction_hash.clone(),
+                    vec![other_event.get_log_index()]
+                )
+            ]
+        );
+    }
+}
@@ -1,6 +1,9 @@
+use std::sync::Arc;
+
+use crate::states::{self, StateMigrations};
 use crate::{
-    contracts, root, ChaindexingError, ChaindexingRepo, ChaindexingRepoClient, Config, Contract,
-    ExecutesWithRawQuery, LoadsDataWithRawQuery, Migratable, RepoMigrations,
+    contracts, reindexing, root, ChaindexingError, ChaindexingRepo, ChaindexingRepoClient, Config,
+    Contract, ExecutesWithRawQuery, LoadsDataWithRawQuery, Migratable, RepoMigrations,
 };
 
 pub async fn setup_nodes<S: Sync + Send + Clone>(
@@ -17,6 +20,7 @@ pub async fn setup<S: Sync + Send + Clone>(
         reset_count,
         reset_including_side_effects_count,
         reset_queries,
+        reindex_count,
         ..
     }: &Config<S>,
     client: &ChaindexingRepoClient,
@@ -33,12 +37,17 @@ pub async fn setup<S: Sync + Send + Clone>(
     .await;
 
     run_internal_migrations(client).await;
+
+    maybe_reset_changed_versions(contracts, client).await;
+
     run_user_migrations(client, contracts).await;
 
     let contract_addresses: Vec<_> =
         contracts.clone().into_iter().flat_map(|c| c.addresses).collect();
     ChaindexingRepo::create_contract_addresses(client, &contract_addresses).await;
 
+    maybe_reindex(*reindex_count, contracts, client).await;
+
     Ok(())
 }
 
@@ -56,6 +65,12 @@ pub async fn setup_root(client: &ChaindexingRepoClient) {
     )
     .await;
 
+    ChaindexingRepo::migrate(
+        client,
+        ChaindexingRepo::create_applied_state_migrations_migration().to_vec(),
+    )
+    .await;
+
     if ChaindexingRepo::load_last_root_state(client).await.is_none() {
         ChaindexingRepo::append_root_state(client, &Default::default()).await;
     }
@@ -100,6 +115,87 @@ async fn maybe_reset<S: Send + Sync + Clone>(
     }
 }
 
+/// Replays only the contracts whose handlers or state migrations got a new version.
+/// Their state tables are recreated and their events get handled again from the
+/// start block, without re-ingesting them or re-running side effects.
+/// Newly added event handlers get cursors to catch up on their own instead.
+async fn maybe_reset_changed_versions<S: Send + Sync + Clone>(
+    contracts: &[Contract<S>],
+    client: &ChaindexingRepoClient,
+) {
+    let mut root_state = ChaindexingRepo::load_last_root_state(client).await.unwrap();
+
+    let handler_versions = contracts::get_handler_versions(contracts);
+    let state_migration_versions = contracts::get_state_migration_versions(contracts);
+
+    if root_state.has_same_versions(&handler_versions, &state_migration_versions) {
+        return;
+    }
+
+    let mut changed_handler_versions = root_state.get_changed_handler_versions(&handler_versions);
+    let new_handler_versions = root_state.get_new_handler_versions(&handler_versions);
+
+    // Transaction handlers have no cursors to catch up with, so new ones replay their contracts
+    let transaction_handler_version_keys: Vec<_> = contracts
+        .iter()
+        .flat_map(|c| c.get_transaction_handler_version_keys())
+        .collect();
+    changed_handler_versions.extend(
+        new_handler_versions
+            .iter()
+            .filter(|key| transaction_handler_version_keys.contains(key))
+            .cloned(),
+    );
+    let mut changed_state_migration_versions =
+        root_state.get_changed_state_migration_versions(&state_migration_versions);
+    changed_state_migration_versions
+        .extend(root_state.get_added_state_migration_versions(&state_migration_versions));
+
+    let contracts_to_reset = contracts::get_contracts_with_versions(
+        contracts,
+        &changed_handler_versions,
+        &changed_state_migration_versions,
+    );
+
+    if !contracts_to_reset.is_empty() {
+        let state_migrations: Vec<_> =
+            contracts_to_reset.iter().flat_map(|c| c.state_migrations.clone()).collect();
+        reset_state_migrations(client, &state_migrations).await;
+
+        let contract_names: Vec<_> = contracts_to_reset.iter().map(|c| c.name.clone()).collect();
+        ChaindexingRepo::restart_next_block_numbers_to_handle_from(client, &contract_names).await;
+    }
+
+    for contract in contracts {
+        for event_abi in contract.get_handler_event_abis(&new_handler_versions) {
+            ChaindexingRepo::create_handler_cursors(client, &contract.name, event_abi).await;
+        }
+    }
+
+    root_state.update_versions(&handler_versions, &state_migration_versions);
+    ChaindexingRepo::append_root_state(client, &root_state).await;
+}
+
+/// Starts rebuilding states in shadow tables when the reindex count gets bumped.
+/// Handlers swap them in once they catch up with the live ones.
+async fn maybe_reindex<S: Send + Sync + Clone>(
+    reindex_count: u64,
+    contracts: &[Contract<S>],
+    client: &ChaindexingRepoClient,
+) {
+    let mut root_state = ChaindexingRepo::load_last_root_state(client).await.unwrap();
+    let state_migrations = contracts::get_state_migrations(contracts);
+
+    if reindex_count > root_state.reindex_count {
+        reindexing::start(client, &state_migrations).await;
+
+        root_state.update_reindex_count(reindex_count);
+        ChaindexingRepo::append_root_state(client, &root_state).await;
+    } else if reindexing::is_in_progress(client).await {
+        reindexing::run_shadow_migrations(client, &state_migrations).await;
+    }
+}
+
 async fn reset<S: Send + Sync + Clone>(
     reset_queries: &Vec<String>,
     contracts: &[Contract<S>],
@@ -121,17 +217,19 @@ pub async fn run_user_migrations<S: Send + Sync + Clone>(
     client: &ChaindexingRepoClient,
     contracts: &[Contract<S>],
 ) {
-    for state_migration in contracts::get_state_migrations(contracts) {
-        ChaindexingRepo::migrate(client, state_migration.get_migrations()).await;
-    }
+    states::run_migrations(client, &contracts::get_state_migrations(contracts)).await;
 }
 async fn reset_user_migrations<S: Send + Sync + Clone>(
     client: &ChaindexingRepoClient,
     contracts: &[Contract<S>],
 ) {
-    for state_migration in contracts::get_state_migrations(contracts) {
-        ChaindexingRepo::migrate(client, state_migration.get_reset_migrations()).await;
-    }
+    reset_state_migrations(client, &contracts::get_state_migrations(contracts)).await;
+}
+async fn reset_state_migrations(
+    client: &ChaindexingRepoClient,
+    state_migrations: &[Arc<dyn StateMigrations>],
+) {
+    states::reset_migrations(client, state_migrations).await;
 }
 async fn run_user_reset_queries(client: &ChaindexingRepoClient, reset_queries: &Vec<String>) {
     for reset_query in reset_queries {
@@ -4,6 +4,7 @@ use tokio::sync::Mutex;
 
 use crate::chain_reorg::MinConfirmationCount;
 use crate::chains::Chain;
+use crate::invariants::Invariant;
 use crate::nodes::{self, NodeHeartbeat};
 use crate::pruning::PruningConfig;
 use crate::{ChaindexingRepo, Contract};
@@ -61,11 +62,16 @@ pub struct Config<SharedState: Sync + Send + Clone> {
     node_election_rate_ms: Option<u64>,
     pub reset_count: u64,
     pub(crate) reset_including_side_effects_count: u64,
+    pub(crate) reindex_count: u64,
     pub reset_queries: Vec<String>,
     pub shared_state: Option<Arc<Mutex<SharedState>>>,
     pub max_concurrent_node_count: u16,
     pub optimization_config: Option<OptimizationConfig>,
     pub(crate) pruning_config: Option<PruningConfig>,
+    pub(crate) is_state_changes_enabled: bool,
+    pub(crate) invariants: Vec<Invariant>,
+    #[cfg(feature = "rest")]
+    pub(crate) rest_server_addr: Option<String>,
 }
 
 impl<SharedState: Sync + Send + Clone> Config<SharedState> {
@@ -82,11 +88,16 @@ impl<SharedState: Sync + Send + Clone> Config<SharedState> {
             node_election_rate_ms: None,
             reset_count: 0,
             reset_including_side_effects_count: 0,
+            reindex_count: 0,
             reset_queries: vec![],
             shared_state: None,
             max_concurrent_node_count: nodes::DEFAULT_MAX_CONCURRENT_NODE_COUNT,
             optimization_config: None,
             pruning_config: None,
+            is_state_changes_enabled: false,
+            invariants: vec![],
+            #[cfg(feature = "rest")]
+            rest_server_addr: None,
         }
     }
 
@@ -104,6 +115,13 @@ impl<SharedState: Sync + Send + Clone> Config<SharedState> {
         self
     }
 
+    /// Adds an invariant checked after every contract's handled batches
+    pub fn add_invariant(mut self, invariant: Invariant) -> Self {
+        self.invariants.push(invariant);
+
+        self
+    }
+
     /// Allows managing derived app states (derived from indexed states)
     pub fn add_reset_query(mut self, reset_query: &str) -> Self {
         self.reset_queries.push(reset_query.to_string());
@@ -112,7 +130,9 @@ impl<SharedState: Sync + Send + Clone> Config<SharedState> {
     }
 
     /// Restarts indexing from scratch for EventHandlers. SideEffectHandlers
-    /// will not run if they ran already
+    /// will not run if they ran already.
+    /// To replay only the contracts whose handler logic changed, bump the
+    /// `version` of their EventHandlers or StateMigrations instead.
     pub fn reset(mut self, count: u64) -> Self {
         self.reset_count = count;
 
@@ -127,6 +147,16 @@ impl<SharedState: Sync + Send + Clone> Config<SharedState> {
         self
     }
 
+    /// Rebuilds states from already ingested events, without downtime.
+    /// States get rebuilt in shadow tables while the live ones keep getting
+    /// served and updated, then get swapped in once caught up.
+    /// SideEffectHandlers will not run again.
+    pub fn reindex(mut self, count: u64) -> Self {
+        self.reindex_count = count;
+
+        self
+    }
+
     /// Defines the initial state for side effect handlers
     pub fn with_initial_state(mut self, initial_state: SharedState) -> Self {
         self.shared_state = Some(Arc::new(Mutex::new(initial_state)));
@@ -209,6 +239,24 @@ impl<SharedState: Sync + Send + Clone> Config<SharedState> {
         self
     }
 
+    /// Notifies the changes of live states, on the `STATE_CHANGES_CHANNEL`
+    /// Postgres channel, for `StateChanges` to stream them. Changes of states
+    /// getting reindexed aren't notified.
+    pub fn with_state_changes(mut self) -> Self {
+        self.is_state_changes_enabled = true;
+
+        self
+    }
+
+    /// Serves the state tables and indexing status over HTTP, on the address,
+    /// once `index_states` sets them up. See the `rest` module.
+    #[cfg(feature = "rest")]
+    pub fn with_rest_server(mut self, addr: impl ToString) -> Self {
+        self.rest_server_addr = Some(addr.to_string());
+
+        self
+    }
+
     /// This enables optimization for indexing with the CAVEAT that you have to
     /// manually keep chaindexing alive e.g. when a user enters certain pages
     /// in your DApp
@@ -2,7 +2,9 @@ use std::fmt::Debug;
 use std::{collections::HashMap, str::FromStr, sync::Arc};
 
 use crate::diesel::schema::chaindexing_contract_addresses;
-use crate::handlers::PureHandler;
+use crate::handlers::{PureHandler, TransactionHandler};
+use crate::invariants::Invariant;
+use crate::root::states::Versions;
 use crate::states::StateMigrations;
 use crate::ChainId;
 use crate::{EventHandler, SideEffectHandler};
@@ -43,7 +45,9 @@ pub struct Contract<S: Send + Sync + Clone> {
     pub name: String,
     pub pure_handlers: HashMap<EventAbi, Arc<dyn PureHandler>>,
     pub side_effect_handlers: HashMap<EventAbi, Arc<dyn SideEffectHandler<SharedState = S>>>,
+    pub transaction_handlers: Vec<Arc<dyn TransactionHandler>>,
     pub state_migrations: Vec<Arc<dyn StateMigrations>>,
+    pub invariants: Vec<Invariant>,
 }
 
 impl<S: Send + Sync + Clone> Contract<S> {
@@ -63,6 +67,8 @@ impl<S: Send + Sync + Clone> Contract<S> {
             name: name.to_string(),
             pure_handlers: HashMap::new(),
             side_effect_handlers: HashMap::new(),
+            transaction_handlers: vec![],
+            invariants: vec![],
         }
     }
 
@@ -100,6 +106,13 @@ impl<S: Send + Sync + Clone> Contract<S> {
         self
     }
 
+    /// Adds a handler for transactions emitting several events together
+    pub fn add_transaction_handler(mut self, handler: impl TransactionHandler + 'static) -> Self {
+        self.transaction_handlers.push(Arc::new(handler));
+
+        self
+    }
+
     /// Adds state migrations for the contract states being indexed
     pub fn add_state_migrations(mut self, state_migration: impl StateMigrations + 'static) -> Self {
         self.state_migrations.push(Arc::new(state_migration));
@@ -107,11 +120,20 @@ impl<S: Send + Sync + Clone> Contract<S> {
         self
     }
 
+    /// Adds an invariant checked after each handled batch of the contract's addresses
+    pub fn add_invariant(mut self, invariant: Invariant) -> Self {
+        self.invariants.push(invariant);
+
+        self
+    }
+
     pub(crate) fn get_event_abis(&self) -> Vec<EventAbi> {
         let mut event_abis: Vec<_> = self.pure_handlers.clone().into_keys().collect();
         let side_effect_abis: Vec<_> = self.pure_handlers.clone().into_keys().collect();
 
         event_abis.extend(side_effect_abis);
+        event_abis.extend(self.transaction_handlers.iter().flat_map(|h| h.abis()));
+        event_abis.sort();
         event_abis.dedup();
 
         event_abis
@@ -127,6 +149,85 @@ impl<S: Send + Sync + Clone> Contract<S> {
     pub(crate) fn build_events(&self) -> Vec<ContractEvent> {
         self.get_event_abis().iter().map(|abi| ContractEvent::new(abi)).collect()
     }
+
+    pub(crate) fn get_handler_versions(&self) -> Versions {
+        let transaction_handler_versions = self.transaction_handlers.iter().map(|handler| {
+            (
+                self.get_transaction_handler_version_key(handler.as_ref()),
+                handler.version(),
+            )
+        });
+
+        self.pure_handlers
+            .iter()
+            .map(|(event_abi, handler)| {
+                (self.get_handler_version_key(event_abi), handler.version())
+            })
+            .chain(transaction_handler_versions)
+            .collect()
+    }
+
+    pub(crate) fn get_transaction_handler_version_keys(&self) -> Vec<String> {
+        self.transaction_handlers
+            .iter()
+            .map(|handler| self.get_transaction_handler_version_key(handler.as_ref()))
+            .collect()
+    }
+
+    /// Event ABIs of the handlers matching the given version keys
+    pub(crate) fn get_handler_event_abis(&self, handler_version_keys: &[String]) -> Vec<EventAbi> {
+        self.pure_handlers
+            .keys()
+            .filter(|event_abi| {
+                handler_version_keys.contains(&self.get_handler_version_key(event_abi))
+            })
+            .copied()
+            .collect()
+    }
+
+    fn get_handler_version_key(&self, event_abi: &str) -> String {
+        format!("{}:{event_abi}", self.name)
+    }
+
+    fn get_transaction_handler_version_key(&self, handler: &dyn TransactionHandler) -> String {
+        format!(
+            "{}:{}:[{}]",
+            self.name,
+            handler.name(),
+            handler.abis().join("; ")
+        )
+    }
+
+    /// Versions its state tables got created with, so tables added to it are told apart
+    pub(crate) fn get_state_migration_versions(&self) -> Versions {
+        self.state_migrations
+            .iter()
+            .flat_map(|state_migration| {
+                state_migration.get_table_names().into_iter().map(|table_name| {
+                    (
+                        format!("{}:{table_name}", self.name),
+                        state_migration.version(),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    pub(crate) fn get_state_table_names(&self) -> Vec<String> {
+        self.state_migrations.iter().flat_map(|sm| sm.get_table_names()).collect()
+    }
+
+    fn has_any_version(
+        &self,
+        handler_version_keys: &[String],
+        state_migration_version_keys: &[String],
+    ) -> bool {
+        self.get_handler_versions().keys().any(|key| handler_version_keys.contains(key))
+            || self
+                .get_state_migration_versions()
+                .keys()
+                .any(|key| state_migration_version_keys.contains(key))
+    }
 }
 
 impl<S: Send + Sync + Clone> Debug for Contract<S> {
@@ -144,6 +245,61 @@ pub fn get_state_migrations<S: Send + Sync + Clone>(
     contracts.iter().flat_map(|c| c.state_migrations.clone()).collect()
 }
 
+pub fn get_handler_versions<S: Send + Sync + Clone>(contracts: &[Contract<S>]) -> Versions {
+    contracts.iter().flat_map(|c| c.get_handler_versions()).collect()
+}
+
+pub fn get_state_migration_versions<S: Send + Sync + Clone>(contracts: &[Contract<S>]) -> Versions {
+    contracts.iter().flat_map(|c| c.get_state_migration_versions()).collect()
+}
+
+/// Contracts with handlers or state migrations matching the given version keys,
+/// along with every contract sharing a state table with them. Resetting a
+/// contract recreates its tables, dropping the states of the others too.
+pub fn get_contracts_with_versions<'a, S: Send + Sync + Clone>(
+    contracts: &'a [Contract<S>],
+    handler_version_keys: &[String],
+    state_migration_version_keys: &[String],
+) -> Vec<&'a Contract<S>> {
+    let mut is_matched: Vec<_> = contracts
+        .iter()
+        .map(|c| c.has_any_version(handler_version_keys, state_migration_version_keys))
+        .collect();
+
+    loop {
+        let matched_table_names: Vec<_> = contracts
+            .iter()
+            .zip(&is_matched)
+            .filter(|(_contract, is_matched)| **is_matched)
+            .flat_map(|(contract, _)| contract.get_state_table_names())
+            .collect();
+
+        let mut has_new_matches = false;
+        for (contract, is_matched) in contracts.iter().zip(is_matched.iter_mut()) {
+            if !*is_matched
+                && contract
+                    .get_state_table_names()
+                    .iter()
+                    .any(|table_name| matched_table_names.contains(table_name))
+            {
+                *is_matched = true;
+                has_new_matches = true;
+            }
+        }
+
+        if !has_new_matches {
+            break;
+        }
+    }
+
+    contracts
+        .iter()
+        .zip(is_matched)
+        .filter(|(_contract, is_matched)| *is_matched)
+        .map(|(contract, _)| contract)
+        .collect()
+}
+
 pub fn get_pure_handlers<S: Send + Sync + Clone>(
     contracts: &[Contract<S>],
 ) -> HashMap<EventAbi, Arc<dyn PureHandler>> {
@@ -155,6 +311,20 @@ pub fn get_pure_handlers<S: Send + Sync + Clone>(
     })
 }
 
+pub fn get_transaction_handlers<S: Send + Sync + Clone>(
+    contracts: &[Contract<S>],
+) -> HashMap<String, Vec<Arc<dyn TransactionHandler>>> {
+    contracts
+        .iter()
+        .fold(HashMap::new(), |mut handlers_by_contract_name, contract| {
+            if !contract.transaction_handlers.is_empty() {
+                handlers_by_contract_name
+                    .insert(contract.name.clone(), contract.transaction_handlers.clone());
+            }
+            handlers_by_contract_name
+        })
+}
+
 pub fn get_side_effect_handlers<S: Send + Sync + Clone>(
     contracts: &[Contract<S>],
 ) -> HashMap<EventAbi, Arc<dyn SideEffectHandler<SharedState = S>>> {
@@ -232,7 +402,7 @@ pub struct ContractAddress {
 }
 
 impl ContractAddress {
-    fn get_chain_id(&self) -> ChainId {
+    pub(crate) fn get_chain_id(&self) -> ChainId {
         U64::from(self.chain_id).try_into().unwrap()
     }
 
@@ -255,3 +425,131 @@ impl ContractAddress {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::{PureHandlerContext, TransactionHandlerContext};
+
+    const TRANSFER_ABI: &str =
+        "event Transfer(address indexed from, address indexed to, uint256 value)";
+    const SWAP_ABI: &str = "event Swap(address indexed sender, uint256 amount0In, uint256 amount1In, uint256 amount0Out, uint256 amount1Out, address indexed to)";
+
+    struct TransferHandler;
+
+    #[crate::augmenting_std::async_trait]
+    impl PureHandler for TransferHandler {
+        fn abi(&self) -> &'static str {
+            TRANSFER_ABI
+        }
+        async fn handle_event<'a, 'b>(&self, _context: PureHandlerContext<'a, 'b>) {}
+    }
+
+    struct SwapTransactionHandler;
+
+    #[crate::augmenting_std::async_trait]
+    impl TransactionHandler for SwapTransactionHandler {
+        fn abis(&self) -> Vec<EventAbi> {
+            vec![TRANSFER_ABI, SWAP_ABI]
+        }
+        fn version(&self) -> u64 {
+            2
+        }
+        async fn handle_transaction<'a, 'b>(&self, _context: TransactionHandlerContext<'a, 'b>) {}
+    }
+
+    #[test]
+    fn ingests_events_of_transaction_handlers() {
+        let contract = Contract::<()>::new("UniswapV2Pair")
+            .add_event_handler(TransferHandler)
+            .add_transaction_handler(SwapTransactionHandler);
+
+        let mut event_abis = contract.get_event_abis();
+        event_abis.sort();
+
+        assert_eq!(event_abis, vec![SWAP_ABI, TRANSFER_ABI]);
+    }
+
+    struct TransfersMigrations;
+
+    impl StateMigrations for TransfersMigrations {
+        fn migrations(&self) -> &'static [&'static str] {
+            &["CREATE TABLE IF NOT EXISTS transfers (value TEXT NOT NULL)"]
+        }
+    }
+
+    struct SwapsMigrations;
+
+    impl StateMigrations for SwapsMigrations {
+        fn migrations(&self) -> &'static [&'static str] {
+            &["CREATE TABLE IF NOT EXISTS swaps (value TEXT NOT NULL)"]
+        }
+    }
+
+    #[test]
+    fn gets_contracts_sharing_state_tables_with_changed_ones() {
+        let contracts = [
+            Contract::<()>::new("UniswapV2Pair")
+                .add_event_handler(TransferHandler)
+                .add_state_migrations(TransfersMigrations),
+            Contract::<()>::new("WETH")
+                .add_state_migrations(TransfersMigrations)
+                .add_state_migrations(SwapsMigrations),
+            Contract::<()>::new("UniswapV3Pool").add_state_migrations(SwapsMigrations),
+            Contract::<()>::new("USDC").add_event_handler(TransferHandler),
+        ];
+        let handler_version_keys: Vec<_> =
+            contracts[0].get_handler_versions().into_keys().collect();
+
+        let contract_names: Vec<_> =
+            get_contracts_with_versions(&contracts, &handler_version_keys, &[])
+                .iter()
+                .map(|c| c.name.as_str())
+                .collect();
+
+        assert_eq!(
+            contract_names,
+            vec!["UniswapV2Pair", "WETH", "UniswapV3Pool"]
+        );
+    }
+
+    struct SyncTransactionHandler;
+
+    #[crate::augmenting_std::async_trait]
+    impl TransactionHandler for SyncTransactionHandler {
+        fn abis(&self) -> Vec<EventAbi> {
+            vec![TRANSFER_ABI, SWAP_ABI]
+        }
+        async fn handle_transaction<'a, 'b>(&self, _context: TransactionHandlerContext<'a, 'b>) {}
+    }
+
+    #[test]
+    fn versions_transaction_handlers_of_the_same_abis_apart() {
+        let contract = Contract::<()>::new("UniswapV2Pair")
+            .add_transaction_handler(SwapTransactionHandler)
+            .add_transaction_handler(SyncTransactionHandler);
+
+        let handler_versions = contract.get_handler_versions();
+
+        assert_eq!(handler_versions.len(), 2);
+        assert_eq!(
+            handler_versions.values().copied().collect::<std::collections::HashSet<_>>(),
+            [1, 2].into()
+        );
+    }
+
+    #[test]
+    fn versions_transaction_handlers() {
+        let contract =
+            Contract::<()>::new("UniswapV2Pair").add_transaction_handler(SwapTransactionHandler);
+
+        let handler_version_keys = contract.get_transaction_handler_version_keys();
+
+        assert_eq!(handler_version_keys.len(), 1);
+        assert_eq!(
+            contract.get_handler_versions().get(&handler_version_keys[0]),
+            Some(&2)
+        );
+        assert!(contract.get_handler_event_abis(&handler_version_keys).is_empty());
+    }
+}
@@ -50,6 +50,18 @@ pub mod schema {
       }
     }
 
+    diesel::table! {
+      chaindexing_handler_cursors (id) {
+          id -> Int8,
+          chain_id -> Int8,
+          contract_address -> VarChar,
+          abi -> Text,
+          next_block_number_to_ingest_from -> Int8,
+          ingest_until_block_number -> Int8,
+          next_block_number_to_handle_from -> Int8,
+      }
+    }
+
     diesel::allow_tables_to_appear_in_same_query!(
         chaindexing_contract_addresses,
         chaindexing_events,
@@ -10,11 +10,11 @@ use ethers::utils::format_ether;
 use crate::{ChainId, ContractEvent};
 use uuid::Uuid;
 
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 /// Events, aka. provider logs, are emitted from smart contracts
 /// to help infer their states.
-#[derive(Debug, Deserialize, Clone, Eq, Queryable, Insertable)]
+#[derive(Debug, Serialize, Deserialize, Clone, Eq, Queryable, Insertable)]
 #[diesel(table_name = chaindexing_events)]
 pub struct Event {
     pub id: Uuid,
@@ -149,10 +149,14 @@ impl EventParam {
         }
     }
 
-    /// N/B: This function is UNSAFE.
-    /// Ensure source contract can be trusted before using it or
-    /// preprocess the string before indexing.
-    /// A potential attacker could inject SQL string statements from  here.
+    /// Returns `string`. Safe to index as is, since states
+    /// get written with bound parameters.
+    pub fn get_string(&self, key: &str) -> String {
+        self.get_token(key).into_string().unwrap()
+    }
+
+    /// Returns the parameter's display representation, whatever its type
+    #[deprecated(note = "use `get_string` instead, states are written with bound parameters")]
     pub fn get_string_unsafely(&self, key: &str) -> String {
         self.value.get(key).unwrap().to_string()
     }
@@ -346,4 +350,10 @@ mod event_param_tests {
             I256::from_dec_str("-26311681626831253271").unwrap()
         );
     }
+
+    #[test]
+    fn returns_string_values() {
+        let event_param = EventParam::new(&json!({"name":{"String":"Bored Ape's Club"}}));
+        assert_eq!(event_param.get_string("name"), "Bored Ape's Club");
+    }
 }
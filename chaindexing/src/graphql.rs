@@ -0,0 +1,788 @@
+//! # GraphQL
+//! Serves a GraphQL schema generated from the registered state tables, with the
+//! `graphql` feature. Each table gets a query field of the same name, filterable,
+//! orderable and paginated like [`Filters`], readable at past blocks from the
+//! state versions, and a `{table}_changes` subscription fed by [`StateChanges`].
+//!
+//! ```graphql
+//! {
+//!   nfts(
+//!     where: { owner_address: "0xbc4c", token_id_gt: 100, or: [{ is_listed: true }] }
+//!     order_by: [{ field: token_id, order: DESC }]
+//!     limit: 20
+//!     at_block: 17000000
+//!   ) {
+//!     token_id
+//!     owner_address
+//!   }
+//! }
+//! ```
+//!
+//! Fields are named after their columns, and filters after their columns with
+//! `_not`, `_gt`, `_gte`, `_lt`, `_lte`, `_like`, `_in`, `_not_in` and `_is_null`
+//! suffixes. `or` matches states matching either the other filters or any of its
+//! own. NUMERIC(78, 0) columns are `BigNumber`s, read as hex strings and filtered
+//! by hex or decimal ones. Subscriptions need the indexing processes to run with
+//! `Config::with_state_changes`.
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! chaindexing::index_states(&config).await.unwrap();
+//! // Once the state migrations ran
+//! chaindexing::graphql::serve(&config, "0.0.0.0:8080").await;
+//! ```
+
+use std::collections::HashMap;
+use std::future::ready;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use async_graphql::dynamic::{
+    Enum, Field, FieldFuture, FieldValue, InputObject, InputValue, Object, ResolverContext, Scalar,
+    Schema, Subscription, SubscriptionField, SubscriptionFieldFuture, TypeRef, ValueAccessor,
+};
+use async_graphql::http::{GraphiQLSource, WebSocket, WebSocketProtocols, WsMessage};
+use async_graphql::{Name, Value};
+use axum::extract::ws::{CloseFrame, Message, WebSocketUpgrade};
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{Html, IntoResponse};
+use axum::routing::get;
+use axum::{Json, Router};
+use futures_util::{stream, SinkExt, StreamExt};
+use tokio::net::ToSocketAddrs;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::states::{
+    self, AtBlock, Filters, Order, StateChange, StateChangeOp, StateChanges, StateColumn,
+    StateQueryClient,
+};
+use crate::{contracts, Config, HasRawQueryClient, I256, U256};
+
+/// Number of states returned when no limit is given
+pub const DEFAULT_LIMIT: u64 = 100;
+/// Largest number of states returned at once
+pub const MAX_LIMIT: u64 = 1_000;
+
+const GRAPHQL_PATH: &str = "/graphql";
+const GRAPHQL_WS_PATH: &str = "/graphql/ws";
+
+/// Serves the schema of the config's state tables until the server fails.
+/// GraphiQL gets served along on GET requests.
+pub async fn serve<S: Send + Sync + Clone>(config: &Config<S>, addr: impl ToSocketAddrs) {
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+
+    axum::serve(listener, router(schema(config).await)).await.unwrap();
+}
+
+/// Routes queries to the schema, for serving it along other routes
+pub fn router(schema: Schema) -> Router {
+    Router::new()
+        .route(GRAPHQL_PATH, get(graphiql).post(execute))
+        .route(GRAPHQL_WS_PATH, get(subscribe))
+        .with_state(schema)
+}
+
+/// Generates the schema of the config's state tables from their columns, so
+/// their migrations must have run already
+pub async fn schema<S: Send + Sync + Clone>(config: &Config<S>) -> Schema {
+    let state_migrations = contracts::get_state_migrations(&config.contracts);
+    let client = config.repo.get_client().await;
+
+    let mut tables = vec![];
+    for table_name in states::get_all_table_names(&state_migrations) {
+        let columns = states::load_columns(&table_name, &client).await;
+
+        tables.push(Table::new(&table_name, columns));
+    }
+
+    let query_client = StateQueryClient::new(&config.repo).await;
+    let state_changes = StateChanges::listen(&config.repo).await;
+
+    build_schema(&tables)
+        .data(Arc::new(query_client))
+        .data(state_changes)
+        .finish()
+        .unwrap()
+}
+
+async fn execute(
+    State(schema): State<Schema>,
+    Json(request): Json<async_graphql::Request>,
+) -> Json<async_graphql::Response> {
+    Json(schema.execute(request).await)
+}
+
+async fn graphiql() -> Html<String> {
+    Html(
+        GraphiQLSource::build()
+            .endpoint(GRAPHQL_PATH)
+            .subscription_endpoint(GRAPHQL_WS_PATH)
+            .finish(),
+    )
+}
+
+async fn subscribe(
+    State(schema): State<Schema>,
+    headers: HeaderMap,
+    upgrade: WebSocketUpgrade,
+) -> impl IntoResponse {
+    let protocol = headers
+        .get("sec-websocket-protocol")
+        .and_then(|protocols| protocols.to_str().ok())
+        .and_then(|protocols| {
+            protocols
+                .split(',')
+                .find_map(|protocol| WebSocketProtocols::from_str(protocol.trim()).ok())
+        })
+        .unwrap_or(WebSocketProtocols::GraphQLWS);
+
+    upgrade
+        .protocols([protocol.sec_websocket_protocol()])
+        .on_upgrade(move |socket| async move {
+            let (mut sink, messages) = socket.split();
+            let messages =
+                messages.take_while(|message| ready(message.is_ok())).filter_map(|message| {
+                    ready(match message.unwrap() {
+                        Message::Text(text) => Some(text.as_str().as_bytes().to_vec()),
+                        Message::Binary(bytes) => Some(bytes.to_vec()),
+                        _ => None,
+                    })
+                });
+
+            let mut replies = WebSocket::new(schema, messages, protocol);
+            while let Some(reply) = replies.next().await {
+                let reply = match reply {
+                    WsMessage::Text(text) => Message::Text(text.into()),
+                    WsMessage::Close(code, reason) => Message::Close(Some(CloseFrame {
+                        code,
+                        reason: reason.into(),
+                    })),
+                };
+
+                if sink.send(reply).await.is_err() {
+                    break;
+                }
+            }
+        })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnKind {
+    Int,
+    BigInt,
+    BigNumber,
+    Float,
+    Boolean,
+    String,
+    Json,
+}
+
+impl ColumnKind {
+    fn from_data_type(data_type: &str) -> Self {
+        match data_type {
+            "smallint" | "integer" => ColumnKind::Int,
+            "bigint" => ColumnKind::BigInt,
+            "numeric(78,0)" => ColumnKind::BigNumber,
+            "real" | "double precision" => ColumnKind::Float,
+            "boolean" => ColumnKind::Boolean,
+            "json" | "jsonb" => ColumnKind::Json,
+            data_type if data_type.starts_with("numeric") => ColumnKind::Float,
+            _ => ColumnKind::String,
+        }
+    }
+
+    fn type_name(self) -> &'static str {
+        match self {
+            ColumnKind::Int => TypeRef::INT,
+            ColumnKind::BigInt => BIG_INT,
+            ColumnKind::BigNumber => BIG_NUMBER,
+            ColumnKind::Float => TypeRef::FLOAT,
+            ColumnKind::Boolean => TypeRef::BOOLEAN,
+            ColumnKind::String => TypeRef::STRING,
+            ColumnKind::Json => JSON,
+        }
+    }
+
+    fn get_filter_ops(self) -> &'static [FilterOp] {
+        use FilterOp::*;
+
+        match self {
+            ColumnKind::Json => &[IsNull],
+            ColumnKind::Boolean => &[Eq, NotEq, IsNull],
+            ColumnKind::String => &[Eq, NotEq, Gt, Gte, Lt, Lte, Like, In, NotIn, IsNull],
+            _ => &[Eq, NotEq, Gt, Gte, Lt, Lte, In, NotIn, IsNull],
+        }
+    }
+
+    /// Converts the GraphQL value to the text Filters compare columns with
+    fn to_filter_value(self, value: &Value) -> async_graphql::Result<String> {
+        let invalid_value = || format!("Invalid {} filter value: {value}", self.type_name());
+
+        let value = match (self, value) {
+            (ColumnKind::BigInt, value) => {
+                parse_big_int(value).ok_or_else(invalid_value)?.to_string()
+            }
+            (ColumnKind::BigNumber, value) => parse_big_number(value).ok_or_else(invalid_value)?,
+            (_, Value::String(value)) => value.to_owned(),
+            (_, Value::Number(value)) => value.to_string(),
+            (_, Value::Boolean(value)) => value.to_string(),
+            (_, value) => return Err(format!("Unsupported filter value: {value}").into()),
+        };
+
+        Ok(value)
+    }
+}
+
+/// BigInt values come as numbers, or strings for those beyond JavaScript's safe integers
+fn parse_big_int(value: &Value) -> Option<i64> {
+    match value {
+        Value::Number(value) => value.as_i64(),
+        Value::String(value) => value.parse().ok(),
+        _ => None,
+    }
+}
+
+/// Decimal text of BigNumber values, given as hex or decimal strings, or integers
+fn parse_big_number(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => match value.strip_prefix("0x") {
+            Some(hex) => U256::from_str_radix(hex, 16).ok().map(|value| value.to_string()),
+            None => I256::from_dec_str(value).ok().map(|value| value.to_string()),
+        },
+        Value::Number(value) if value.is_i64() || value.is_u64() => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+const BIG_INT: &str = "BigInt";
+const BIG_NUMBER: &str = "BigNumber";
+const JSON: &str = "JSON";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum FilterOp {
+    Eq,
+    NotEq,
+    Gt,
+    Gte,
+    Lt,
+    Lte,
+    Like,
+    In,
+    NotIn,
+    IsNull,
+}
+
+impl FilterOp {
+    fn suffix(self) -> &'static str {
+        match self {
+            FilterOp::Eq => "",
+            FilterOp::NotEq => "_not",
+            FilterOp::Gt => "_gt",
+            FilterOp::Gte => "_gte",
+            FilterOp::Lt => "_lt",
+            FilterOp::Lte => "_lte",
+            FilterOp::Like => "_like",
+            FilterOp::In => "_in",
+            FilterOp::NotIn => "_not_in",
+            FilterOp::IsNull => "_is_null",
+        }
+    }
+
+    fn to_type_ref(self, kind: ColumnKind) -> TypeRef {
+        match self {
+            FilterOp::In | FilterOp::NotIn => TypeRef::named_nn_list(kind.type_name()),
+            FilterOp::IsNull => TypeRef::named(TypeRef::BOOLEAN),
+            _ => TypeRef::named(kind.type_name()),
+        }
+    }
+}
+
+/// GraphQL types of a state table
+#[derive(Clone, Debug)]
+struct Table {
+    name: String,
+    type_name: String,
+    columns: Vec<(String, ColumnKind)>,
+    /// Column and op of each filter field
+    filter_fields: HashMap<String, (String, ColumnKind, FilterOp)>,
+}
+
+impl Table {
+    fn new(name: &str, columns: Vec<StateColumn>) -> Self {
+        let columns: Vec<_> = columns
+            .into_iter()
+            .map(|column| (column.name, ColumnKind::from_data_type(&column.data_type)))
+            .collect();
+
+        let filter_fields = columns
+            .iter()
+            .flat_map(|(column, kind)| {
+                kind.get_filter_ops().iter().map(move |op| {
+                    (
+                        format!("{column}{}", op.suffix()),
+                        (column.clone(), *kind, *op),
+                    )
+                })
+            })
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            type_name: to_pascal_case(name),
+            columns,
+            filter_fields,
+        }
+    }
+
+    fn filter_type_name(&self) -> String {
+        format!("{}Filter", self.type_name)
+    }
+
+    fn field_type_name(&self) -> String {
+        format!("{}Field", self.type_name)
+    }
+
+    fn order_by_type_name(&self) -> String {
+        format!("{}OrderBy", self.type_name)
+    }
+
+    fn change_type_name(&self) -> String {
+        format!("{}Change", self.type_name)
+    }
+
+    fn to_filters(&self, filter: &ValueAccessor) -> async_graphql::Result<Filters> {
+        let mut filters = Filters::all();
+        let mut or_filters = vec![];
+
+        for (field, value) in filter.object()?.iter() {
+            if field.as_str() == "or" {
+                for filter in value.list()?.iter() {
+                    or_filters.push(self.to_filters(&filter)?);
+                }
+
+                continue;
+            }
+
+            let (column, kind, op) = &self.filter_fields[field.as_str()];
+            filters = match op {
+                FilterOp::IsNull if value.boolean()? => filters.is_null(column),
+                FilterOp::IsNull => filters.is_not_null(column),
+                FilterOp::In | FilterOp::NotIn => {
+                    let values = value
+                        .list()?
+                        .iter()
+                        .map(|value| kind.to_filter_value(value.as_value()))
+                        .collect::<async_graphql::Result<Vec<_>>>()?;
+
+                    if *op == FilterOp::In {
+                        filters.is_in(column, values)
+                    } else {
+                        filters.not_in(column, values)
+                    }
+                }
+                op => {
+                    let value = kind.to_filter_value(value.as_value())?;
+
+                    match op {
+                        FilterOp::Eq => filters.add(column, value),
+                        FilterOp::NotEq => filters.not_eq(column, value),
+                        FilterOp::Gt => filters.gt(column, value),
+                        FilterOp::Gte => filters.gte(column, value),
+                        FilterOp::Lt => filters.lt(column, value),
+                        FilterOp::Lte => filters.lte(column, value),
+                        _ => filters.like(column, value),
+                    }
+                }
+            };
+        }
+
+        Ok(or_filters.into_iter().fold(filters, Filters::or))
+    }
+
+    /// Filters of a query field's arguments
+    fn to_query_filters(&self, ctx: &ResolverContext) -> async_graphql::Result<Filters> {
+        let mut filters = match ctx.args.get("where") {
+            Some(filter) => self.to_filters(&filter)?,
+            None => Filters::all(),
+        };
+
+        if let Some(order_bys) = ctx.args.get("order_by") {
+            for order_by in order_bys.list()?.iter() {
+                let order_by = order_by.object()?;
+                let order = match order_by.try_get("order")?.enum_name()? {
+                    "DESC" => Order::Desc,
+                    _ => Order::Asc,
+                };
+
+                filters = filters.order_by(order_by.try_get("field")?.enum_name()?, order);
+            }
+        }
+
+        let limit = match ctx.args.get("limit") {
+            Some(limit) => limit.u64()?.min(MAX_LIMIT),
+            None => DEFAULT_LIMIT,
+        };
+        filters = filters.limit(limit);
+
+        if let Some(offset) = ctx.args.get("offset") {
+            filters = filters.offset(offset.u64()?);
+        }
+
+        Ok(filters)
+    }
+
+    fn object(&self) -> Object {
+        self.columns
+            .iter()
+            .fold(Object::new(&self.type_name), |object, (column, kind)| {
+                let column = column.clone();
+
+                object.field(Field::new(
+                    column.clone(),
+                    TypeRef::named(kind.type_name()),
+                    move |ctx| {
+                        let column = column.clone();
+
+                        FieldFuture::new(async move {
+                            let state = ctx.parent_value.try_downcast_ref::<serde_json::Value>()?;
+
+                            match state.get(&column) {
+                                None | Some(serde_json::Value::Null) => Ok(None),
+                                Some(value) => {
+                                    Ok(Some(FieldValue::value(Value::from_json(value.clone())?)))
+                                }
+                            }
+                        })
+                    },
+                ))
+            })
+    }
+
+    fn filter_input(&self) -> InputObject {
+        let mut filter_fields: Vec<_> = self.filter_fields.iter().collect();
+        filter_fields.sort_by_key(|(field, _)| field.as_str());
+
+        filter_fields.into_iter().fold(
+            InputObject::new(self.filter_type_name()).field(InputValue::new(
+                "or",
+                TypeRef::named_nn_list(self.filter_type_name()),
+            )),
+            |input, (field, (_, kind, op))| {
+                input.field(InputValue::new(field, op.to_type_ref(*kind)))
+            },
+        )
+    }
+
+    fn field_enum(&self) -> Enum {
+        Enum::new(self.field_type_name())
+            .items(self.columns.iter().map(|(column, _)| column.as_str()))
+    }
+
+    fn order_by_input(&self) -> InputObject {
+        InputObject::new(self.order_by_type_name())
+            .field(InputValue::new(
+                "field",
+                TypeRef::named_nn(self.field_type_name()),
+            ))
+            .field(
+                InputValue::new("order", TypeRef::named_nn(ORDER))
+                    .default_value(Value::Enum(Name::new("ASC"))),
+            )
+    }
+
+    fn query_field(self: &Arc<Self>) -> Field {
+        let table = self.clone();
+
+        Field::new(
+            self.name.clone(),
+            TypeRef::named_nn_list_nn(&self.type_name),
+            move |ctx| {
+                let table = table.clone();
+
+                FieldFuture::new(async move {
+                    let filters = table.to_query_filters(&ctx)?;
+                    let query_client = ctx.data::<Arc<StateQueryClient>>()?;
+
+                    let states: Vec<serde_json::Value> = match ctx.args.get("at_block") {
+                        Some(block_number) => {
+                            let mut at = AtBlock::new(block_number.u64()?);
+                            if let Some(log_index) = ctx.args.get("at_log_index") {
+                                at = at.log_index(log_index.u64()? as u32);
+                            }
+
+                            query_client.read_many_at(&table.name, &filters, &at).await
+                        }
+                        None => query_client.read_many(&table.name, &filters).await,
+                    };
+
+                    Ok(Some(FieldValue::list(
+                        states.into_iter().map(FieldValue::owned_any),
+                    )))
+                })
+            },
+        )
+        .argument(InputValue::new(
+            "where",
+            TypeRef::named(self.filter_type_name()),
+        ))
+        .argument(InputValue::new(
+            "order_by",
+            TypeRef::named_nn_list(self.order_by_type_name()),
+        ))
+        .argument(InputValue::new("limit", TypeRef::named(TypeRef::INT)))
+        .argument(InputValue::new("offset", TypeRef::named(TypeRef::INT)))
+        .argument(InputValue::new("at_block", TypeRef::named(BIG_INT)))
+        .argument(InputValue::new(
+            "at_log_index",
+            TypeRef::named(TypeRef::INT),
+        ))
+    }
+
+    fn change_object(&self) -> Object {
+        let change_field =
+            |name: &str, type_ref: TypeRef, get: fn(&StateChange) -> FieldValue<'static>| {
+                Field::new(name, type_ref, move |ctx| {
+                    FieldFuture::new(async move {
+                        let change = ctx.parent_value.try_downcast_ref::<StateChange>()?;
+
+                        Ok(Some(get(change)))
+                    })
+                })
+            };
+        let view_field = |name: &str, get: fn(&StateChange) -> Option<serde_json::Value>| {
+            Field::new(name, TypeRef::named(&self.type_name), move |ctx| {
+                FieldFuture::new(async move {
+                    let change = ctx.parent_value.try_downcast_ref::<StateChange>()?;
+
+                    Ok(get(change).map(FieldValue::owned_any))
+                })
+            })
+        };
+
+        Object::new(self.change_type_name())
+            .field(change_field(
+                "op",
+                TypeRef::named_nn(STATE_CHANGE_OP),
+                |change| {
+                    let op = match change.op {
+                        StateChangeOp::Create => "CREATE",
+                        StateChangeOp::Update => "UPDATE",
+                        StateChangeOp::Delete => "DELETE",
+                        StateChangeOp::Retract => "RETRACT",
+                    };
+
+                    FieldValue::value(Value::Enum(Name::new(op)))
+                },
+            ))
+            .field(view_field("old_state", |change| change.old_view.clone()))
+            .field(view_field("new_state", |change| change.new_view.clone()))
+            .field(change_field(
+                "chain_id",
+                TypeRef::named_nn(BIG_INT),
+                |change| FieldValue::value(change.chain_id),
+            ))
+            .field(change_field(
+                "block_number",
+                TypeRef::named_nn(BIG_INT),
+                |change| FieldValue::value(change.block_number),
+            ))
+            .field(change_field(
+                "contract_address",
+                TypeRef::named(TypeRef::STRING),
+                |change| FieldValue::value(change.contract_address.clone()),
+            ))
+            .field(change_field(
+                "block_hash",
+                TypeRef::named(TypeRef::STRING),
+                |change| FieldValue::value(change.block_hash.clone()),
+            ))
+            .field(change_field(
+                "transaction_hash",
+                TypeRef::named(TypeRef::STRING),
+                |change| FieldValue::value(change.transaction_hash.clone()),
+            ))
+            .field(change_field(
+                "log_index",
+                TypeRef::named(TypeRef::INT),
+                |change| FieldValue::value(change.log_index),
+            ))
+            .field(change_field(
+                "is_truncated",
+                TypeRef::named_nn(TypeRef::BOOLEAN),
+                |change| FieldValue::value(change.is_truncated),
+            ))
+    }
+
+    fn subscription_field(&self) -> SubscriptionField {
+        let table_name = self.name.clone();
+
+        SubscriptionField::new(
+            format!("{}_changes", self.name),
+            TypeRef::named_nn(self.change_type_name()),
+            move |ctx| {
+                let table_name = table_name.clone();
+
+                SubscriptionFieldFuture::new(async move {
+                    let subscription = ctx.data::<StateChanges>()?.subscribe();
+
+                    Ok(stream::unfold(subscription, move |mut subscription| {
+                        let table_name = table_name.clone();
+
+                        async move {
+                            loop {
+                                match subscription.recv().await {
+                                    Ok(change) if change.table_name == table_name => {
+                                        let change = FieldValue::owned_any(change);
+
+                                        break Some((Ok(change), subscription));
+                                    }
+                                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                                    Err(RecvError::Closed) => break None,
+                                }
+                            }
+                        }
+                    }))
+                })
+            },
+        )
+    }
+}
+
+const ORDER: &str = "Order";
+const STATE_CHANGE_OP: &str = "StateChangeOp";
+
+fn build_schema(tables: &[Table]) -> async_graphql::dynamic::SchemaBuilder {
+    let mut query = Object::new("Query");
+    let mut subscription = Subscription::new("Subscription");
+    let mut types = vec![];
+
+    for table in tables {
+        let table = Arc::new(table.clone());
+
+        query = query.field(table.query_field());
+        subscription = subscription.field(table.subscription_field());
+
+        types.push(table.object().into());
+        types.push(table.filter_input().into());
+        types.push(table.field_enum().into());
+        types.push(table.order_by_input().into());
+        types.push(table.change_object().into());
+    }
+
+    let schema = Schema::build("Query", None, Some("Subscription"))
+        .register(query)
+        .register(subscription)
+        .register(
+            Scalar::new(BIG_INT)
+                .description("64-bit integer")
+                .validator(|value| parse_big_int(value).is_some()),
+        )
+        .register(
+            Scalar::new(BIG_NUMBER)
+                .description("NUMERIC(78, 0) value, such as U256 and I256 ones, as a hex string")
+                .validator(|value| parse_big_number(value).is_some()),
+        )
+        .register(Scalar::new(JSON))
+        .register(Enum::new(ORDER).items(["ASC", "DESC"]))
+        .register(Enum::new(STATE_CHANGE_OP).items(["CREATE", "UPDATE", "DELETE", "RETRACT"]));
+
+    types.into_iter().fold(schema, |schema, ty: async_graphql::dynamic::Type| {
+        schema.register(ty)
+    })
+}
+
+fn to_pascal_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token_balances() -> Table {
+        let column = |name: &str, data_type: &str| StateColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+        };
+
+        Table::new(
+            "token_balances",
+            vec![
+                column("token_id", "integer"),
+                column("owner", "text"),
+                column("amount", "numeric(78,0)"),
+                column("metadata", "jsonb"),
+                column("block_number", "bigint"),
+            ],
+        )
+    }
+
+    #[test]
+    fn maps_columns_to_graphql_types() {
+        let table = token_balances();
+
+        assert_eq!(table.type_name, "TokenBalances");
+        assert_eq!(
+            table.columns.iter().map(|(_, kind)| kind.type_name()).collect::<Vec<_>>(),
+            vec!["Int", "String", "BigNumber", "JSON", "BigInt"]
+        );
+        assert!(table.filter_fields.contains_key("owner_like"));
+        assert!(!table.filter_fields.contains_key("amount_like"));
+        assert!(!table.filter_fields.contains_key("metadata"));
+        assert!(table.filter_fields.contains_key("metadata_is_null"));
+    }
+
+    #[test]
+    fn generates_a_valid_schema() {
+        let schema = build_schema(&[token_balances()]).finish().unwrap();
+        let sdl = schema.sdl();
+
+        assert!(sdl.contains("token_balances(where: TokenBalancesFilter"));
+        assert!(sdl.contains("token_balances_changes: TokenBalancesChange!"));
+        assert!(sdl.contains("amount_gte: BigNumber"));
+        assert!(sdl.contains("token_id_in: [Int!]"));
+    }
+
+    #[test]
+    fn reads_big_number_filters_as_decimals() {
+        let hex = Value::String("0x1bc16d674ec80000".to_string());
+        let decimal = Value::String("2000000000000000000".to_string());
+
+        assert_eq!(
+            ColumnKind::BigNumber.to_filter_value(&hex).unwrap(),
+            "2000000000000000000"
+        );
+        assert_eq!(
+            ColumnKind::BigNumber.to_filter_value(&decimal).unwrap(),
+            "2000000000000000000"
+        );
+    }
+    #[test]
+    fn rejects_non_numeric_filters_of_numeric_columns() {
+        let text = Value::String("abc".to_string());
+
+        assert!(ColumnKind::BigNumber.to_filter_value(&text).is_err());
+        assert!(ColumnKind::BigInt.to_filter_value(&text).is_err());
+        assert_eq!(
+            ColumnKind::BigNumber
+                .to_filter_value(&Value::String("-42".to_string()))
+                .unwrap(),
+            "-42"
+        );
+        assert_eq!(
+            ColumnKind::BigInt.to_filter_value(&Value::String("42".to_string())).unwrap(),
+            "42"
+        );
+    }
+}
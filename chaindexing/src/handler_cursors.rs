@@ -0,0 +1,74 @@
+use serde::Deserialize;
+
+/// Tracks an EventHandler lagging behind its contract address, typically
+/// because it got added after the contract's events were already handled.
+/// It catches up on its own: events for its ABI are backfilled from the contract
+/// address' start, then handled until it reaches the contract address' cursor.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct HandlerCursor {
+    pub id: i64,
+    pub chain_id: i64,
+    pub contract_address: String,
+    pub abi: String,
+    pub next_block_number_to_ingest_from: i64,
+    /// Exclusive. The ingester ingests the ABI's events from here onwards.
+    pub ingest_until_block_number: i64,
+    pub next_block_number_to_handle_from: i64,
+}
+
+impl HandlerCursor {
+    pub fn is_backfilling_events(&self) -> bool {
+        self.next_block_number_to_ingest_from < self.ingest_until_block_number
+    }
+
+    /// Exclusive block number up to which events can be handled,
+    /// given the contract address' own cursor
+    pub fn get_handle_until_block_number(&self, next_block_number_to_handle_from: i64) -> i64 {
+        if self.is_backfilling_events() {
+            next_block_number_to_handle_from.min(self.next_block_number_to_ingest_from)
+        } else {
+            next_block_number_to_handle_from
+        }
+    }
+
+    pub fn has_caught_up(&self, next_block_number_to_handle_from: i64) -> bool {
+        !self.is_backfilling_events()
+            && self.next_block_number_to_handle_from >= next_block_number_to_handle_from
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler_cursor(ingest_from: i64, ingest_until: i64, handle_from: i64) -> HandlerCursor {
+        HandlerCursor {
+            id: 1,
+            chain_id: 1,
+            contract_address: "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d".to_string(),
+            abi:
+                "event Transfer(address indexed from, address indexed to, uint256 indexed tokenId)"
+                    .to_string(),
+            next_block_number_to_ingest_from: ingest_from,
+            ingest_until_block_number: ingest_until,
+            next_block_number_to_handle_from: handle_from,
+        }
+    }
+
+    #[test]
+    fn handles_only_backfilled_events() {
+        let handler_cursor = handler_cursor(150, 200, 100);
+
+        assert_eq!(handler_cursor.get_handle_until_block_number(300), 150);
+        assert!(!handler_cursor.has_caught_up(100));
+    }
+
+    #[test]
+    fn catches_up_with_contract_address_after_backfilling() {
+        let handler_cursor = handler_cursor(200, 200, 300);
+
+        assert_eq!(handler_cursor.get_handle_until_block_number(300), 300);
+        assert!(handler_cursor.has_caught_up(300));
+        assert!(!handler_cursor.has_caught_up(301));
+    }
+}
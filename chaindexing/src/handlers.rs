@@ -2,22 +2,28 @@ use std::cmp::max;
 use std::fmt::Debug;
 use std::{sync::Arc, time::Duration};
 
-mod handle_events;
+pub mod handle_events;
 mod handler_context;
 mod maybe_handle_chain_reorg;
 mod pure_handler;
 mod side_effect_handler;
+mod transaction_handler;
 
 pub use handler_context::HandlerContext;
 pub use pure_handler::{PureHandler, PureHandlerContext};
 pub use side_effect_handler::{SideEffectHandler, SideEffectHandlerContext};
+pub use transaction_handler::{handle_transaction, load_transaction_events};
+pub use transaction_handler::{TransactionHandler, TransactionHandlerContext};
 
-use tokio::{sync::Mutex, time::interval};
+use tokio::{
+    sync::{Mutex, RwLock},
+    time::interval,
+};
 
-use crate::deferred_futures::DeferredFutures;
+use crate::invariants::InvariantChecker;
 use crate::nodes::NodeTask;
 use crate::Config;
-use crate::{contracts, states, HasRawQueryClient};
+use crate::{contracts, reindexing, states, ChaindexingRepoClient, HasRawQueryClient};
 
 pub async fn start<S: Send + Sync + Clone + Debug + 'static>(config: &Config<S>) -> NodeTask {
     let node_task = NodeTask::new();
@@ -27,15 +33,13 @@ pub async fn start<S: Send + Sync + Clone + Debug + 'static>(config: &Config<S>)
         .add_subtask(tokio::spawn({
             let node_task = node_task.clone();
 
-            // MultiChainStates are indexed in an order-agnostic fashion, so no need for txn client
-            let repo_client_for_mcs = Arc::new(Mutex::new(config.repo.get_client().await));
-            let deferred_mutations_for_mcs = DeferredFutures::new();
+            // Live states only get paused by chain reorgs and reindexing swaps
+            let states_lock = Arc::new(RwLock::new(()));
 
             async move {
                 for chain_ids in get_chunked_chain_ids(&config) {
                     let config = config.clone();
-                    let repo_client_for_mcs = repo_client_for_mcs.clone();
-                    let deferred_mutations_for_mcs = deferred_mutations_for_mcs.clone();
+                    let states_lock = states_lock.clone();
 
                     node_task
                         .clone()
@@ -43,29 +47,71 @@ pub async fn start<S: Send + Sync + Clone + Debug + 'static>(config: &Config<S>)
                             let mut interval =
                                 interval(Duration::from_millis(config.handler_rate_ms));
 
-                            let repo_client = Arc::new(Mutex::new(config.repo.get_client().await));
+                            let repo_client = Arc::new(Mutex::new(get_live_client(&config).await));
                             let pure_handlers = contracts::get_pure_handlers(&config.contracts);
+                            let transaction_handlers =
+                                contracts::get_transaction_handlers(&config.contracts);
                             let side_effect_handlers =
                                 contracts::get_side_effect_handlers(&config.contracts);
+                            let invariant_checker = InvariantChecker::new(&config).await;
 
                             loop {
+                                let states_lock = states_lock.read().await;
+
                                 handle_events::run(
                                     &pure_handlers,
+                                    &transaction_handlers,
                                     &side_effect_handlers,
                                     (&chain_ids, config.blocks_per_batch),
-                                    (&repo_client, &repo_client_for_mcs),
-                                    &deferred_mutations_for_mcs,
+                                    &repo_client,
                                     &config.shared_state,
+                                    &invariant_checker,
                                 )
                                 .await;
 
+                                drop(states_lock);
+
                                 interval.tick().await;
                             }
                         }))
                         .await;
                 }
 
-                let mut repo_client = config.repo.get_client().await;
+                node_task
+                    .clone()
+                    .add_subtask(tokio::spawn({
+                        let config = config.clone();
+                        let states_lock = states_lock.clone();
+
+                        async move {
+                            let mut interval =
+                                interval(Duration::from_millis(config.handler_rate_ms));
+
+                            let repo_client = Arc::new(Mutex::new(config.repo.get_client().await));
+                            // Applies the MultiChainState mutations queued for shadow states
+                            let repo_client_for_mcs = config.repo.get_client().await;
+                            reindexing::set_shadow_search_path(&repo_client_for_mcs).await;
+                            let repo_client_for_mcs = Arc::new(Mutex::new(repo_client_for_mcs));
+                            let pure_handlers = contracts::get_pure_handlers(&config.contracts);
+                            let transaction_handlers =
+                                contracts::get_transaction_handlers(&config.contracts);
+
+                            loop {
+                                reindexing::run(
+                                    &config,
+                                    (&pure_handlers, &transaction_handlers),
+                                    &states_lock,
+                                    (&repo_client, &repo_client_for_mcs),
+                                )
+                                .await;
+
+                                interval.tick().await;
+                            }
+                        }
+                    }))
+                    .await;
+
+                let mut repo_client = get_live_client(&config).await;
 
                 let state_migrations = contracts::get_state_migrations(&config.contracts);
                 let state_table_names = states::get_all_table_names(&state_migrations);
@@ -73,9 +119,15 @@ pub async fn start<S: Send + Sync + Clone + Debug + 'static>(config: &Config<S>)
                 let mut interval = interval(Duration::from_millis(2 * config.handler_rate_ms));
 
                 loop {
-                    maybe_handle_chain_reorg::run(&mut repo_client, &state_table_names).await;
-
-                    deferred_mutations_for_mcs.consume().await;
+                    {
+                        let _states_lock = states_lock.write().await;
+                        maybe_handle_chain_reorg::run(&mut repo_client, &state_table_names).await;
+                    }
+                    {
+                        // MultiChainStates are indexed in an order-agnostic fashion, after their handlers commit
+                        let _states_lock = states_lock.read().await;
+                        states::apply_mcs_mutations(&mut repo_client).await;
+                    }
 
                     interval.tick().await;
                 }
@@ -86,6 +138,19 @@ pub async fn start<S: Send + Sync + Clone + Debug + 'static>(config: &Config<S>)
     node_task
 }
 
+/// Client for indexing live states, notifying their changes when enabled
+pub(crate) async fn get_live_client<S: Send + Sync + Clone>(
+    config: &Config<S>,
+) -> ChaindexingRepoClient {
+    let client = config.repo.get_client().await;
+
+    if config.is_state_changes_enabled {
+        states::enable_state_changes(&client).await;
+    }
+
+    client
+}
+
 fn get_chunked_chain_ids<S: Send + Sync + Clone + Debug + 'static>(
     config: &Config<S>,
 ) -> Vec<Vec<u64>> {
@@ -1,31 +1,46 @@
+use std::collections::{HashMap, HashSet};
 use std::fmt::Debug;
-use std::{collections::HashMap, sync::Arc};
+use std::sync::Arc;
 
 use futures_util::StreamExt;
 use tokio::sync::Mutex;
 
-use crate::deferred_futures::DeferredFutures;
+use crate::handler_cursors::HandlerCursor;
+use crate::invariants::{self, InvariantChecker};
+use crate::states::StateCache;
 use crate::streams::ContractAddressesStream;
-use crate::{ChaindexingRepo, ChaindexingRepoClientMutex};
+use crate::{ChaindexingRepo, ChaindexingRepoClientMutex, ChaindexingRepoTxnClient, Event};
 use crate::{EventAbi, ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery};
 
 use super::pure_handler::{PureHandler, PureHandlerContext};
 use super::side_effect_handler::{SideEffectHandler, SideEffectHandlerContext};
+use super::transaction_handler::{self, TransactionHandler};
 
-pub async fn run<'a, S: Send + Sync + Clone + Debug>(
+pub async fn run<S: Send + Sync + Clone + Debug>(
     pure_handlers: &HashMap<EventAbi, Arc<dyn PureHandler>>,
+    transaction_handlers: &HashMap<String, Vec<Arc<dyn TransactionHandler>>>,
     side_effect_handlers: &HashMap<EventAbi, Arc<dyn SideEffectHandler<SharedState = S>>>,
     (chain_ids, blocks_per_batch): (&[u64], u64),
-    (repo_client, repo_client_for_mcs): (&ChaindexingRepoClientMutex, &ChaindexingRepoClientMutex),
-    deferred_mutations_for_mcs: &DeferredFutures<'a>,
+    repo_client: &ChaindexingRepoClientMutex,
     shared_state: &Option<Arc<Mutex<S>>>,
+    invariant_checker: &InvariantChecker,
 ) {
     for chain_id in chain_ids {
+        let handler_cursors =
+            ChaindexingRepo::load_handler_cursors(&*repo_client.lock().await, *chain_id).await;
+        let halted_contract_addresses =
+            invariants::load_halted_contract_addresses(&*repo_client.lock().await, *chain_id).await;
+
         let mut contract_addresses_stream =
             ContractAddressesStream::new(repo_client, *chain_id as i64).with_chunk_size(200);
 
         while let Some(contract_addresses) = contract_addresses_stream.next().await {
             for contract_address in contract_addresses {
+                // Halted by invariant violations until they get resolved
+                if halted_contract_addresses.contains(&contract_address.address) {
+                    continue;
+                }
+
                 let from_block_number = contract_address.next_block_number_to_handle_from as u64;
 
                 let client = repo_client.clone();
@@ -41,29 +56,98 @@ pub async fn run<'a, S: Send + Sync + Clone + Debug>(
                 )
                 .await;
 
+                let mut lagging_events_by_handler_cursor = vec![];
+                for handler_cursor in handler_cursors
+                    .iter()
+                    .filter(|hc| hc.contract_address == contract_address.address)
+                {
+                    // Batched by blocks so that no block gets handled partially
+                    let until_block_number = handler_cursor
+                        .get_handle_until_block_number(
+                            contract_address.next_block_number_to_handle_from,
+                        )
+                        .min(
+                            handler_cursor.next_block_number_to_handle_from
+                                + blocks_per_batch as i64,
+                        );
+                    let lagging_events = ChaindexingRepo::load_events_by_abi(
+                        &client,
+                        handler_cursor,
+                        until_block_number as u64,
+                    )
+                    .await;
+
+                    lagging_events_by_handler_cursor.push((
+                        handler_cursor,
+                        until_block_number,
+                        lagging_events,
+                    ));
+                }
+
+                let transaction_handlers = transaction_handlers
+                    .get(&contract_address.contract_name)
+                    .map(|h| h.as_slice())
+                    .unwrap_or_default();
+                let events_by_transaction_hash = transaction_handler::load_transaction_events(
+                    &client,
+                    *chain_id,
+                    &events,
+                    transaction_handlers,
+                )
+                .await;
+                let mut handled_transactions = HashSet::new();
+
                 // ChainStates which include ContractState have to be handled orderly
                 let txn_client = ChaindexingRepo::get_txn_client(&mut client).await;
+                let state_cache = StateCache::new();
+
+                let mut lagging_abis = vec![];
+                for (handler_cursor, until_block_number, lagging_events) in
+                    &lagging_events_by_handler_cursor
+                {
+                    let has_caught_up = handle_lagging_events(
+                        pure_handlers,
+                        handler_cursor,
+                        (*until_block_number, lagging_events),
+                        contract_address.next_block_number_to_handle_from,
+                        &txn_client,
+                        &state_cache,
+                    )
+                    .await;
+
+                    if !has_caught_up {
+                        lagging_abis.push(handler_cursor.abi.as_str());
+                    }
+                }
 
                 for event in &events {
-                    {
+                    // Lagging handlers handle their events once they catch up
+                    if !lagging_abis.contains(&event.get_abi()) {
                         if let Some(handler) = pure_handlers.get(event.get_abi()) {
-                            let handler_context = PureHandlerContext::new(
-                                event,
-                                &txn_client,
-                                repo_client_for_mcs,
-                                deferred_mutations_for_mcs,
-                            );
+                            let handler_context = PureHandlerContext::for_event(event, &txn_client)
+                                .with_state_cache(&state_cache);
 
                             handler.handle_event(handler_context).await;
                         }
                     }
 
+                    transaction_handler::handle_transaction(
+                        event,
+                        transaction_handlers,
+                        &events_by_transaction_hash,
+                        &mut handled_transactions,
+                        &txn_client,
+                        &state_cache,
+                    )
+                    .await;
+
                     {
                         if event.block_number >= contract_address.next_block_number_for_side_effects
                         {
                             if let Some(handler) = side_effect_handlers.get(event.get_abi()) {
                                 let handler_context =
-                                    SideEffectHandlerContext::new(event, &txn_client, shared_state);
+                                    SideEffectHandlerContext::new(event, &txn_client, shared_state)
+                                        .with_state_cache(&state_cache);
 
                                 handler.handle_event(handler_context).await;
                             }
@@ -71,6 +155,8 @@ pub async fn run<'a, S: Send + Sync + Clone + Debug>(
                     }
                 }
 
+                state_cache.flush(&txn_client).await;
+
                 if let Some(last_event) = events.last() {
                     let next_block_number_to_handle_from = last_event.block_number as u64 + 1;
 
@@ -96,7 +182,57 @@ pub async fn run<'a, S: Send + Sync + Clone + Debug>(
                 }
 
                 ChaindexingRepo::commit_txns(txn_client).await;
+
+                if let Some(last_event) = events.last() {
+                    invariant_checker
+                        .run(
+                            &contract_address,
+                            (from_block_number, last_event.block_number as u64),
+                            &client,
+                        )
+                        .await;
+                }
             }
         }
     }
 }
+
+/// Handles events for a handler lagging behind its contract address, up to
+/// `until_block_number`, returning whether it caught up
+async fn handle_lagging_events<'a>(
+    pure_handlers: &HashMap<EventAbi, Arc<dyn PureHandler>>,
+    handler_cursor: &HandlerCursor,
+    (until_block_number, events): (i64, &[Event]),
+    next_block_number_to_handle_from: i64,
+    txn_client: &ChaindexingRepoTxnClient<'a>,
+    state_cache: &StateCache,
+) -> bool {
+    if let Some(handler) = pure_handlers.get(handler_cursor.abi.as_str()) {
+        for event in events {
+            let handler_context =
+                PureHandlerContext::for_event(event, txn_client).with_state_cache(state_cache);
+
+            handler.handle_event(handler_context).await;
+        }
+    }
+
+    let handler_cursor = HandlerCursor {
+        next_block_number_to_handle_from: until_block_number,
+        ..handler_cursor.clone()
+    };
+
+    let has_caught_up = handler_cursor.has_caught_up(next_block_number_to_handle_from);
+
+    if has_caught_up {
+        ChaindexingRepo::delete_handler_cursor(txn_client, &handler_cursor).await;
+    } else {
+        ChaindexingRepo::update_handler_cursor_next_block_number_to_handle_from(
+            txn_client,
+            &handler_cursor,
+            handler_cursor.next_block_number_to_handle_from as u64,
+        )
+        .await;
+    }
+
+    has_caught_up
+}
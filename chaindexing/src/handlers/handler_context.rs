@@ -1,6 +1,13 @@
+use crate::states::StateCache;
 use crate::{ChaindexingRepoTxnClient, Event};
 
 pub trait HandlerContext<'a>: Send + Sync {
     fn get_event(&self) -> &Event;
     fn get_client(&self) -> &ChaindexingRepoTxnClient<'a>;
+
+    /// Cache of the batch's state views, whose pending views reads must flush
+    #[doc(hidden)]
+    fn get_state_cache(&self) -> Option<&StateCache> {
+        None
+    }
 }
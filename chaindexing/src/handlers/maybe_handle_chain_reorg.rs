@@ -1,11 +1,14 @@
 use crate::chain_reorg::{ReorgedBlock, ReorgedBlocks};
-use crate::{states, ChaindexingRepo, LoadsDataWithRawQuery};
+use crate::{reindexing, states, ChaindexingRepo, LoadsDataWithRawQuery};
 use crate::{ChaindexingRepoClient, ExecutesWithRawQuery, HasRawQueryClient};
 
-pub async fn run(repo_client: &mut ChaindexingRepoClient, table_names: &Vec<String>) {
+pub async fn run(repo_client: &mut ChaindexingRepoClient, table_names: &[String]) {
     let reorged_blocks = ChaindexingRepo::load_unhandled_reorged_blocks(repo_client).await;
 
     if !reorged_blocks.is_empty() {
+        let is_reindexing = reindexing::is_in_progress(repo_client).await;
+        let live_schema = reindexing::load_live_schema(repo_client).await;
+
         let repo_txn_client = ChaindexingRepo::get_txn_client(repo_client).await;
 
         let reorged_blocks = ReorgedBlocks::only_earliest_per_chain(&reorged_blocks);
@@ -16,7 +19,22 @@ pub async fn run(repo_client: &mut ChaindexingRepoClient, table_names: &Vec<Stri
             ..
         } in &reorged_blocks
         {
-            states::backtrack_states(table_names, *chain_id, *block_number, &repo_txn_client).await;
+            states::backtrack_states(table_names, *chain_id, *block_number, &[], &repo_txn_client)
+                .await;
+
+            if is_reindexing {
+                reindexing::set_local_shadow_search_path(&repo_txn_client, &live_schema).await;
+                states::backtrack_states(
+                    table_names,
+                    *chain_id,
+                    *block_number,
+                    &[],
+                    &repo_txn_client,
+                )
+                .await;
+                reindexing::reset_local_search_path(&repo_txn_client, &live_schema).await;
+            }
+
             ChaindexingRepo::update_next_block_numbers_to_handle_from(
                 &repo_txn_client,
                 *chain_id as u64,
@@ -1,9 +1,11 @@
+use std::marker::PhantomData;
 use std::sync::Arc;
 
 use tokio::sync::Mutex;
 
 use crate::deferred_futures::DeferredFutures;
 use crate::events::Event;
+use crate::states::StateCache;
 use crate::{ChaindexingRepoClient, ChaindexingRepoTxnClient, EventParam};
 
 use super::handler_context::HandlerContext;
@@ -17,6 +19,14 @@ pub trait PureHandler: Send + Sync {
     /// `PoolCreated(address indexed token0, address indexed token1, uint24 indexed fee, int24 tickSpacing, address pool)`.
     /// The chain explorer's event section can also be used to infer this.
     fn abi(&self) -> &'static str;
+
+    /// Version of the handler's logic. Bumping it rebuilds, on the next boot,
+    /// the states of the handler's contract by replaying its already ingested
+    /// events. Other contracts are left untouched.
+    fn version(&self) -> u64 {
+        1
+    }
+
     async fn handle_event<'a, 'b>(&self, context: PureHandlerContext<'a, 'b>);
 }
 
@@ -25,25 +35,41 @@ pub trait PureHandler: Send + Sync {
 pub struct PureHandlerContext<'a, 'b> {
     pub event: Event,
     pub(crate) repo_client: &'a ChaindexingRepoTxnClient<'a>,
-    pub(crate) repo_client_for_mcs: Arc<Mutex<ChaindexingRepoClient>>,
-    pub(crate) deferred_mutations_for_mcs: DeferredFutures<'b>,
+    pub(crate) state_cache: Option<StateCache>,
+    // Keeps handlers' signatures from when MultiChainState mutations got deferred in memory
+    _deferred_mutations: PhantomData<&'b ()>,
 }
 
 impl<'a, 'b> PureHandlerContext<'a, 'b> {
-    pub fn new(
-        event: &Event,
-        repo_client: &'a ChaindexingRepoTxnClient<'a>,
-        repo_client_for_mcs: &Arc<Mutex<ChaindexingRepoClient>>,
-        deferred_mutations_for_mcs: &DeferredFutures<'b>,
-    ) -> Self {
+    pub fn for_event(event: &Event, repo_client: &'a ChaindexingRepoTxnClient<'a>) -> Self {
         Self {
             event: event.clone(),
             repo_client,
-            repo_client_for_mcs: repo_client_for_mcs.clone(),
-            deferred_mutations_for_mcs: deferred_mutations_for_mcs.clone(),
+            state_cache: None,
+            _deferred_mutations: PhantomData,
         }
     }
 
+    /// MultiChainState mutations now get queued within the handler's transaction,
+    /// so neither the separate client nor the deferred futures get used
+    #[deprecated(
+        note = "use `for_event` instead, MultiChainState mutations no longer get deferred"
+    )]
+    pub fn new(
+        event: &Event,
+        repo_client: &'a ChaindexingRepoTxnClient<'a>,
+        _repo_client_for_mcs: &Arc<Mutex<ChaindexingRepoClient>>,
+        _deferred_mutations_for_mcs: &DeferredFutures<'b>,
+    ) -> Self {
+        Self::for_event(event, repo_client)
+    }
+
+    /// Serves and coalesces the handler's state views through the batch's cache
+    pub fn with_state_cache(mut self, state_cache: &StateCache) -> Self {
+        self.state_cache = Some(state_cache.clone());
+        self
+    }
+
     pub fn get_event_params(&self) -> EventParam {
         self.event.get_params()
     }
@@ -57,4 +83,8 @@ impl<'a, 'b> HandlerContext<'a> for PureHandlerContext<'a, 'b> {
     fn get_client(&self) -> &ChaindexingRepoTxnClient<'a> {
         self.repo_client
     }
+
+    fn get_state_cache(&self) -> Option<&StateCache> {
+        self.state_cache.as_ref()
+    }
 }
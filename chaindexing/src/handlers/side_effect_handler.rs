@@ -4,6 +4,7 @@ use std::sync::Arc;
 use tokio::sync::Mutex;
 
 use crate::events::Event;
+use crate::states::StateCache;
 use crate::{ChaindexingRepoTxnClient, EventParam};
 
 use super::handler_context::HandlerContext;
@@ -32,6 +33,7 @@ pub struct SideEffectHandlerContext<'a, SharedState: Sync + Send + Clone> {
     pub event: Event,
     pub(crate) repo_client: &'a ChaindexingRepoTxnClient<'a>,
     shared_state: Option<Arc<Mutex<SharedState>>>,
+    state_cache: Option<StateCache>,
 }
 
 impl<'a, SharedState: Sync + Send + Clone> SideEffectHandlerContext<'a, SharedState> {
@@ -44,9 +46,16 @@ impl<'a, SharedState: Sync + Send + Clone> SideEffectHandlerContext<'a, SharedSt
             event: event.clone(),
             repo_client,
             shared_state: shared_state.clone(),
+            state_cache: None,
         }
     }
 
+    /// Reads the states written earlier in the batch through its cache
+    pub fn with_state_cache(mut self, state_cache: &StateCache) -> Self {
+        self.state_cache = Some(state_cache.clone());
+        self
+    }
+
     pub async fn get_shared_state(&self) -> SharedState {
         let shared_state = self.shared_state.clone().unwrap();
         let shared_state = shared_state.lock().await;
@@ -68,4 +77,8 @@ impl<'a, SharedState: Sync + Send + Clone> HandlerContext<'a>
     fn get_client(&self) -> &ChaindexingRepoTxnClient<'a> {
         self.repo_client
     }
+
+    fn get_state_cache(&self) -> Option<&StateCache> {
+        self.state_cache.as_ref()
+    }
 }
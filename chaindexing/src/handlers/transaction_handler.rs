@@ -0,0 +1,159 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::events::Event;
+use crate::states::StateCache;
+use crate::LoadsDataWithRawQuery;
+use crate::{ChaindexingRepo, ChaindexingRepoClient, ChaindexingRepoTxnClient, EventAbi};
+
+use super::handler_context::HandlerContext;
+use super::pure_handler::PureHandlerContext;
+
+/// Transaction handlers are pure handlers that get all the events of a transaction
+/// together, for protocols whose accounting spans several events. For example,
+/// Uniswap emits `Transfer`, `Sync` and `Swap` in a single swap transaction.
+/// They get called once per transaction for each of the contract's addresses.
+#[crate::augmenting_std::async_trait]
+pub trait TransactionHandler: Send + Sync {
+    /// The human-readable ABIs of the events making up the transaction.
+    /// Events emitted by other contracts are included, as long as they get
+    /// ingested for their own contracts.
+    fn abis(&self) -> Vec<EventAbi>;
+
+    /// Tells the handler's version apart from other transaction handlers of
+    /// the contract expecting the same ABIs. Defaults to its type's name.
+    fn name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    /// Version of the handler's logic. Bumping it rebuilds, on the next boot,
+    /// the states of the handler's contract by replaying its already ingested
+    /// events. Other contracts are left untouched.
+    fn version(&self) -> u64 {
+        1
+    }
+
+    async fn handle_transaction<'a, 'b>(&self, context: TransactionHandlerContext<'a, 'b>);
+}
+
+/// Transaction's context in a transaction handler. It derefs to the context of
+/// the first event emitted by the contract address being handled, which states
+/// get indexed against.
+#[derive(Clone)]
+pub struct TransactionHandlerContext<'a, 'b> {
+    /// All events of the transaction matching the handler's ABIs, in log order
+    pub events: Vec<Event>,
+    event_context: PureHandlerContext<'a, 'b>,
+}
+
+impl<'a, 'b> TransactionHandlerContext<'a, 'b> {
+    pub fn new(
+        event: &Event,
+        events: &[Event],
+        repo_client: &'a ChaindexingRepoTxnClient<'a>,
+    ) -> Self {
+        Self {
+            events: events.to_vec(),
+            event_context: PureHandlerContext::for_event(event, repo_client),
+        }
+    }
+
+    /// Serves and coalesces the handler's state views through the batch's cache
+    pub fn with_state_cache(mut self, state_cache: &StateCache) -> Self {
+        self.event_context = self.event_context.with_state_cache(state_cache);
+        self
+    }
+
+    pub fn get_transaction_hash(&self) -> &str {
+        &self.event_context.event.transaction_hash
+    }
+
+    /// Events of the transaction with the given ABI, in log order
+    pub fn get_events(&self, abi: &str) -> Vec<&Event> {
+        self.events.iter().filter(|e| e.get_abi() == abi).collect()
+    }
+}
+
+impl<'a, 'b> Deref for TransactionHandlerContext<'a, 'b> {
+    type Target = PureHandlerContext<'a, 'b>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.event_context
+    }
+}
+
+impl<'a, 'b> HandlerContext<'a> for TransactionHandlerContext<'a, 'b> {
+    fn get_event(&self) -> &Event {
+        self.event_context.get_event()
+    }
+
+    fn get_client(&self) -> &ChaindexingRepoTxnClient<'a> {
+        self.event_context.get_client()
+    }
+
+    fn get_state_cache(&self) -> Option<&StateCache> {
+        self.event_context.get_state_cache()
+    }
+}
+
+/// Loads the events of the transactions transaction handlers get called for, by hash
+pub async fn load_transaction_events(
+    client: &ChaindexingRepoClient,
+    chain_id: u64,
+    events: &[Event],
+    transaction_handlers: &[Arc<dyn TransactionHandler>],
+) -> HashMap<String, Vec<Event>> {
+    let abis: HashSet<_> = transaction_handlers.iter().flat_map(|h| h.abis()).collect();
+
+    let mut transaction_hashes: Vec<_> = events
+        .iter()
+        .filter(|e| abis.contains(e.get_abi()))
+        .map(|e| e.transaction_hash.clone())
+        .collect();
+    transaction_hashes.sort_unstable();
+    transaction_hashes.dedup();
+
+    ChaindexingRepo::load_events_by_transaction_hashes(client, chain_id, &transaction_hashes)
+        .await
+        .into_iter()
+        .fold(HashMap::new(), |mut events_by_transaction_hash, event| {
+            events_by_transaction_hash
+                .entry(event.transaction_hash.clone())
+                .or_insert_with(Vec::new)
+                .push(event);
+
+            events_by_transaction_hash
+        })
+}
+
+/// Calls the transaction handlers expecting the event, once per transaction
+pub async fn handle_transaction<'a>(
+    event: &Event,
+    transaction_handlers: &[Arc<dyn TransactionHandler>],
+    events_by_transaction_hash: &HashMap<String, Vec<Event>>,
+    handled_transactions: &mut HashSet<(usize, String)>,
+    txn_client: &'a ChaindexingRepoTxnClient<'a>,
+    state_cache: &StateCache,
+) {
+    for (index, handler) in transaction_handlers.iter().enumerate() {
+        let abis = handler.abis();
+
+        if abis.iter().any(|abi| *abi == event.get_abi())
+            && handled_transactions.insert((index, event.transaction_hash.clone()))
+        {
+            let events: Vec<_> = events_by_transaction_hash
+                .get(&event.transaction_hash)
+                .into_iter()
+                .flatten()
+                .filter(|e| abis.iter().any(|abi| *abi == e.get_abi()))
+                .cloned()
+                .collect();
+
+            let handler_context = TransactionHandlerContext::new(event, &events, txn_client)
+                .with_state_cache(state_cache);
+
+            handler.handle_transaction(handler_context).await;
+        }
+    }
+}
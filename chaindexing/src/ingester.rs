@@ -1,3 +1,4 @@
+mod backfill_events;
 mod error;
 mod filters;
 mod ingest_events;
@@ -28,7 +29,7 @@ use crate::Config;
 use crate::Contract;
 use crate::ContractAddress;
 use crate::{ChaindexingRepo, ChaindexingRepoClient, ChaindexingRepoConn};
-use crate::{ExecutesWithRawQuery, HasRawQueryClient, Repo};
+use crate::{ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery, Repo};
 
 pub async fn start<S: Sync + Send + Clone + 'static>(config: &Config<S>) -> NodeTask {
     let node_task = NodeTask::new();
@@ -90,6 +91,8 @@ pub async fn ingest_for_chain<'a, S: Send + Sync + Clone>(
     last_pruned_at_per_chain_id: &mut HashMap<u64, u64>,
 ) -> Result<(), IngesterError> {
     let current_block_number = provider::fetch_current_block_number(&provider).await;
+    let handler_cursors =
+        ChaindexingRepo::load_handler_cursors(&*repo_client.lock().await, *chain_id as u64).await;
     let mut contract_addresses_stream =
         ContractAddressesStream::new(repo_client, *chain_id as i64).with_chunk_size(5);
 
@@ -111,6 +114,16 @@ pub async fn ingest_for_chain<'a, S: Send + Sync + Clone>(
         )
         .await?;
 
+        backfill_events::run(
+            &mut conn,
+            &handler_cursors,
+            &contract_addresses,
+            &provider,
+            chain_id,
+            config,
+        )
+        .await?;
+
         maybe_handle_chain_reorg::run(
             &mut conn,
             contract_addresses,
@@ -0,0 +1,98 @@
+use std::cmp::min;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use futures_util::FutureExt;
+
+use super::filters::Filter;
+use super::provider::{self, Provider};
+use super::IngesterError;
+
+use crate::contracts::ContractEvent;
+use crate::handler_cursors::HandlerCursor;
+use crate::Config;
+use crate::{events, ChainId};
+use crate::{ChaindexingRepo, ChaindexingRepoConn, ContractAddress, Repo};
+
+/// Ingests events of newly added handlers' ABIs, which contract
+/// addresses had not ingested before the handlers got added
+pub async fn run<'a, S: Send + Sync + Clone>(
+    conn: &mut ChaindexingRepoConn<'a>,
+    handler_cursors: &[HandlerCursor],
+    contract_addresses: &[ContractAddress],
+    provider: &Arc<impl Provider>,
+    chain_id: &ChainId,
+    Config {
+        contracts,
+        blocks_per_batch,
+        ..
+    }: &Config<S>,
+) -> Result<(), IngesterError> {
+    for contract_address in contract_addresses {
+        for handler_cursor in handler_cursors.iter().filter(|hc| {
+            hc.contract_address == contract_address.address && hc.is_backfilling_events()
+        }) {
+            let from_block_number = handler_cursor.next_block_number_to_ingest_from as u64;
+            let to_block_number = min(
+                from_block_number + blocks_per_batch,
+                handler_cursor.ingest_until_block_number as u64 - 1,
+            );
+
+            let filter = Filter::new(
+                contract_address,
+                &[ContractEvent::new(&handler_cursor.abi).value.signature()],
+                from_block_number,
+                to_block_number,
+            );
+
+            let logs = provider::fetch_logs(provider, &[filter]).await;
+            let blocks_by_number = provider::fetch_blocks_by_number(provider, &logs).await;
+            let events = events::get(
+                &logs,
+                contracts,
+                std::slice::from_ref(contract_address),
+                chain_id,
+                &blocks_by_number,
+            );
+
+            // Events could have been ingested while confirming recent blocks
+            let already_ingested_events: HashSet<_> = ChaindexingRepo::get_events(
+                conn,
+                contract_address.address.to_owned(),
+                from_block_number,
+                to_block_number,
+            )
+            .await
+            .into_iter()
+            .map(|e| (e.transaction_hash, e.log_index))
+            .collect();
+            let events: Vec<_> = events
+                .into_iter()
+                .filter(|e| {
+                    !already_ingested_events.contains(&(e.transaction_hash.clone(), e.log_index))
+                })
+                .collect();
+
+            let handler_cursor = handler_cursor.clone();
+
+            ChaindexingRepo::run_in_transaction(conn, move |conn| {
+                async move {
+                    ChaindexingRepo::create_events(conn, &events).await;
+
+                    ChaindexingRepo::update_handler_cursor_next_block_number_to_ingest_from(
+                        conn,
+                        &handler_cursor,
+                        to_block_number as i64 + 1,
+                    )
+                    .await;
+
+                    Ok(())
+                }
+                .boxed()
+            })
+            .await?;
+        }
+    }
+
+    Ok(())
+}
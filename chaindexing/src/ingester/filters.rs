@@ -79,10 +79,8 @@ impl Filter {
         execution: &Execution,
     ) -> Option<Filter> {
         let ContractAddress {
-            id: contract_address_id,
             next_block_number_to_ingest_from,
             start_block_number,
-            address,
             ..
         } = contract_address;
 
@@ -114,7 +112,22 @@ impl Filter {
                 }
             }
         }
-        .map(|(from_block_number, to_block_number)| Filter {
+        .map(|(from_block_number, to_block_number)| {
+            Filter::new(contract_address, topics, from_block_number, to_block_number)
+        })
+    }
+
+    pub fn new(
+        ContractAddress {
+            id: contract_address_id,
+            address,
+            ..
+        }: &ContractAddress,
+        topics: &[ContractEventTopic],
+        from_block_number: u64,
+        to_block_number: u64,
+    ) -> Filter {
+        Filter {
             contract_address_id: *contract_address_id,
             address: address.to_string(),
             value: EthersFilter::new()
@@ -122,6 +135,6 @@ impl Filter {
                 .topic0(topics.to_vec())
                 .from_block(from_block_number)
                 .to_block(to_block_number),
-        })
+        }
     }
 }
@@ -0,0 +1,311 @@
+//! # Invariants
+//! Checks over state tables, such as balances never going negative, that run
+//! after each batch of handled events commits. A violation gets logged by
+//! default, or recorded in `chaindexing_invariant_violations` along with the
+//! offending block range. Halting ones also stop handling the contract address
+//! until its violations get resolved with [`resume`](crate::invariants::resume).
+//!
+//! ## Example
+//!
+//! ```rust,ignore
+//! use chaindexing::invariants::{Invariant, InvariantAction};
+//!
+//! Contract::new("ERC20")
+//!     .add_invariant(Invariant::sql(
+//!         "non_negative_balances",
+//!         "SELECT * FROM token_balances WHERE amount < 0",
+//!     ))
+//!     .add_invariant(
+//!         Invariant::check("supply_matches_balances", |context| {
+//!             Box::pin(async move {
+//!                 let filters = Filters::all()
+//!                     .for_contract_address(&context.chain_id, context.contract_address);
+//!                 let supply: U256 = context
+//!                     .query_client
+//!                     .aggregate("token_balances", &Aggregate::Sum("amount".to_string()), &filters)
+//!                     .await;
+//!
+//!                 if supply > MAX_SUPPLY {
+//!                     return Err(format!("supply of {supply} exceeds the maximum"));
+//!                 }
+//!
+//!                 Ok(())
+//!             })
+//!         })
+//!         .on_violation(InvariantAction::Halt),
+//!     );
+//! ```
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use futures_core::future::BoxFuture;
+use futures_util::FutureExt;
+use serde::Deserialize;
+
+use crate::states::StateQueryClient;
+use crate::{ChainId, ChaindexingRepo, ChaindexingRepoClient, Config, ContractAddress};
+use crate::{ExecutesWithRawQuery, LoadsDataWithRawQuery, SqlParams};
+
+/// Rows of a violated SQL invariant kept as its details
+const MAX_VIOLATING_ROWS: i64 = 10;
+
+type CheckFn =
+    dyn for<'a> Fn(&'a InvariantContext<'a>) -> BoxFuture<'a, Result<(), String>> + Send + Sync;
+
+/// What follows a violation of an invariant
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum InvariantAction {
+    /// Logs the violation
+    #[default]
+    Log,
+    /// Logs and records the violation with its block range
+    Record,
+    /// Logs and records the violation, then stops handling the contract
+    /// address's events until [`resume`] gets called for it
+    Halt,
+}
+
+#[derive(Clone)]
+enum InvariantCheck {
+    Sql(String),
+    Closure(Arc<CheckFn>),
+}
+
+/// Check over state tables that must hold after every handled batch
+#[derive(Clone)]
+pub struct Invariant {
+    pub(crate) name: String,
+    check: InvariantCheck,
+    pub(crate) action: InvariantAction,
+}
+
+impl Invariant {
+    /// Invariant violated whenever the query selects any row, such as
+    /// `SELECT * FROM token_balances WHERE amount < 0`. Its session is read-only.
+    /// Queries failing, such as invalid ones, count as violations too.
+    pub fn sql(name: &str, query: &str) -> Self {
+        Self::new(name, InvariantCheck::Sql(query.to_string()))
+    }
+
+    /// Invariant violated whenever the check returns an error, describing the violation
+    pub fn check<F>(name: &str, check: F) -> Self
+    where
+        F: for<'a> Fn(&'a InvariantContext<'a>) -> BoxFuture<'a, Result<(), String>>
+            + Send
+            + Sync
+            + 'static,
+    {
+        Self::new(name, InvariantCheck::Closure(Arc::new(check)))
+    }
+
+    fn new(name: &str, check: InvariantCheck) -> Self {
+        Self {
+            name: name.to_string(),
+            check,
+            action: InvariantAction::default(),
+        }
+    }
+
+    /// Sets what follows a violation. Defaults to `InvariantAction::Log`.
+    pub fn on_violation(mut self, action: InvariantAction) -> Self {
+        self.action = action;
+
+        self
+    }
+
+    async fn run(&self, context: &InvariantContext<'_>) -> Result<(), String> {
+        match &self.check {
+            InvariantCheck::Sql(query) => run_sql(query, context.query_client).await,
+            InvariantCheck::Closure(check) => check(context).await,
+        }
+    }
+}
+
+impl Debug for Invariant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Invariant")
+            .field("name", &self.name)
+            .field("action", &self.action)
+            .finish()
+    }
+}
+
+/// Batch an invariant gets checked after
+pub struct InvariantContext<'a> {
+    pub chain_id: ChainId,
+    pub contract_address: &'a str,
+    pub from_block_number: u64,
+    pub to_block_number: u64,
+    /// Reads the states committed by the batch
+    pub query_client: &'a StateQueryClient,
+}
+
+#[derive(Deserialize)]
+struct ViolatingRow {
+    row: serde_json::Value,
+}
+
+async fn run_sql(query: &str, query_client: &StateQueryClient) -> Result<(), String> {
+    let query = format!(
+        "SELECT to_jsonb(chaindexing_violation) AS row
+        FROM ({query}) chaindexing_violation LIMIT {MAX_VIOLATING_ROWS}"
+    );
+    let violating_rows =
+        ChaindexingRepo::load_data_list::<ViolatingRow>(query_client.get_client(), &query);
+
+    // Invalid queries can't tell whether the invariant holds
+    let violating_rows = match AssertUnwindSafe(violating_rows).catch_unwind().await {
+        Ok(violating_rows) => violating_rows,
+        Err(panic) => {
+            let error = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|error| error.to_string()))
+                .unwrap_or_else(|| "unknown error".to_string());
+
+            return Err(format!("Invariant query failed: {error}"));
+        }
+    };
+
+    if violating_rows.is_empty() {
+        return Ok(());
+    }
+
+    let violating_rows: Vec<_> = violating_rows.into_iter().map(|row| row.row).collect();
+
+    Err(serde_json::to_string(&violating_rows).unwrap())
+}
+
+/// Checks the invariants of each contract, along with the config's, after its batches
+#[doc(hidden)]
+pub struct InvariantChecker {
+    invariants_by_contract_name: HashMap<String, Vec<Invariant>>,
+    query_client: Option<StateQueryClient>,
+}
+
+impl InvariantChecker {
+    pub async fn new<S: Send + Sync + Clone>(config: &Config<S>) -> Self {
+        let invariants_by_contract_name: HashMap<_, _> = config
+            .contracts
+            .iter()
+            .map(|contract| {
+                let invariants: Vec<_> =
+                    contract.invariants.iter().chain(&config.invariants).cloned().collect();
+
+                (contract.name.clone(), invariants)
+            })
+            .filter(|(_contract_name, invariants)| !invariants.is_empty())
+            .collect();
+
+        // Only connects when there is something to check
+        let query_client = if invariants_by_contract_name.is_empty() {
+            None
+        } else {
+            Some(StateQueryClient::new(&config.repo).await)
+        };
+
+        Self {
+            invariants_by_contract_name,
+            query_client,
+        }
+    }
+
+    /// Runs the invariants of the contract address after its batch committed
+    pub async fn run(
+        &self,
+        contract_address: &ContractAddress,
+        (from_block_number, to_block_number): (u64, u64),
+        client: &ChaindexingRepoClient,
+    ) {
+        let (Some(invariants), Some(query_client)) = (
+            self.invariants_by_contract_name.get(&contract_address.contract_name),
+            &self.query_client,
+        ) else {
+            return;
+        };
+
+        let context = InvariantContext {
+            chain_id: contract_address.get_chain_id(),
+            contract_address: &contract_address.address,
+            from_block_number,
+            to_block_number,
+            query_client,
+        };
+
+        for invariant in invariants {
+            if let Err(details) = invariant.run(&context).await {
+                eprintln!(
+                    "Invariant Violation: {} for {} on chain {} in blocks {from_block_number} to {to_block_number}: {details}",
+                    invariant.name, context.contract_address, contract_address.chain_id
+                );
+
+                if invariant.action != InvariantAction::Log {
+                    record_violation(invariant, &context, &details, client).await;
+                }
+            }
+        }
+    }
+}
+
+async fn record_violation(
+    invariant: &Invariant,
+    context: &InvariantContext<'_>,
+    details: &str,
+    client: &ChaindexingRepoClient,
+) {
+    let mut params = SqlParams::new();
+    let query = format!(
+        "INSERT INTO chaindexing_invariant_violations
+        (chain_id, contract_address, invariant_name, from_block_number, to_block_number, details, halts)
+        VALUES ({}, {}, {}, {}, {}, {}, {})",
+        params.add(context.chain_id as i64),
+        params.add(context.contract_address),
+        params.add(invariant.name.as_str()),
+        params.add(context.from_block_number as i64),
+        params.add(context.to_block_number as i64),
+        params.add(details),
+        params.add(invariant.action == InvariantAction::Halt),
+    );
+
+    ChaindexingRepo::execute_with_params(client, &query, params.get_values()).await;
+}
+
+#[derive(Deserialize)]
+struct HaltedContractAddress {
+    contract_address: String,
+}
+
+/// Contract addresses of the chain with unresolved halting violations
+#[doc(hidden)]
+pub async fn load_halted_contract_addresses(
+    client: &ChaindexingRepoClient,
+    chain_id: u64,
+) -> Vec<String> {
+    ChaindexingRepo::load_data_list_with_params::<HaltedContractAddress>(
+        client,
+        "SELECT DISTINCT contract_address FROM chaindexing_invariant_violations
+        WHERE chain_id = $1 AND halts AND resolved_at IS NULL",
+        &[(chain_id as i64).into()],
+    )
+    .await
+    .into_iter()
+    .map(|halted| halted.contract_address)
+    .collect()
+}
+
+/// Resolves the violations of the contract address, resuming the handling of
+/// its events if they halted it
+pub async fn resume(client: &ChaindexingRepoClient, chain_id: &ChainId, contract_address: &str) {
+    ChaindexingRepo::execute_with_params(
+        client,
+        "UPDATE chaindexing_invariant_violations SET resolved_at = NOW()
+        WHERE chain_id = $1 AND contract_address = $2 AND resolved_at IS NULL",
+        &[
+            (*chain_id as i64).into(),
+            contract_address.to_lowercase().into(),
+        ],
+    )
+    .await;
+}
@@ -15,6 +15,7 @@ mod chains;
 mod config;
 mod contracts;
 mod diesel;
+mod handler_cursors;
 mod handlers;
 mod nodes;
 mod pruning;
@@ -30,17 +31,28 @@ pub use contracts::{Contract, ContractAddress, EventAbi};
 pub use events::{Event, EventParam};
 pub use handlers::{
     PureHandler as EventHandler, PureHandlerContext as EventContext, SideEffectHandler,
-    SideEffectHandlerContext as SideEffectContext,
+    SideEffectHandlerContext as SideEffectContext, TransactionHandler,
+    TransactionHandlerContext as TransactionContext,
 };
 pub use nodes::NodeHeartbeat as Heartbeat;
+pub use rewinding::Rewind;
 
-pub use chaindexing_macros::state_migrations;
+pub use chaindexing_macros::{state_migrations, ChainState, ContractState, MultiChainState};
 pub use ethers::types::{I256, U256};
 use tokio::sync::Mutex;
 
 /// Houses traits and structs for implementing states that can be indexed.
 pub mod states;
 
+/// Checks over states that must hold after every handled batch
+pub mod invariants;
+
+#[cfg(feature = "graphql")]
+pub mod graphql;
+
+#[cfg(feature = "rest")]
+pub mod rest;
+
 /// Hexadecimal representation of addresses (such as contract addresses)
 pub type Address = ethers::types::Address;
 /// Represents bytes
@@ -57,8 +69,14 @@ pub mod events;
 #[doc(hidden)]
 pub mod ingester;
 #[doc(hidden)]
+pub mod reindexing;
+#[doc(hidden)]
+pub mod rewinding;
+#[doc(hidden)]
 pub use contracts::{ContractEvent, UnsavedContractAddress};
 #[doc(hidden)]
+pub use handlers::{handle_events, handle_transaction, load_transaction_events};
+#[doc(hidden)]
 pub use ingester::Provider as IngesterProvider;
 #[doc(hidden)]
 pub use repos::*;
@@ -98,6 +116,7 @@ use tokio::time;
 
 use config::ConfigError;
 use nodes::NodeTasks;
+use rewinding::RewindError;
 
 use crate::nodes::{NodeTask, NodeTasksRunner};
 
@@ -106,6 +125,7 @@ pub(crate) type ChaindexingRepoClientMutex = Arc<Mutex<PostgresRepoClient>>;
 /// Errors from mis-configurations, database connections, internal errors, etc.
 pub enum ChaindexingError {
     Config(ConfigError),
+    Rewind(RewindError),
 }
 
 impl From<ConfigError> for ChaindexingError {
@@ -114,12 +134,21 @@ impl From<ConfigError> for ChaindexingError {
     }
 }
 
+impl From<RewindError> for ChaindexingError {
+    fn from(value: RewindError) -> Self {
+        ChaindexingError::Rewind(value)
+    }
+}
+
 impl Debug for ChaindexingError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             ChaindexingError::Config(config_error) => {
                 write!(f, "Config Error: {config_error:?}")
             }
+            ChaindexingError::Rewind(rewind_error) => {
+                write!(f, "Rewind Error: {rewind_error:?}")
+            }
         }
     }
 }
@@ -137,6 +166,12 @@ pub async fn index_states<S: Send + Sync + Clone + Debug + 'static>(
 
     booting::setup(config, &client).await?;
 
+    #[cfg(feature = "rest")]
+    if let Some(rest_server_addr) = config.rest_server_addr.clone() {
+        let config = config.clone();
+        tokio::spawn(async move { rest::serve(&config, rest_server_addr).await });
+    }
+
     let config = config.clone();
     tokio::spawn(async move {
         let mut interval =
@@ -201,6 +236,35 @@ pub async fn include_contract<'a, C: handlers::HandlerContext<'a>>(
     ChaindexingRepo::create_contract_address(event_context.get_client(), &contract_address).await;
 }
 
+/// Rewinds indexed states to a block, so events get handled again from there.
+/// Should run before `index_states` to not race with running handlers.
+/// Nothing gets rewound if any of its contract addresses is not indexed.
+///
+/// # Example
+///
+/// ```ignore
+/// // Replays events of a single buggy contract from block 17_800_000
+/// chaindexing::rewind(
+///     &config,
+///     &Rewind::new(&ChainId::Mainnet, 17_800_000).for_contract_address(&contract_address),
+/// )
+/// .await
+/// .unwrap();
+/// chaindexing::index_states(&config).await.unwrap();
+/// ```
+pub async fn rewind<S: Send + Sync + Clone>(
+    config: &Config<S>,
+    rewind: &Rewind,
+) -> Result<(), ChaindexingError> {
+    let mut client = handlers::get_live_client(config).await;
+
+    let txn_client = ChaindexingRepo::get_txn_client(&mut client).await;
+    rewinding::run(&txn_client, rewind, &config.contracts).await?;
+    ChaindexingRepo::commit_txns(txn_client).await;
+
+    Ok(())
+}
+
 async fn wait_for_non_leader_nodes_to_abort(node_election_rate_ms: u64) {
     time::sleep(Duration::from_millis(node_election_rate_ms)).await;
 }
@@ -233,11 +297,15 @@ pub mod prelude {
     pub use crate::events::{Event, EventParam};
     pub use crate::handlers::{
         PureHandler as EventHandler, PureHandlerContext as EventContext, SideEffectHandler,
-        SideEffectHandlerContext as SideEffectContext,
+        SideEffectHandlerContext as SideEffectContext, TransactionHandler,
+        TransactionHandlerContext as TransactionContext,
     };
+    pub use crate::invariants::{Invariant, InvariantAction};
     pub use crate::nodes::NodeHeartbeat as Heartbeat;
+    pub use crate::rewinding::Rewind;
     pub use crate::states::{
-        ChainState, ContractState, Filters, MultiChainState, StateMigrations, Updates,
+        Aggregate, AtBlock, ChainState, ContractState, Filters, MultiChainState, Order, Rollup,
+        RollupEntry, StateMigrations, StateQueryClient, Updates,
     };
     pub use crate::Address;
     pub use chaindexing_macros::state_migrations;
@@ -0,0 +1,397 @@
+//! Blue-green reindexing: states get rebuilt in shadow tables, next to the live ones,
+//! by replaying already ingested events. The shadow tables replace the live ones in a
+//! single transaction once they reach the live cursors, so readers see no downtime.
+
+use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::sync::RwLock;
+
+use crate::handlers::{self, PureHandler, PureHandlerContext, TransactionHandler};
+use crate::states::{self, StateCache, StateMigrations};
+use crate::streams::ContractAddressesStream;
+use crate::{
+    ChaindexingRepo, ChaindexingRepoClient, ChaindexingRepoClientMutex, ChaindexingRepoTxnClient,
+    Config, ContractAddress, EventAbi, ExecutesWithRawQuery, HasRawQueryClient,
+    LoadsDataWithRawQuery,
+};
+
+type PureHandlers = HashMap<EventAbi, Arc<dyn PureHandler>>;
+type TransactionHandlers = HashMap<String, Vec<Arc<dyn TransactionHandler>>>;
+
+pub const SHADOW_SCHEMA: &str = "chaindexing_shadow";
+
+/// Tracks replaying a contract address' events into the shadow state tables
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+pub struct ShadowCursor {
+    pub id: i64,
+    pub chain_id: i64,
+    pub contract_address: String,
+    pub next_block_number_to_handle_from: i64,
+}
+
+/// Starts a new generation of shadow state tables, discarding any unfinished one
+pub async fn start(client: &ChaindexingRepoClient, state_migrations: &[Arc<dyn StateMigrations>]) {
+    ChaindexingRepo::execute(
+        client,
+        &format!("DROP SCHEMA IF EXISTS {SHADOW_SCHEMA} CASCADE"),
+    )
+    .await;
+    ChaindexingRepo::execute(client, &format!("CREATE SCHEMA {SHADOW_SCHEMA}")).await;
+    ChaindexingRepo::execute(client, "DELETE FROM chaindexing_shadow_cursors").await;
+    ChaindexingRepo::execute(
+        client,
+        &format!(
+            "DELETE FROM chaindexing_applied_state_migrations WHERE schema_name = '{SHADOW_SCHEMA}'"
+        ),
+    )
+    .await;
+
+    run_shadow_migrations(client, state_migrations).await;
+
+    ChaindexingRepo::create_shadow_cursors(client).await;
+}
+
+pub async fn is_in_progress(client: &ChaindexingRepoClient) -> bool {
+    !ChaindexingRepo::load_shadow_cursors(client).await.is_empty()
+}
+
+pub async fn run_shadow_migrations(
+    client: &ChaindexingRepoClient,
+    state_migrations: &[Arc<dyn StateMigrations>],
+) {
+    let live_schema = load_live_schema(client).await;
+
+    ChaindexingRepo::execute(client, &get_shadow_search_path_query("SET", &live_schema)).await;
+
+    // Recorded against the shadow schema, the current one
+    states::run_migrations(client, state_migrations).await;
+
+    ChaindexingRepo::execute(client, &format!("SET search_path TO {live_schema}")).await;
+}
+
+#[derive(Deserialize)]
+struct CurrentSchema {
+    schema_name: String,
+}
+
+/// Schema of the live state tables, the current one until a shadow search path gets set
+pub async fn load_live_schema(client: &ChaindexingRepoClient) -> String {
+    let current_schema: CurrentSchema =
+        ChaindexingRepo::load_data(client, "SELECT current_schema()::TEXT AS schema_name")
+            .await
+            .unwrap();
+
+    current_schema.schema_name
+}
+
+/// Makes unqualified state tables resolve to the shadow ones for the rest of the
+/// transaction. Internal tables are still resolved from the live schema.
+pub async fn set_local_shadow_search_path<'a>(
+    client: &ChaindexingRepoTxnClient<'a>,
+    live_schema: &str,
+) {
+    ChaindexingRepo::execute_in_txn(
+        client,
+        &get_shadow_search_path_query("SET LOCAL", live_schema),
+    )
+    .await;
+}
+pub async fn reset_local_search_path<'a>(client: &ChaindexingRepoTxnClient<'a>, live_schema: &str) {
+    ChaindexingRepo::execute_in_txn(client, &format!("SET LOCAL search_path TO {live_schema}"))
+        .await;
+}
+
+/// For MultiChainStates, which get mutated outside of transactions
+pub async fn set_shadow_search_path(client: &ChaindexingRepoClient) {
+    let live_schema = load_live_schema(client).await;
+
+    ChaindexingRepo::execute(client, &get_shadow_search_path_query("SET", &live_schema)).await;
+}
+
+fn get_shadow_search_path_query(set_command: &str, live_schema: &str) -> String {
+    format!("{set_command} search_path TO {SHADOW_SCHEMA}, {live_schema}")
+}
+
+/// Replays events into the shadow state tables and swaps them in once caught up.
+/// Live handlers only get paused, through `states_lock`, for the final catch-up and swap.
+pub async fn run<S: Send + Sync + Clone>(
+    config: &Config<S>,
+    handlers: (&PureHandlers, &TransactionHandlers),
+    states_lock: &RwLock<()>,
+    (repo_client, repo_client_for_mcs): (&ChaindexingRepoClientMutex, &ChaindexingRepoClientMutex),
+) {
+    let live_schema = {
+        let client = repo_client.lock().await;
+
+        if !is_in_progress(&client).await {
+            return;
+        }
+
+        // Includes contract addresses discovered since reindexing started
+        ChaindexingRepo::create_shadow_cursors(&client).await;
+
+        load_live_schema(&client).await
+    };
+
+    let handle_events = || {
+        handle_events(
+            config,
+            handlers,
+            &live_schema,
+            (repo_client, repo_client_for_mcs),
+        )
+    };
+
+    while {
+        let _states_lock = states_lock.read().await;
+        handle_events().await
+    } {}
+
+    let _states_lock = states_lock.write().await;
+    while handle_events().await {}
+
+    let state_migrations = crate::contracts::get_state_migrations(&config.contracts);
+    let state_table_names = states::get_all_table_names(&state_migrations);
+    maybe_swap(
+        &state_table_names,
+        &live_schema,
+        &mut *repo_client.lock().await,
+    )
+    .await;
+}
+
+/// Handles a batch of events per contract address, returning whether any shadow cursor moved
+async fn handle_events<S: Send + Sync + Clone>(
+    Config {
+        chains,
+        blocks_per_batch,
+        ..
+    }: &Config<S>,
+    (pure_handlers, transaction_handlers): (&PureHandlers, &TransactionHandlers),
+    live_schema: &str,
+    (repo_client, repo_client_for_mcs): (&ChaindexingRepoClientMutex, &ChaindexingRepoClientMutex),
+) -> bool {
+    let shadow_cursors = ChaindexingRepo::load_shadow_cursors(&*repo_client.lock().await).await;
+    let mut has_progressed = false;
+
+    for chain in chains {
+        let chain_id = chain.id as u64;
+        let handler_cursors =
+            ChaindexingRepo::load_handler_cursors(&*repo_client.lock().await, chain_id).await;
+
+        let mut contract_addresses_stream =
+            ContractAddressesStream::new(repo_client, chain_id as i64).with_chunk_size(200);
+
+        while let Some(contract_addresses) = contract_addresses_stream.next().await {
+            for contract_address @ ContractAddress { address, .. } in &contract_addresses {
+                let Some(shadow_cursor) = shadow_cursors
+                    .iter()
+                    .find(|sc| sc.chain_id == chain_id as i64 && &sc.contract_address == address)
+                else {
+                    continue;
+                };
+
+                // Never past the live handlers nor events still being backfilled
+                let until_block_number = handler_cursors
+                    .iter()
+                    .filter(|hc| &hc.contract_address == address && hc.is_backfilling_events())
+                    .map(|hc| hc.next_block_number_to_ingest_from)
+                    .fold(contract_address.next_block_number_to_handle_from, min);
+
+                if shadow_cursor.next_block_number_to_handle_from >= until_block_number {
+                    continue;
+                }
+
+                let mut client = repo_client.lock().await;
+
+                let events = ChaindexingRepo::load_events(
+                    &client,
+                    chain_id,
+                    address,
+                    shadow_cursor.next_block_number_to_handle_from as u64,
+                    *blocks_per_batch,
+                )
+                .await;
+
+                let block_numbers: Vec<_> = events.iter().map(|e| e.block_number).collect();
+                let next_block_number_to_handle_from = get_next_block_number_to_handle_from(
+                    &block_numbers,
+                    *blocks_per_batch,
+                    until_block_number,
+                );
+                let events: Vec<_> =
+                    events.into_iter().filter(|e| e.block_number < until_block_number).collect();
+
+                let transaction_handlers = transaction_handlers
+                    .get(&contract_address.contract_name)
+                    .map(|h| h.as_slice())
+                    .unwrap_or_default();
+                let events_by_transaction_hash = handlers::load_transaction_events(
+                    &client,
+                    chain_id,
+                    &events,
+                    transaction_handlers,
+                )
+                .await;
+                let mut handled_transactions = HashSet::new();
+
+                let txn_client = ChaindexingRepo::get_txn_client(&mut client).await;
+                set_local_shadow_search_path(&txn_client, live_schema).await;
+                let state_cache = StateCache::new();
+
+                for event in &events {
+                    if let Some(handler) = pure_handlers.get(event.get_abi()) {
+                        let handler_context = PureHandlerContext::for_event(event, &txn_client)
+                            .with_state_cache(&state_cache);
+
+                        handler.handle_event(handler_context).await;
+                    }
+
+                    handlers::handle_transaction(
+                        event,
+                        transaction_handlers,
+                        &events_by_transaction_hash,
+                        &mut handled_transactions,
+                        &txn_client,
+                        &state_cache,
+                    )
+                    .await;
+                }
+
+                state_cache.flush(&txn_client).await;
+
+                ChaindexingRepo::update_shadow_cursor(
+                    &txn_client,
+                    shadow_cursor,
+                    next_block_number_to_handle_from as u64,
+                )
+                .await;
+
+                ChaindexingRepo::commit_txns(txn_client).await;
+
+                has_progressed = true;
+            }
+        }
+    }
+
+    // Its client reads and writes the shadow schema
+    states::apply_mcs_mutations(&mut *repo_client_for_mcs.lock().await).await;
+
+    has_progressed
+}
+
+/// Jumps straight to `until_block_number` once the loaded batch reaches it
+fn get_next_block_number_to_handle_from(
+    loaded_block_numbers: &[i64],
+    limit: u64,
+    until_block_number: i64,
+) -> i64 {
+    let has_reached_until_block_number = loaded_block_numbers.len() < limit as usize
+        || loaded_block_numbers.iter().any(|b| *b >= until_block_number);
+
+    match loaded_block_numbers.last() {
+        Some(last_block_number) if !has_reached_until_block_number => last_block_number + 1,
+        _ => until_block_number,
+    }
+}
+
+async fn maybe_swap(
+    state_table_names: &[String],
+    live_schema: &str,
+    client: &mut ChaindexingRepoClient,
+) {
+    let txn_client = ChaindexingRepo::get_txn_client(client).await;
+
+    let contract_addresses_ahead_of_shadow: Vec<ContractAddress> =
+        ChaindexingRepo::load_data_list_in_txn(
+            &txn_client,
+            "SELECT ca.* FROM chaindexing_contract_addresses ca
+            LEFT JOIN chaindexing_shadow_cursors sc
+            ON sc.chain_id = ca.chain_id AND sc.contract_address = ca.address
+            WHERE sc.id IS NULL
+            OR sc.next_block_number_to_handle_from < ca.next_block_number_to_handle_from",
+        )
+        .await;
+
+    if !contract_addresses_ahead_of_shadow.is_empty() {
+        return;
+    }
+
+    for table_name in state_table_names {
+        for table_name in [
+            table_name.to_owned(),
+            states::get_state_versions_table_name(table_name),
+        ] {
+            ChaindexingRepo::execute_in_txn(
+                &txn_client,
+                &format!("DROP TABLE IF EXISTS {live_schema}.{table_name}"),
+            )
+            .await;
+            ChaindexingRepo::execute_in_txn(
+                &txn_client,
+                &format!("ALTER TABLE {SHADOW_SCHEMA}.{table_name} SET SCHEMA {live_schema}"),
+            )
+            .await;
+        }
+    }
+
+    // Shadow states were built with every handler, lagging ones included
+    ChaindexingRepo::execute_in_txn(
+        &txn_client,
+        "UPDATE chaindexing_handler_cursors hc
+        SET next_block_number_to_handle_from = GREATEST(hc.next_block_number_to_handle_from, ca.next_block_number_to_handle_from)
+        FROM chaindexing_contract_addresses ca
+        WHERE hc.chain_id = ca.chain_id AND hc.contract_address = ca.address",
+    )
+    .await;
+
+    // Migrations applied to the shadow tables now are to the live ones
+    ChaindexingRepo::execute_in_txn_with_params(
+        &txn_client,
+        "DELETE FROM chaindexing_applied_state_migrations WHERE schema_name = $1",
+        &[live_schema.into()],
+    )
+    .await;
+    ChaindexingRepo::execute_in_txn_with_params(
+        &txn_client,
+        &format!(
+            "UPDATE chaindexing_applied_state_migrations SET schema_name = $1
+            WHERE schema_name = '{SHADOW_SCHEMA}'"
+        ),
+        &[live_schema.into()],
+    )
+    .await;
+
+    ChaindexingRepo::execute_in_txn(&txn_client, "DELETE FROM chaindexing_shadow_cursors").await;
+    ChaindexingRepo::execute_in_txn(&txn_client, &format!("DROP SCHEMA {SHADOW_SCHEMA} CASCADE"))
+        .await;
+
+    ChaindexingRepo::commit_txns(txn_client).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn continues_after_last_loaded_block_within_limit() {
+        assert_eq!(
+            get_next_block_number_to_handle_from(&[10, 12, 15], 3, 100),
+            16
+        );
+    }
+
+    #[test]
+    fn stops_at_live_cursor() {
+        assert_eq!(get_next_block_number_to_handle_from(&[10, 12], 3, 100), 100);
+        assert_eq!(get_next_block_number_to_handle_from(&[], 3, 100), 100);
+        assert_eq!(
+            get_next_block_number_to_handle_from(&[10, 12, 150], 3, 100),
+            100
+        );
+    }
+}
@@ -9,9 +9,11 @@ pub use postgres_repo::{
 };
 
 mod repo;
+mod sql_params;
 
 #[doc(hidden)]
 pub use repo::{ExecutesWithRawQuery, HasRawQueryClient, Repo, RepoError};
+pub use sql_params::{SqlParams, SqlValue};
 
 #[doc(hidden)]
 pub(crate) use repo::{LoadsDataWithRawQuery, Migratable, RepoMigrations, SQLikeMigrations};
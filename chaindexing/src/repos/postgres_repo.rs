@@ -2,6 +2,7 @@ mod migrations;
 mod raw_queries;
 
 use crate::chain_reorg::UnsavedReorgedBlock;
+use crate::handler_cursors::HandlerCursor;
 
 use crate::{contracts::ContractAddress, events::Event, nodes::Node};
 use diesel_async::RunQueryDsl;
@@ -132,6 +133,21 @@ impl Repo for PostgresRepo {
             .unwrap();
     }
 
+    async fn update_handler_cursor_next_block_number_to_ingest_from<'a>(
+        conn: &mut Self::Conn<'a>,
+        handler_cursor: &HandlerCursor,
+        block_number: i64,
+    ) {
+        use crate::diesel::schema::chaindexing_handler_cursors::dsl::*;
+
+        diesel::update(chaindexing_handler_cursors)
+            .filter(id.eq(handler_cursor.id))
+            .set(next_block_number_to_ingest_from.eq(block_number))
+            .execute(conn)
+            .await
+            .unwrap();
+    }
+
     async fn create_reorged_block<'a>(
         conn: &mut Self::Conn<'a>,
         reorged_block: &UnsavedReorgedBlock,
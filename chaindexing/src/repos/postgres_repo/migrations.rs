@@ -29,9 +29,44 @@ impl RepoMigrations for PostgresRepo {
         SQLikeMigrations::drop_reorged_blocks()
     }
 
+    fn create_handler_cursors_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_handler_cursors()
+    }
+    fn drop_handler_cursors_migration() -> &'static [&'static str] {
+        SQLikeMigrations::drop_handler_cursors()
+    }
+
+    fn create_shadow_cursors_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_shadow_cursors()
+    }
+    fn drop_shadow_cursors_migration() -> &'static [&'static str] {
+        SQLikeMigrations::drop_shadow_cursors()
+    }
+
+    fn create_mcs_mutations_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_mcs_mutations()
+    }
+    fn drop_mcs_mutations_migration() -> &'static [&'static str] {
+        SQLikeMigrations::drop_mcs_mutations()
+    }
+
+    fn create_invariant_violations_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_invariant_violations()
+    }
+    fn drop_invariant_violations_migration() -> &'static [&'static str] {
+        SQLikeMigrations::drop_invariant_violations()
+    }
+
+    fn create_state_functions_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_state_functions()
+    }
+
     fn create_root_states_migration() -> &'static [&'static str] {
         SQLikeMigrations::create_root_states()
     }
+    fn create_applied_state_migrations_migration() -> &'static [&'static str] {
+        SQLikeMigrations::create_applied_state_migrations()
+    }
 }
 
 impl Migratable for PostgresRepo {}
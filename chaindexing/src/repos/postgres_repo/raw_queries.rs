@@ -1,11 +1,21 @@
-use tokio_postgres::{types::ToSql, Client, NoTls, Transaction};
+use std::error::Error;
+
+use bytes::BytesMut;
+use ethers::types::U256;
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use tokio_postgres::types::{to_sql_checked, Format, IsNull, ToSql, Type};
+use tokio_postgres::{AsyncMessage, Client, NoTls, Transaction};
 
 use crate::chain_reorg::ReorgedBlock;
 use crate::events::PartialEvent;
+use crate::handler_cursors::HandlerCursor;
 use crate::nodes::Node;
-use crate::{root, Event, UnsavedContractAddress};
+use crate::reindexing::ShadowCursor;
+use crate::{root, Event, SqlParams, SqlValue, UnsavedContractAddress};
 use crate::{ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery, PostgresRepo};
 use serde::de::DeserializeOwned;
+use serde::Deserialize;
 
 pub type PostgresRepoClient = Client;
 pub type PostgresRepoTxnClient<'a> = Transaction<'a>;
@@ -27,15 +37,63 @@ impl HasRawQueryClient for PostgresRepo {
     ) -> Self::RawQueryTxnClient<'a> {
         client.transaction().await.unwrap()
     }
+
+    async fn listen(&self, channel: &str) -> mpsc::UnboundedReceiver<String> {
+        let (client, mut conn) = tokio_postgres::connect(&self.url, NoTls).await.unwrap();
+        let (sender, receiver) = mpsc::unbounded_channel();
+
+        tokio::spawn({
+            let sender = sender.clone();
+
+            async move {
+                let mut messages = futures_util::stream::poll_fn(|cx| conn.poll_message(cx));
+
+                while let Some(message) = messages.next().await {
+                    match message {
+                        Ok(AsyncMessage::Notification(notification)) => {
+                            if sender.send(notification.payload().to_string()).is_err() {
+                                break;
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(error) => {
+                            eprintln!("connection error: {error}");
+                            break;
+                        }
+                    }
+                }
+            }
+        });
+
+        client.batch_execute(&format!("LISTEN {channel}")).await.unwrap();
+
+        // The connection closes along with its client
+        tokio::spawn(async move {
+            sender.closed().await;
+            drop(client);
+        });
+
+        receiver
+    }
 }
 
 #[crate::augmenting_std::async_trait]
 impl ExecutesWithRawQuery for PostgresRepo {
     async fn execute(client: &Self::RawQueryClient, query: &str) {
-        client.execute(query, &[] as &[&(dyn ToSql + Sync)]).await.unwrap();
+        Self::execute_with_params(client, query, &[]).await;
     }
     async fn execute_in_txn<'a>(txn_client: &Self::RawQueryTxnClient<'a>, query: &str) {
-        txn_client.execute(query, &[] as &[&(dyn ToSql + Sync)]).await.unwrap();
+        Self::execute_in_txn_with_params(txn_client, query, &[]).await;
+    }
+    async fn execute_with_params(client: &Self::RawQueryClient, query: &str, params: &[SqlValue]) {
+        client.execute(query, &to_sql_params(params)).await.unwrap();
+    }
+    async fn execute_in_txn_with_params<'a>(
+        txn_client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+        params: &[SqlValue],
+    ) {
+        txn_client.execute(query, &to_sql_params(params)).await.unwrap();
     }
     async fn commit_txns<'a>(client: Self::RawQueryTxnClient<'a>) {
         client.commit().await.unwrap();
@@ -45,6 +103,7 @@ impl ExecutesWithRawQuery for PostgresRepo {
         client: &Self::RawQueryClient,
         contract_addresses: &[UnsavedContractAddress],
     ) {
+        let mut params = SqlParams::new();
         let contract_addresses_values = contract_addresses
             .iter()
             .map(
@@ -55,7 +114,11 @@ impl ExecutesWithRawQuery for PostgresRepo {
                      start_block_number,
                      ..
                  }| {
-                    format!("('{address}', {chain_id}, '{contract_name}', {start_block_number}, {start_block_number}, {start_block_number})")
+                    format!(
+                        "({address}, {chain_id}, {contract_name}, {start_block_number}, {start_block_number}, {start_block_number})",
+                        address = params.add(address),
+                        contract_name = params.add(contract_name)
+                    )
                 },
             )
             .collect::<Vec<_>>()
@@ -69,7 +132,7 @@ impl ExecutesWithRawQuery for PostgresRepo {
             DO NOTHING
         ");
 
-        Self::execute(client, &query).await;
+        Self::execute_with_params(client, &query, params.get_values()).await;
     }
 
     async fn create_contract_address<'a>(
@@ -81,15 +144,18 @@ impl ExecutesWithRawQuery for PostgresRepo {
         let chain_id = contract_address.chain_id;
         let start_block_number = contract_address.start_block_number;
 
+        let mut params = SqlParams::new();
         let query = format!(
             "INSERT INTO chaindexing_contract_addresses 
             (address, chain_id, contract_name, next_block_number_to_handle_from, next_block_number_to_ingest_from, start_block_number)
-            VALUES ('{address}', {chain_id}, '{contract_name}', {start_block_number}, {start_block_number}, {start_block_number})
+            VALUES ({address}, {chain_id}, {contract_name}, {start_block_number}, {start_block_number}, {start_block_number})
             ON CONFLICT (chain_id, address)
-            DO NOTHING"
+            DO NOTHING",
+            address = params.add(address),
+            contract_name = params.add(contract_name)
         );
 
-        Self::execute_in_txn(client, &query).await;
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
 
     async fn update_next_block_number_to_handle_from<'a>(
@@ -101,10 +167,10 @@ impl ExecutesWithRawQuery for PostgresRepo {
         let query = format!(
             "UPDATE chaindexing_contract_addresses
         SET next_block_number_to_handle_from = {block_number}
-        WHERE chain_id = {chain_id} AND address = '{address}'"
+        WHERE chain_id = {chain_id} AND address = $1"
         );
 
-        Self::execute_in_txn(client, &query).await;
+        Self::execute_in_txn_with_params(client, &query, &[address.into()]).await;
     }
 
     async fn update_next_block_numbers_to_handle_from<'a>(
@@ -119,6 +185,119 @@ impl ExecutesWithRawQuery for PostgresRepo {
         );
 
         Self::execute_in_txn(client, &query).await;
+
+        let query = format!(
+            "UPDATE chaindexing_handler_cursors
+        SET next_block_number_to_handle_from = LEAST(next_block_number_to_handle_from, {block_number})
+        WHERE chain_id = {chain_id}"
+        );
+
+        Self::execute_in_txn(client, &query).await;
+
+        let query = format!(
+            "UPDATE chaindexing_shadow_cursors
+        SET next_block_number_to_handle_from = LEAST(next_block_number_to_handle_from, {block_number})
+        WHERE chain_id = {chain_id}"
+        );
+
+        Self::execute_in_txn(client, &query).await;
+    }
+
+    async fn restart_next_block_numbers_to_handle_from(
+        client: &Self::RawQueryClient,
+        contract_names: &[String],
+    ) {
+        if contract_names.is_empty() {
+            return;
+        }
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_contract_addresses
+        SET next_block_number_to_handle_from = start_block_number
+        WHERE contract_name IN ({contract_names})",
+            contract_names = params.add_list(contract_names)
+        );
+
+        Self::execute_with_params(client, &query, params.get_values()).await;
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_handler_cursors hc
+        SET next_block_number_to_handle_from = ca.start_block_number
+        FROM chaindexing_contract_addresses ca
+        WHERE hc.chain_id = ca.chain_id AND hc.contract_address = ca.address
+        AND ca.contract_name IN ({contract_names})",
+            contract_names = params.add_list(contract_names)
+        );
+
+        Self::execute_with_params(client, &query, params.get_values()).await;
+    }
+
+    async fn rewind_next_block_numbers_to_handle_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        block_number: u64,
+    ) {
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_contract_addresses
+        SET next_block_number_to_handle_from = GREATEST(start_block_number, LEAST(next_block_number_to_handle_from, {block_number}))
+        WHERE chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_handler_cursors
+        SET next_block_number_to_handle_from = LEAST(next_block_number_to_handle_from, {block_number})
+        WHERE chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("contract_address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_shadow_cursors
+        SET next_block_number_to_handle_from = LEAST(next_block_number_to_handle_from, {block_number})
+        WHERE chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("contract_address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
+    }
+
+    async fn rewind_next_block_numbers_to_ingest_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        block_number: u64,
+    ) {
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_contract_addresses
+        SET next_block_number_to_ingest_from = GREATEST(start_block_number, LEAST(next_block_number_to_ingest_from, {block_number}))
+        WHERE chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
+
+        // Discarded events past the backfill get ingested again by contract addresses
+        let mut params = SqlParams::new();
+        let query = format!(
+            "UPDATE chaindexing_handler_cursors
+        SET ingest_until_block_number = LEAST(ingest_until_block_number, {block_number}),
+        next_block_number_to_ingest_from = LEAST(next_block_number_to_ingest_from, {block_number})
+        WHERE chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("contract_address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
 
     async fn update_next_block_number_for_side_effects<'a>(
@@ -130,12 +309,107 @@ impl ExecutesWithRawQuery for PostgresRepo {
         let query = format!(
             "UPDATE chaindexing_contract_addresses
         SET next_block_number_for_side_effects = {block_number}
-        WHERE chain_id = {chain_id} AND address = '{address}'"
+        WHERE chain_id = {chain_id} AND address = $1"
+        );
+
+        Self::execute_in_txn_with_params(client, &query, &[address.into()]).await;
+    }
+
+    async fn create_handler_cursors(client: &Self::RawQueryClient, contract_name: &str, abi: &str) {
+        // Events of the ABI ingested before may not cover the handler's range, so all of
+        // it gets backfilled. Backfilling skips events that already got ingested.
+        let query = "INSERT INTO chaindexing_handler_cursors
+            (chain_id, contract_address, abi, next_block_number_to_ingest_from, ingest_until_block_number, next_block_number_to_handle_from)
+            SELECT ca.chain_id, ca.address, $1,
+            ca.start_block_number,
+            ca.next_block_number_to_ingest_from,
+            ca.start_block_number
+            FROM chaindexing_contract_addresses ca
+            WHERE ca.contract_name = $2
+            AND ca.next_block_number_to_ingest_from > ca.start_block_number
+            ON CONFLICT (chain_id, contract_address, abi)
+            DO NOTHING";
+
+        Self::execute_with_params(client, query, &[abi.into(), contract_name.into()]).await;
+    }
+
+    async fn update_handler_cursor_next_block_number_to_handle_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        handler_cursor: &HandlerCursor,
+        block_number: u64,
+    ) {
+        let query = format!(
+            "UPDATE chaindexing_handler_cursors
+        SET next_block_number_to_handle_from = {block_number}
+        WHERE id = {id}",
+            id = handler_cursor.id
+        );
+
+        Self::execute_in_txn(client, &query).await;
+    }
+
+    async fn delete_handler_cursor<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        handler_cursor: &HandlerCursor,
+    ) {
+        let query = format!(
+            "DELETE FROM chaindexing_handler_cursors WHERE id = {id}",
+            id = handler_cursor.id
+        );
+
+        Self::execute_in_txn(client, &query).await;
+    }
+
+    async fn create_shadow_cursors(client: &Self::RawQueryClient) {
+        let query = "INSERT INTO chaindexing_shadow_cursors
+            (chain_id, contract_address, next_block_number_to_handle_from)
+            SELECT chain_id, address, start_block_number
+            FROM chaindexing_contract_addresses
+            ON CONFLICT (chain_id, contract_address)
+            DO NOTHING";
+
+        Self::execute(client, query).await;
+    }
+
+    async fn update_shadow_cursor<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        shadow_cursor: &ShadowCursor,
+        block_number: u64,
+    ) {
+        let query = format!(
+            "UPDATE chaindexing_shadow_cursors
+        SET next_block_number_to_handle_from = {block_number}
+        WHERE id = {id}",
+            id = shadow_cursor.id
         );
 
         Self::execute_in_txn(client, &query).await;
     }
 
+    async fn append_applied_state_migration(client: &Self::RawQueryClient, migration: &str) {
+        let query = "INSERT INTO chaindexing_applied_state_migrations (schema_name, migration)
+            VALUES (current_schema(), $1)
+            ON CONFLICT (schema_name, md5(migration))
+            DO NOTHING";
+
+        Self::execute_with_params(client, query, &[migration.into()]).await;
+    }
+
+    async fn delete_applied_state_migrations(client: &Self::RawQueryClient, migrations: &[&str]) {
+        if migrations.is_empty() {
+            return;
+        }
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "DELETE FROM chaindexing_applied_state_migrations
+            WHERE schema_name = current_schema() AND migration IN ({})",
+            params.add_list(migrations.iter().copied())
+        );
+
+        Self::execute_with_params(client, &query, params.get_values()).await;
+    }
+
     async fn update_reorged_blocks_as_handled<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         reorged_block_ids: &[i32],
@@ -159,14 +433,39 @@ impl ExecutesWithRawQuery for PostgresRepo {
     async fn append_root_state(client: &Self::RawQueryClient, new_root_state: &root::State) {
         let reset_count = new_root_state.reset_count;
         let reset_including_side_effects_count = new_root_state.reset_including_side_effects_count;
+        let reindex_count = new_root_state.reindex_count;
+        let handler_versions = to_json_or_null(&new_root_state.handler_versions);
+        let state_migration_versions = to_json_or_null(&new_root_state.state_migration_versions);
 
         let query = format!(
             "INSERT INTO chaindexing_root_states
-            (reset_count, reset_including_side_effects_count)
-            VALUES ('{reset_count}', '{reset_including_side_effects_count}')"
+            (reset_count, reset_including_side_effects_count, reindex_count, handler_versions, state_migration_versions)
+            VALUES ({reset_count}, {reset_including_side_effects_count}, {reindex_count}, $1, $2)"
         );
 
-        Self::execute(client, &query).await;
+        Self::execute_with_params(
+            client,
+            &query,
+            &[handler_versions, state_migration_versions],
+        )
+        .await;
+    }
+
+    async fn delete_events_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        from_block_number: u64,
+    ) {
+        let mut params = SqlParams::new();
+        let query = format!(
+            "DELETE FROM chaindexing_events
+            WHERE block_number >= {from_block_number}
+            AND chain_id = {chain_id} {addresses_filter}",
+            addresses_filter = and_addresses_filter("contract_address", addresses, &mut params)
+        );
+
+        Self::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
 
     async fn prune_events(client: &Self::RawQueryClient, min_block_number: u64, chain_id: u64) {
@@ -241,19 +540,62 @@ impl LoadsDataWithRawQuery for PostgresRepo {
     ) -> Vec<Event> {
         let query = format!(
             "SELECT * from chaindexing_events
-            WHERE chain_id = {chain_id} AND contract_address= '{contract_address}'
+            WHERE chain_id = {chain_id} AND contract_address = $1
             AND block_number >= {from_block_number} 
             ORDER BY block_number ASC, log_index ASC
             LIMIT {limit}",
         );
 
-        Self::load_data_list(client, &query).await
+        Self::load_data_list_with_params(client, &query, &[contract_address.into()]).await
+    }
+
+    async fn load_events_by_transaction_hashes(
+        client: &Self::RawQueryClient,
+        chain_id: u64,
+        transaction_hashes: &[String],
+    ) -> Vec<Event> {
+        if transaction_hashes.is_empty() {
+            return vec![];
+        }
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "SELECT * from chaindexing_events
+            WHERE chain_id = {chain_id} AND transaction_hash IN ({transaction_hashes})
+            ORDER BY block_number ASC, log_index ASC",
+            transaction_hashes = params.add_list(transaction_hashes)
+        );
+
+        Self::load_data_list_with_params(client, &query, params.get_values()).await
+    }
+
+    async fn load_events_by_abi(
+        client: &Self::RawQueryClient,
+        HandlerCursor {
+            chain_id,
+            contract_address,
+            abi,
+            next_block_number_to_handle_from,
+            ..
+        }: &HandlerCursor,
+        until_block_number: u64,
+    ) -> Vec<Event> {
+        let query = format!(
+            "SELECT * from chaindexing_events
+            WHERE chain_id = {chain_id} AND contract_address = $1 AND abi = $2
+            AND block_number >= {next_block_number_to_handle_from} AND block_number < {until_block_number}
+            ORDER BY block_number ASC, log_index ASC",
+        );
+
+        Self::load_data_list_with_params(client, &query, &[contract_address.into(), abi.into()])
+            .await
     }
 
     async fn load_latest_events(
         client: &Self::RawQueryClient,
         addresses: &[String],
     ) -> Vec<PartialEvent> {
+        let mut params = SqlParams::new();
         let query = format!(
             "WITH EventsWithRowNumbers AS (
                 SELECT
@@ -270,10 +612,10 @@ impl LoadsDataWithRawQuery for PostgresRepo {
                 EventsWithRowNumbers
             WHERE
                 row_no = 1",
-                addresses = join_strings_with_comma(addresses),
+                addresses = params.add_list(addresses),
         );
 
-        Self::load_data_list(client, &query).await
+        Self::load_data_list_with_params(client, &query, params.get_values()).await
     }
     async fn load_unhandled_reorged_blocks(client: &Self::RawQueryClient) -> Vec<ReorgedBlock> {
         Self::load_data_list(
@@ -283,32 +625,88 @@ impl LoadsDataWithRawQuery for PostgresRepo {
         .await
     }
 
+    async fn load_handler_cursors(
+        client: &Self::RawQueryClient,
+        chain_id: u64,
+    ) -> Vec<HandlerCursor> {
+        Self::load_data_list(
+            client,
+            &format!("SELECT * FROM chaindexing_handler_cursors WHERE chain_id = {chain_id}"),
+        )
+        .await
+    }
+
+    async fn load_shadow_cursors(client: &Self::RawQueryClient) -> Vec<ShadowCursor> {
+        Self::load_data_list(client, "SELECT * FROM chaindexing_shadow_cursors").await
+    }
+
+    async fn load_applied_state_migrations(client: &Self::RawQueryClient) -> Vec<String> {
+        let applied_state_migrations: Vec<AppliedStateMigration> = Self::load_data_list(
+            client,
+            "SELECT migration FROM chaindexing_applied_state_migrations
+            WHERE schema_name = current_schema()",
+        )
+        .await;
+
+        applied_state_migrations.into_iter().map(|m| m.migration).collect()
+    }
+
     async fn load_data<Data: Send + DeserializeOwned>(
         client: &Self::RawQueryClient,
         query: &str,
     ) -> Option<Data> {
-        let mut data_list: Vec<Data> = Self::load_data_list(client, query).await;
+        Self::load_data_with_params(client, query, &[]).await
+    }
+    async fn load_data_in_txn<'a, Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Option<Data> {
+        Self::load_data_in_txn_with_params(client, query, &[]).await
+    }
+    async fn load_data_list<Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryClient,
+        query: &str,
+    ) -> Vec<Data> {
+        Self::load_data_list_with_params(client, query, &[]).await
+    }
+    async fn load_data_list_in_txn<'a, Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+    ) -> Vec<Data> {
+        Self::load_data_list_in_txn_with_params(client, query, &[]).await
+    }
+
+    async fn load_data_with_params<Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryClient,
+        query: &str,
+        params: &[SqlValue],
+    ) -> Option<Data> {
+        let mut data_list: Vec<Data> =
+            Self::load_data_list_with_params(client, query, params).await;
 
         assert!(data_list.len() <= 1);
 
         data_list.pop()
     }
-    async fn load_data_in_txn<'a, Data: Send + DeserializeOwned>(
+    async fn load_data_in_txn_with_params<'a, Data: Send + DeserializeOwned>(
         client: &Self::RawQueryTxnClient<'a>,
         query: &str,
+        params: &[SqlValue],
     ) -> Option<Data> {
-        let mut data_list: Vec<Data> = Self::load_data_list_in_txn(client, query).await;
+        let mut data_list: Vec<Data> =
+            Self::load_data_list_in_txn_with_params(client, query, params).await;
 
         assert!(data_list.len() <= 1);
 
         data_list.pop()
     }
 
-    async fn load_data_list<Data: Send + DeserializeOwned>(
+    async fn load_data_list_with_params<Data: Send + DeserializeOwned>(
         client: &Self::RawQueryClient,
         query: &str,
+        params: &[SqlValue],
     ) -> Vec<Data> {
-        let json_aggregate = get_json_aggregate(client, query).await;
+        let json_aggregate = get_json_aggregate(client, query, params).await;
 
         if json_aggregate.is_object() || json_aggregate.is_array() {
             serde_json::from_value(json_aggregate).unwrap()
@@ -317,11 +715,12 @@ impl LoadsDataWithRawQuery for PostgresRepo {
         }
     }
 
-    async fn load_data_list_in_txn<'a, Data: Send + DeserializeOwned>(
+    async fn load_data_list_in_txn_with_params<'a, Data: Send + DeserializeOwned>(
         txn_client: &Self::RawQueryTxnClient<'a>,
         query: &str,
+        params: &[SqlValue],
     ) -> Vec<Data> {
-        let json_aggregate = get_json_aggregate_in_txn(txn_client, query).await;
+        let json_aggregate = get_json_aggregate_in_txn(txn_client, query, params).await;
 
         if json_aggregate.is_object() || json_aggregate.is_array() {
             serde_json::from_value(json_aggregate).unwrap()
@@ -331,19 +730,74 @@ impl LoadsDataWithRawQuery for PostgresRepo {
     }
 }
 
-async fn get_json_aggregate(client: &PostgresRepoClient, query: &str) -> serde_json::Value {
-    let rows = client.query(json_aggregate_query(query).as_str(), &[]).await.unwrap();
+async fn get_json_aggregate(
+    client: &PostgresRepoClient,
+    query: &str,
+    params: &[SqlValue],
+) -> serde_json::Value {
+    let rows = client
+        .query(json_aggregate_query(query).as_str(), &to_sql_params(params))
+        .await
+        .unwrap();
     rows.first().unwrap().get(0)
 }
 
 async fn get_json_aggregate_in_txn<'a>(
     txn_client: &PostgresRepoTxnClient<'a>,
     query: &str,
+    params: &[SqlValue],
 ) -> serde_json::Value {
-    let rows = txn_client.query(json_aggregate_query(query).as_str(), &[]).await.unwrap();
+    let rows = txn_client
+        .query(json_aggregate_query(query).as_str(), &to_sql_params(params))
+        .await
+        .unwrap();
     rows.first().unwrap().get(0)
 }
 
+fn to_sql_params(params: &[SqlValue]) -> Vec<&(dyn ToSql + Sync)> {
+    params.iter().map(|param| param as &(dyn ToSql + Sync)).collect()
+}
+
+impl ToSql for SqlValue {
+    fn to_sql(
+        &self,
+        ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn Error + Sync + Send>> {
+        match self.to_text() {
+            Some(text) => {
+                let text = match text.strip_prefix("0x") {
+                    // U256 and I256 fields serialize as hex, which NUMERIC doesn't parse
+                    Some(hex) if *ty == Type::NUMERIC => {
+                        U256::from_str_radix(hex, 16).map_err(|e| e.to_string())?.to_string()
+                    }
+                    _ => text,
+                };
+
+                out.extend_from_slice(text.as_bytes());
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    // Postgres parses values sent as text by their parameters' types, just like literals
+    fn encode_format(&self, _ty: &Type) -> Format {
+        Format::Text
+    }
+
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+#[derive(Deserialize)]
+struct AppliedStateMigration {
+    migration: String,
+}
+
 fn json_aggregate_query(query: &str) -> String {
     format!("WITH result AS ({query}) SELECT COALESCE(json_agg(result), '[]'::json) FROM result",)
 }
@@ -352,6 +806,14 @@ fn join_numbers_with_comma(numbers: &[impl ToString]) -> String {
     numbers.iter().map(|n| n.to_string()).collect::<Vec<String>>().join(",")
 }
 
-fn join_strings_with_comma(strings: &[String]) -> String {
-    strings.iter().map(|string| format!("'{string}'")).collect::<Vec<_>>().join(",")
+fn and_addresses_filter(column: &str, addresses: &[String], params: &mut SqlParams) -> String {
+    if addresses.is_empty() {
+        "".to_string()
+    } else {
+        format!("AND {column} IN ({})", params.add_list(addresses))
+    }
+}
+
+fn to_json_or_null(value: &Option<impl serde::Serialize>) -> SqlValue {
+    value.as_ref().map(|value| serde_json::to_value(value).unwrap()).into()
 }
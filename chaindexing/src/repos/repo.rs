@@ -6,7 +6,10 @@ use futures_core::future::BoxFuture;
 use serde::de::DeserializeOwned;
 
 use crate::chain_reorg::{ReorgedBlock, UnsavedReorgedBlock};
+use crate::handler_cursors::HandlerCursor;
+use crate::reindexing::ShadowCursor;
 use crate::root;
+use crate::SqlValue;
 use crate::{
     contracts::UnsavedContractAddress,
     events::{Event, PartialEvent},
@@ -57,6 +60,12 @@ pub trait Repo:
         block_number: i64,
     );
 
+    async fn update_handler_cursor_next_block_number_to_ingest_from<'a>(
+        conn: &mut Self::Conn<'a>,
+        handler_cursor: &HandlerCursor,
+        block_number: i64,
+    );
+
     async fn create_reorged_block<'a>(
         conn: &mut Self::Conn<'a>,
         reorged_block: &UnsavedReorgedBlock,
@@ -78,12 +87,22 @@ pub trait HasRawQueryClient {
     async fn get_txn_client<'a>(
         client: &'a mut Self::RawQueryClient,
     ) -> Self::RawQueryTxnClient<'a>;
+
+    /// Listens to the channel on a dedicated connection, receiving the payloads
+    /// of its notifications until the receiver gets dropped
+    async fn listen(&self, channel: &str) -> tokio::sync::mpsc::UnboundedReceiver<String>;
 }
 
 #[crate::augmenting_std::async_trait]
 pub trait ExecutesWithRawQuery: HasRawQueryClient {
     async fn execute(client: &Self::RawQueryClient, query: &str);
     async fn execute_in_txn<'a>(client: &Self::RawQueryTxnClient<'a>, query: &str);
+    async fn execute_with_params(client: &Self::RawQueryClient, query: &str, params: &[SqlValue]);
+    async fn execute_in_txn_with_params<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+        params: &[SqlValue],
+    );
     async fn commit_txns<'a>(client: Self::RawQueryTxnClient<'a>);
 
     async fn create_contract_address<'a>(
@@ -109,6 +128,26 @@ pub trait ExecutesWithRawQuery: HasRawQueryClient {
         block_number: u64,
     );
 
+    async fn restart_next_block_numbers_to_handle_from(
+        client: &Self::RawQueryClient,
+        contract_names: &[String],
+    );
+
+    /// Moves cursors back to the block number, never forward or before start block numbers.
+    /// All addresses in the chain get rewound when `addresses` is empty.
+    async fn rewind_next_block_numbers_to_handle_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        block_number: u64,
+    );
+    async fn rewind_next_block_numbers_to_ingest_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        block_number: u64,
+    );
+
     async fn update_next_block_number_for_side_effects<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         address: &str,
@@ -116,12 +155,43 @@ pub trait ExecutesWithRawQuery: HasRawQueryClient {
         block_number: u64,
     );
 
+    /// Creates cursors for a newly added handler of the contract's addresses
+    /// that already ingested events
+    async fn create_handler_cursors(client: &Self::RawQueryClient, contract_name: &str, abi: &str);
+    async fn update_handler_cursor_next_block_number_to_handle_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        handler_cursor: &HandlerCursor,
+        block_number: u64,
+    );
+    async fn delete_handler_cursor<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        handler_cursor: &HandlerCursor,
+    );
+
+    /// Creates cursors for contract addresses that are yet to be reindexed
+    async fn create_shadow_cursors(client: &Self::RawQueryClient);
+    async fn update_shadow_cursor<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        shadow_cursor: &ShadowCursor,
+        block_number: u64,
+    );
+
+    /// Records the state migration as applied in the current schema
+    async fn append_applied_state_migration(client: &Self::RawQueryClient, migration: &str);
+    async fn delete_applied_state_migrations(client: &Self::RawQueryClient, migrations: &[&str]);
+
     async fn update_reorged_blocks_as_handled<'a>(
         client: &Self::RawQueryTxnClient<'a>,
         reorged_block_ids: &[i32],
     );
 
     async fn append_root_state(client: &Self::RawQueryClient, new_root_state: &root::State);
+    async fn delete_events_from<'a>(
+        client: &Self::RawQueryTxnClient<'a>,
+        chain_id: u64,
+        addresses: &[String],
+        from_block_number: u64,
+    );
     async fn prune_events(client: &Self::RawQueryClient, min_block_number: u64, chain_id: u64);
     async fn prune_nodes(client: &Self::RawQueryClient, retain_size: u16);
     async fn prune_root_states(client: &Self::RawQueryClient, retain_size: u64);
@@ -136,6 +206,13 @@ pub trait LoadsDataWithRawQuery: HasRawQueryClient {
         addresses: &[String],
     ) -> Vec<PartialEvent>;
     async fn load_unhandled_reorged_blocks(client: &Self::RawQueryClient) -> Vec<ReorgedBlock>;
+    async fn load_handler_cursors(
+        client: &Self::RawQueryClient,
+        chain_id: u64,
+    ) -> Vec<HandlerCursor>;
+    async fn load_shadow_cursors(client: &Self::RawQueryClient) -> Vec<ShadowCursor>;
+    /// State migrations applied in the current schema
+    async fn load_applied_state_migrations(client: &Self::RawQueryClient) -> Vec<String>;
 
     async fn load_events(
         client: &Self::RawQueryClient,
@@ -144,6 +221,17 @@ pub trait LoadsDataWithRawQuery: HasRawQueryClient {
         from_block_number: u64,
         limit: u64,
     ) -> Vec<Event>;
+    /// Events of the given transactions across contract addresses, in log order
+    async fn load_events_by_transaction_hashes(
+        client: &Self::RawQueryClient,
+        chain_id: u64,
+        transaction_hashes: &[String],
+    ) -> Vec<Event>;
+    async fn load_events_by_abi(
+        client: &Self::RawQueryClient,
+        handler_cursor: &HandlerCursor,
+        until_block_number: u64,
+    ) -> Vec<Event>;
 
     async fn load_data<Data: Send + DeserializeOwned>(
         client: &Self::RawQueryClient,
@@ -161,10 +249,32 @@ pub trait LoadsDataWithRawQuery: HasRawQueryClient {
         conn: &Self::RawQueryTxnClient<'a>,
         query: &str,
     ) -> Vec<Data>;
+
+    async fn load_data_with_params<Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryClient,
+        query: &str,
+        params: &[SqlValue],
+    ) -> Option<Data>;
+    async fn load_data_in_txn_with_params<'a, Data: Send + DeserializeOwned>(
+        client: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+        params: &[SqlValue],
+    ) -> Option<Data>;
+    async fn load_data_list_with_params<Data: Send + DeserializeOwned>(
+        conn: &Self::RawQueryClient,
+        query: &str,
+        params: &[SqlValue],
+    ) -> Vec<Data>;
+    async fn load_data_list_in_txn_with_params<'a, Data: Send + DeserializeOwned>(
+        conn: &Self::RawQueryTxnClient<'a>,
+        query: &str,
+        params: &[SqlValue],
+    ) -> Vec<Data>;
 }
 
 pub trait RepoMigrations: Migratable {
     fn create_root_states_migration() -> &'static [&'static str];
+    fn create_applied_state_migrations_migration() -> &'static [&'static str];
 
     fn create_nodes_migration() -> &'static [&'static str];
 
@@ -178,10 +288,29 @@ pub trait RepoMigrations: Migratable {
     fn create_reorged_blocks_migration() -> &'static [&'static str];
     fn drop_reorged_blocks_migration() -> &'static [&'static str];
 
+    fn create_handler_cursors_migration() -> &'static [&'static str];
+    fn drop_handler_cursors_migration() -> &'static [&'static str];
+
+    fn create_shadow_cursors_migration() -> &'static [&'static str];
+    fn drop_shadow_cursors_migration() -> &'static [&'static str];
+
+    fn create_mcs_mutations_migration() -> &'static [&'static str];
+    fn drop_mcs_mutations_migration() -> &'static [&'static str];
+
+    fn create_invariant_violations_migration() -> &'static [&'static str];
+    fn drop_invariant_violations_migration() -> &'static [&'static str];
+
+    fn create_state_functions_migration() -> &'static [&'static str];
+
     fn get_internal_migrations() -> Vec<&'static str> {
         [
             Self::create_events_migration(),
             Self::create_reorged_blocks_migration(),
+            Self::create_handler_cursors_migration(),
+            Self::create_shadow_cursors_migration(),
+            Self::create_mcs_mutations_migration(),
+            Self::create_invariant_violations_migration(),
+            Self::create_state_functions_migration(),
         ]
         .concat()
     }
@@ -190,6 +319,10 @@ pub trait RepoMigrations: Migratable {
         [
             Self::drop_events_migration(),
             Self::drop_reorged_blocks_migration(),
+            Self::drop_handler_cursors_migration(),
+            Self::drop_shadow_cursors_migration(),
+            Self::drop_mcs_mutations_migration(),
+            Self::drop_invariant_violations_migration(),
             Self::restart_ingest_and_handlers_next_block_numbers_migration(),
         ]
         .concat()
@@ -212,11 +345,29 @@ pub struct SQLikeMigrations;
 
 impl SQLikeMigrations {
     pub fn create_root_states() -> &'static [&'static str] {
-        &["CREATE TABLE IF NOT EXISTS chaindexing_root_states (
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_root_states (
                 id BIGSERIAL PRIMARY KEY,
                 reset_count BIGINT NOT NULL,
                 reset_including_side_effects_count BIGINT NOT NULL
-            )"]
+            )",
+            "ALTER TABLE chaindexing_root_states ADD COLUMN IF NOT EXISTS handler_versions JSONB",
+            "ALTER TABLE chaindexing_root_states ADD COLUMN IF NOT EXISTS state_migration_versions JSONB",
+            "ALTER TABLE chaindexing_root_states ADD COLUMN IF NOT EXISTS reindex_count BIGINT NOT NULL DEFAULT 0",
+        ]
+    }
+
+    pub fn create_applied_state_migrations() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_applied_state_migrations (
+                id BIGSERIAL PRIMARY KEY,
+                schema_name VARCHAR NOT NULL,
+                migration TEXT NOT NULL,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+            "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_applied_state_migrations_schema_migration_index
+            ON chaindexing_applied_state_migrations(schema_name, md5(migration))",
+        ]
     }
 
     pub fn create_nodes() -> &'static [&'static str] {
@@ -292,4 +443,128 @@ impl SQLikeMigrations {
     pub fn drop_reorged_blocks() -> &'static [&'static str] {
         &["DROP TABLE IF EXISTS chaindexing_reorged_blocks"]
     }
+
+    pub fn create_handler_cursors() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_handler_cursors (
+                id BIGSERIAL PRIMARY KEY,
+                chain_id BIGINT NOT NULL,
+                contract_address VARCHAR NOT NULL,
+                abi TEXT NOT NULL,
+                next_block_number_to_ingest_from BIGINT NOT NULL,
+                ingest_until_block_number BIGINT NOT NULL,
+                next_block_number_to_handle_from BIGINT NOT NULL
+            )",
+            "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_handler_cursors_chain_address_abi_index
+            ON chaindexing_handler_cursors(chain_id, contract_address, abi)",
+        ]
+    }
+    pub fn drop_handler_cursors() -> &'static [&'static str] {
+        &["DROP TABLE IF EXISTS chaindexing_handler_cursors"]
+    }
+
+    pub fn create_shadow_cursors() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_shadow_cursors (
+                id BIGSERIAL PRIMARY KEY,
+                chain_id BIGINT NOT NULL,
+                contract_address VARCHAR NOT NULL,
+                next_block_number_to_handle_from BIGINT NOT NULL
+            )",
+            "CREATE UNIQUE INDEX IF NOT EXISTS chaindexing_shadow_cursors_chain_address_index
+            ON chaindexing_shadow_cursors(chain_id, contract_address)",
+        ]
+    }
+    pub fn drop_shadow_cursors() -> &'static [&'static str] {
+        &[
+            "DROP TABLE IF EXISTS chaindexing_shadow_cursors",
+            "DROP SCHEMA IF EXISTS chaindexing_shadow CASCADE",
+        ]
+    }
+
+    pub fn create_mcs_mutations() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_mcs_mutations (
+                id BIGSERIAL PRIMARY KEY,
+                schema_name VARCHAR NOT NULL,
+                table_name VARCHAR NOT NULL,
+                chain_id BIGINT NOT NULL,
+                contract_address VARCHAR NOT NULL,
+                block_number BIGINT NOT NULL,
+                mutation JSONB NOT NULL,
+                event JSONB NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                last_error TEXT,
+                inserted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+            "CREATE INDEX IF NOT EXISTS chaindexing_mcs_mutations_schema_index
+            ON chaindexing_mcs_mutations(schema_name, id)",
+        ]
+    }
+    pub fn drop_mcs_mutations() -> &'static [&'static str] {
+        &["DROP TABLE IF EXISTS chaindexing_mcs_mutations"]
+    }
+
+    pub fn create_invariant_violations() -> &'static [&'static str] {
+        &[
+            "CREATE TABLE IF NOT EXISTS chaindexing_invariant_violations (
+                id BIGSERIAL PRIMARY KEY,
+                chain_id BIGINT NOT NULL,
+                contract_address VARCHAR NOT NULL,
+                invariant_name VARCHAR NOT NULL,
+                from_block_number BIGINT NOT NULL,
+                to_block_number BIGINT NOT NULL,
+                details TEXT NOT NULL,
+                halts BOOLEAN NOT NULL,
+                resolved_at TIMESTAMPTZ,
+                inserted_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+            )",
+            "CREATE INDEX IF NOT EXISTS chaindexing_invariant_violations_chain_address_index
+            ON chaindexing_invariant_violations(chain_id, contract_address)",
+        ]
+    }
+    pub fn drop_invariant_violations() -> &'static [&'static str] {
+        &["DROP TABLE IF EXISTS chaindexing_invariant_violations"]
+    }
+
+    pub fn create_state_functions() -> &'static [&'static str] {
+        // Reads NUMERIC(78, 0) values as the hex U256 and I256 deserialize from,
+        // two's complement for negative ones
+        &["DO $$
+            BEGIN
+                IF to_regprocedure('chaindexing_numeric_to_hex(NUMERIC)') IS NULL THEN
+                    CREATE FUNCTION chaindexing_numeric_to_hex(value NUMERIC) RETURNS TEXT AS $function$
+                    DECLARE
+                        remaining NUMERIC := CASE WHEN value < 0 THEN value + 2::NUMERIC ^ 256 ELSE value END;
+                        hex TEXT := '';
+                    BEGIN
+                        LOOP
+                            hex := substr('0123456789abcdef', mod(remaining, 16)::INTEGER + 1, 1) || hex;
+                            remaining := div(remaining, 16);
+                            EXIT WHEN remaining = 0;
+                        END LOOP;
+
+                        RETURN '0x' || hex;
+                    END
+                    $function$ LANGUAGE plpgsql IMMUTABLE STRICT;
+                END IF;
+            END
+            $$",
+            // Notifications can't exceed 8000 bytes, so larger changes get sent without their views
+            "DO $$
+            BEGIN
+                IF to_regprocedure('chaindexing_notify_state_change(JSONB)') IS NULL THEN
+                    CREATE FUNCTION chaindexing_notify_state_change(change JSONB) RETURNS VOID AS $function$
+                    BEGIN
+                        IF octet_length(change::TEXT) >= 8000 THEN
+                            change := change - 'old_view' - 'new_view' || '{\"is_truncated\": true}'::JSONB;
+                        END IF;
+
+                        PERFORM pg_notify('chaindexing_state_changes', change::TEXT);
+                    END
+                    $function$ LANGUAGE plpgsql;
+                END IF;
+            END
+            $$"]
+    }
 }
@@ -0,0 +1,145 @@
+use serde::{Deserialize, Serialize};
+
+/// Value bound to a raw query's parameter instead of getting formatted into it.
+/// The database parses it according to the type of the column it gets compared
+/// with or inserted into, just like it would a literal.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum SqlValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Text(String),
+    Json(serde_json::Value),
+}
+
+impl SqlValue {
+    /// Text representation of the value. None for NULL.
+    pub fn to_text(&self) -> Option<String> {
+        match self {
+            SqlValue::Null => None,
+            SqlValue::Bool(value) => Some(value.to_string()),
+            SqlValue::Int(value) => Some(value.to_string()),
+            SqlValue::Text(value) => Some(value.to_owned()),
+            SqlValue::Json(value) => Some(value.to_string()),
+        }
+    }
+}
+
+impl From<bool> for SqlValue {
+    fn from(value: bool) -> Self {
+        SqlValue::Bool(value)
+    }
+}
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Int(value)
+    }
+}
+impl From<i32> for SqlValue {
+    fn from(value: i32) -> Self {
+        SqlValue::Int(value as i64)
+    }
+}
+impl From<u64> for SqlValue {
+    /// Values beyond BIGINT's range get sent as text, to be parsed as NUMERIC
+    fn from(value: u64) -> Self {
+        i64::try_from(value)
+            .map(SqlValue::Int)
+            .unwrap_or_else(|_| SqlValue::Text(value.to_string()))
+    }
+}
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::Text(value.to_owned())
+    }
+}
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+impl From<&String> for SqlValue {
+    fn from(value: &String) -> Self {
+        SqlValue::Text(value.to_owned())
+    }
+}
+impl From<serde_json::Value> for SqlValue {
+    fn from(value: serde_json::Value) -> Self {
+        SqlValue::Json(value)
+    }
+}
+impl<T: Into<SqlValue>> From<Option<T>> for SqlValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(SqlValue::Null)
+    }
+}
+
+/// Collects the values bound to a raw query, handing out their placeholders.
+///
+/// # Example
+///
+/// ```ignore
+/// let mut params = SqlParams::new();
+/// let query = format!("SELECT * FROM nfts WHERE owner = {}", params.add(owner));
+/// ChaindexingRepo::load_data_list_with_params(&client, &query, params.get_values()).await;
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct SqlParams {
+    values: Vec<SqlValue>,
+}
+
+impl SqlParams {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Binds the value, returning its placeholder, e.g. `$1`
+    pub fn add(&mut self, value: impl Into<SqlValue>) -> String {
+        self.values.push(value.into());
+
+        format!("${}", self.values.len())
+    }
+
+    /// Binds the values, returning their comma-separated placeholders, e.g. `$1,$2`
+    pub fn add_list<V: Into<SqlValue>>(&mut self, values: impl IntoIterator<Item = V>) -> String {
+        values.into_iter().map(|value| self.add(value)).collect::<Vec<_>>().join(",")
+    }
+
+    pub fn get_values(&self) -> &[SqlValue] {
+        &self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hands_out_placeholders_in_order() {
+        let mut params = SqlParams::new();
+
+        assert_eq!(params.add("O'Reilly"), "$1");
+        assert_eq!(params.add_list([2_i64, 3]), "$2,$3");
+        assert_eq!(params.add(None::<String>), "$4");
+
+        assert_eq!(
+            params.get_values(),
+            &[
+                SqlValue::Text("O'Reilly".to_string()),
+                SqlValue::Int(2),
+                SqlValue::Int(3),
+                SqlValue::Null
+            ]
+        );
+    }
+
+    #[test]
+    fn sends_u64_values_beyond_bigint_as_text() {
+        assert_eq!(SqlValue::from(42_u64), SqlValue::Int(42));
+        assert_eq!(SqlValue::from(i64::MAX as u64), SqlValue::Int(i64::MAX));
+        assert_eq!(
+            SqlValue::from(u64::MAX),
+            SqlValue::Text("18446744073709551615".to_string())
+        );
+    }
+}
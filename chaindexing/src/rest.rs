@@ -0,0 +1,446 @@
+//! # REST
+//! Serves the registered state tables as paginated JSON collections, along
+//! with the indexing status, with the `rest` feature. It gets started by
+//! `index_states` when configured with `Config::with_rest_server`.
+//!
+//! - `GET /states` lists the state tables.
+//! - `GET /states/{table_name}` lists the table's states. They get filtered by
+//!   query parameters named after their columns, with `_not`, `_gt`, `_gte`,
+//!   `_lt`, `_lte`, `_like`, `_in`, `_not_in` and `_is_null` suffixes, and
+//!   paginated with `order_by`, `limit` and `offset`. `at_block` and `at_log_index`
+//!   read them as they were at a past block.
+//! - `GET /status` lists the ingesting and handling cursors of each chain's
+//!   contract addresses.
+//!
+//! ```text
+//! GET /states/nfts?owner_address=0xbc4c&token_id_in=1,2,3&order_by=token_id.desc&limit=20
+//! ```
+//!
+//! `_in` and `_not_in` values are comma-separated, and `order_by` ones are
+//! `{column}` or `{column}.asc` and `{column}.desc`, comma-separated too.
+//! NUMERIC(78, 0) values read as hex strings and get filtered by hex or decimal ones.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::routing::get;
+use axum::{Json, Router};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tokio::net::ToSocketAddrs;
+
+use crate::states::{self, AtBlock, Filters, Order, StateColumn, StateQueryClient};
+use crate::{
+    contracts, ChaindexingRepo, ChaindexingRepoClient, Config, HasRawQueryClient,
+    LoadsDataWithRawQuery, I256, U256,
+};
+
+/// Number of states returned when no limit is given
+pub const DEFAULT_LIMIT: u64 = 100;
+/// Largest number of states returned at once
+pub const MAX_LIMIT: u64 = 1_000;
+
+const FILTER_SUFFIXES: [&str; 9] = [
+    "_not_in", "_is_null", "_not", "_gte", "_gt", "_lte", "_lt", "_like", "_in",
+];
+
+/// Serves the config's state tables and indexing status until the server fails
+pub async fn serve<S: Send + Sync + Clone>(config: &Config<S>, addr: impl ToSocketAddrs) {
+    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
+
+    axum::serve(listener, router(config).await).await.unwrap();
+}
+
+/// Routes requests for the config's state tables and indexing status, for serving
+/// them along other routes. The state migrations must have run already.
+pub async fn router<S: Send + Sync + Clone>(config: &Config<S>) -> Router {
+    let state_migrations = contracts::get_state_migrations(&config.contracts);
+    let client = config.repo.get_client().await;
+
+    let mut tables = HashMap::new();
+    for table_name in states::get_all_table_names(&state_migrations) {
+        let columns = states::load_columns(&table_name, &client).await;
+
+        tables.insert(table_name, columns);
+    }
+
+    let server = Server {
+        tables,
+        query_client: StateQueryClient::new(&config.repo).await,
+        client,
+    };
+
+    Router::new()
+        .route("/states", get(list_tables))
+        .route("/states/{table_name}", get(list_states))
+        .route("/status", get(get_status))
+        .with_state(Arc::new(server))
+}
+
+struct Server {
+    tables: HashMap<String, Vec<StateColumn>>,
+    query_client: StateQueryClient,
+    client: ChaindexingRepoClient,
+}
+
+type ServerState = State<Arc<Server>>;
+type ErrorResponse = (StatusCode, Json<serde_json::Value>);
+
+fn error_response(status: StatusCode, error: impl ToString) -> ErrorResponse {
+    (status, Json(json!({ "error": error.to_string() })))
+}
+
+async fn list_tables(State(server): ServerState) -> Json<serde_json::Value> {
+    let mut table_names: Vec<_> = server.tables.keys().collect();
+    table_names.sort();
+
+    Json(json!({ "tables": table_names }))
+}
+
+async fn list_states(
+    State(server): ServerState,
+    Path(table_name): Path<String>,
+    Query(params): Query<Vec<(String, String)>>,
+) -> Result<Json<serde_json::Value>, ErrorResponse> {
+    let columns = server.tables.get(&table_name).ok_or_else(|| {
+        error_response(
+            StatusCode::NOT_FOUND,
+            format!("Unknown state table: {table_name}"),
+        )
+    })?;
+
+    let query = StatesQuery::parse(columns, &params)
+        .map_err(|error| error_response(StatusCode::BAD_REQUEST, error))?;
+
+    let states: Vec<serde_json::Value> = match &query.at {
+        Some(at) => server.query_client.read_many_at(&table_name, &query.filters, at).await,
+        None => server.query_client.read_many(&table_name, &query.filters).await,
+    };
+
+    Ok(Json(json!({
+        "data": states,
+        "limit": query.limit,
+        "offset": query.offset,
+    })))
+}
+
+/// Filters and pagination of a states request
+#[derive(Debug)]
+struct StatesQuery {
+    filters: Filters,
+    at: Option<AtBlock>,
+    limit: u64,
+    offset: u64,
+}
+
+impl StatesQuery {
+    fn parse(columns: &[StateColumn], params: &[(String, String)]) -> Result<Self, String> {
+        let get_column = |name: &str| {
+            columns
+                .iter()
+                .find(|column| column.name == name)
+                .ok_or_else(|| format!("Unknown column: {name}"))
+        };
+        let parse_number = |name: &str, value: &str| {
+            value.parse::<u64>().map_err(|_| format!("Invalid {name}: {value}"))
+        };
+
+        let mut filters = Filters::all();
+        let mut block_number = None;
+        let mut log_index = None;
+        let mut limit = DEFAULT_LIMIT;
+        let mut offset = 0;
+
+        for (name, value) in params {
+            match name.as_str() {
+                "limit" => limit = parse_number(name, value)?.min(MAX_LIMIT),
+                "offset" => offset = parse_number(name, value)?,
+                "at_block" => block_number = Some(parse_number(name, value)?),
+                "at_log_index" => log_index = Some(parse_number(name, value)? as u32),
+                "order_by" => {
+                    for ordering in value.split(',') {
+                        let (column, order) = match ordering.split_once('.') {
+                            Some((column, "asc")) => (column, Order::Asc),
+                            Some((column, "desc")) => (column, Order::Desc),
+                            Some(_) => return Err(format!("Invalid order_by: {ordering}")),
+                            None => (ordering, Order::Asc),
+                        };
+
+                        filters = filters.order_by(&get_column(column)?.name, order);
+                    }
+                }
+                name => {
+                    // Columns named like suffixed ones match as themselves
+                    let (column, suffix) = match get_column(name) {
+                        Ok(column) => (column, ""),
+                        Err(error) => FILTER_SUFFIXES
+                            .iter()
+                            .find_map(|suffix| {
+                                let column = get_column(name.strip_suffix(suffix)?).ok()?;
+
+                                Some((column, *suffix))
+                            })
+                            .ok_or(error)?,
+                    };
+
+                    filters = add_filter(filters, column, suffix, value)?;
+                }
+            }
+        }
+
+        let at = match (block_number, log_index) {
+            (Some(block_number), Some(log_index)) => {
+                Some(AtBlock::new(block_number).log_index(log_index))
+            }
+            (Some(block_number), None) => Some(AtBlock::new(block_number)),
+            (None, Some(_)) => return Err("at_log_index needs at_block".to_string()),
+            (None, None) => None,
+        };
+
+        Ok(Self {
+            filters: filters.limit(limit).offset(offset),
+            at,
+            limit,
+            offset,
+        })
+    }
+}
+
+fn add_filter(
+    filters: Filters,
+    column: &StateColumn,
+    suffix: &str,
+    value: &str,
+) -> Result<Filters, String> {
+    let field = &column.name;
+    let to_filter_value = |value: &str| {
+        to_filter_value(&column.data_type, value).ok_or_else(|| format!("Invalid {field}: {value}"))
+    };
+    let to_filter_values =
+        |values: &str| values.split(',').map(to_filter_value).collect::<Result<Vec<_>, _>>();
+
+    let filters = match suffix {
+        "" => filters.add(field, to_filter_value(value)?),
+        "_not" => filters.not_eq(field, to_filter_value(value)?),
+        "_gt" => filters.gt(field, to_filter_value(value)?),
+        "_gte" => filters.gte(field, to_filter_value(value)?),
+        "_lt" => filters.lt(field, to_filter_value(value)?),
+        "_lte" => filters.lte(field, to_filter_value(value)?),
+        "_like" if is_text(&column.data_type) => filters.like(field, value),
+        "_like" => return Err(format!("{field} is not a text column")),
+        "_in" => filters.is_in(field, to_filter_values(value)?),
+        "_not_in" => filters.not_in(field, to_filter_values(value)?),
+        _ => match value {
+            "true" => filters.is_null(field),
+            "false" => filters.is_not_null(field),
+            _ => return Err(format!("Invalid {field}_is_null: {value}")),
+        },
+    };
+
+    Ok(filters)
+}
+
+/// Checks the value against the column's type, since the states query client
+/// expects valid ones. NUMERIC columns compare with decimal values only.
+fn to_filter_value(data_type: &str, value: &str) -> Option<String> {
+    match (data_type, value.strip_prefix("0x")) {
+        ("numeric(78,0)", Some(hex)) => {
+            U256::from_str_radix(hex, 16).ok().map(|value| value.to_string())
+        }
+        ("numeric(78,0)", None) => I256::from_dec_str(value).ok().map(|value| value.to_string()),
+        _ => is_valid_filter_value(data_type, value).then(|| value.to_string()),
+    }
+}
+
+fn is_valid_filter_value(data_type: &str, value: &str) -> bool {
+    match data_type {
+        "smallint" => value.parse::<i16>().is_ok(),
+        "integer" => value.parse::<i32>().is_ok(),
+        "bigint" => value.parse::<i64>().is_ok(),
+        "real" | "double precision" => value.parse::<f64>().is_ok(),
+        data_type if data_type.starts_with("numeric") => {
+            value.parse::<f64>().is_ok_and(|value| value.is_finite())
+        }
+        "boolean" => value.parse::<bool>().is_ok(),
+        "json" | "jsonb" => serde_json::from_str::<serde_json::Value>(value).is_ok(),
+        _ => true,
+    }
+}
+
+fn is_text(data_type: &str) -> bool {
+    data_type == "text" || data_type.starts_with("character")
+}
+
+/// Indexing status of a chain
+#[derive(Debug, Serialize)]
+struct ChainStatus {
+    chain_id: i64,
+    /// Furthest block any of the chain's contract addresses got ingested up to.
+    /// Not the chain's head, which ingesting trails.
+    last_ingested_block_number: i64,
+    /// Block every one of the chain's contract addresses got handled up to
+    last_handled_block_number: i64,
+    contract_addresses: Vec<ContractAddressStatus>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ContractAddressStatus {
+    chain_id: i64,
+    address: String,
+    contract_name: String,
+    start_block_number: i64,
+    next_block_number_to_ingest_from: i64,
+    next_block_number_to_handle_from: i64,
+    next_block_number_for_side_effects: i64,
+}
+
+async fn get_status(State(server): ServerState) -> Json<serde_json::Value> {
+    let contract_addresses: Vec<ContractAddressStatus> = ChaindexingRepo::load_data_list(
+        &server.client,
+        "SELECT chain_id, address, contract_name, start_block_number,
+        next_block_number_to_ingest_from, next_block_number_to_handle_from,
+        next_block_number_for_side_effects
+        FROM chaindexing_contract_addresses
+        ORDER BY chain_id, id",
+    )
+    .await;
+
+    Json(json!({ "chains": to_chain_statuses(contract_addresses) }))
+}
+
+fn to_chain_statuses(contract_addresses: Vec<ContractAddressStatus>) -> Vec<ChainStatus> {
+    let mut contract_addresses_by_chain: BTreeMap<i64, Vec<ContractAddressStatus>> =
+        BTreeMap::new();
+    for contract_address in contract_addresses {
+        contract_addresses_by_chain
+            .entry(contract_address.chain_id)
+            .or_default()
+            .push(contract_address);
+    }
+
+    contract_addresses_by_chain
+        .into_iter()
+        .map(|(chain_id, contract_addresses)| ChainStatus {
+            chain_id,
+            last_ingested_block_number: contract_addresses
+                .iter()
+                .map(|c| c.next_block_number_to_ingest_from - 1)
+                .max()
+                .unwrap(),
+            last_handled_block_number: contract_addresses
+                .iter()
+                .map(|c| c.next_block_number_to_handle_from - 1)
+                .min()
+                .unwrap(),
+            contract_addresses,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn columns() -> Vec<StateColumn> {
+        [
+            ("token_id", "integer"),
+            ("owner", "text"),
+            ("owner_not", "text"),
+            ("amount", "numeric(78,0)"),
+        ]
+        .into_iter()
+        .map(|(name, data_type)| StateColumn {
+            name: name.to_string(),
+            data_type: data_type.to_string(),
+        })
+        .collect()
+    }
+
+    fn params(params: &[(&str, &str)]) -> Vec<(String, String)> {
+        params
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_filters_and_pagination() {
+        let query = StatesQuery::parse(
+            &columns(),
+            &params(&[
+                ("owner_not", "a"),
+                ("amount_gte", "0x1bc16d674ec80000"),
+                ("token_id_in", "1,2"),
+                ("order_by", "token_id.desc,owner"),
+                ("limit", "5000"),
+                ("offset", "20"),
+                ("at_block", "17000000"),
+            ]),
+        )
+        .unwrap();
+
+        let filters = format!("{:?}", query.filters);
+        assert!(filters.contains("field: \"owner_not\", operator: \"=\", value: \"a\""));
+        assert!(filters.contains("operator: \">=\", value: \"2000000000000000000\""));
+        assert!(filters.contains("values: [\"1\", \"2\"]"));
+        assert_eq!(query.limit, MAX_LIMIT);
+        assert_eq!(query.offset, 20);
+        assert_eq!(query.at, Some(AtBlock::new(17_000_000)));
+    }
+
+    #[test]
+    fn rejects_unknown_columns() {
+        assert_eq!(
+            StatesQuery::parse(&columns(), &params(&[("owner; DROP TABLE nfts", "a")]))
+                .unwrap_err(),
+            "Unknown column: owner; DROP TABLE nfts"
+        );
+        assert_eq!(
+            StatesQuery::parse(&columns(), &params(&[("order_by", "balance.desc")])).unwrap_err(),
+            "Unknown column: balance"
+        );
+    }
+
+    #[test]
+    fn rejects_values_not_matching_column_types() {
+        assert_eq!(
+            StatesQuery::parse(&columns(), &params(&[("token_id", "abc")])).unwrap_err(),
+            "Invalid token_id: abc"
+        );
+        assert_eq!(
+            StatesQuery::parse(&columns(), &params(&[("amount_in", "1,abc")])).unwrap_err(),
+            "Invalid amount: abc"
+        );
+        assert_eq!(
+            StatesQuery::parse(&columns(), &params(&[("token_id_like", "1%")])).unwrap_err(),
+            "token_id is not a text column"
+        );
+        assert!(StatesQuery::parse(&columns(), &params(&[("amount_lt", "-5")])).is_ok());
+    }
+
+    #[test]
+    fn summarizes_cursors_per_chain() {
+        let contract_address = |chain_id, next_to_ingest, next_to_handle| ContractAddressStatus {
+            chain_id,
+            address: "0xbc4c".to_string(),
+            contract_name: "BoredApeYachtClub".to_string(),
+            start_block_number: 100,
+            next_block_number_to_ingest_from: next_to_ingest,
+            next_block_number_to_handle_from: next_to_handle,
+            next_block_number_for_side_effects: 100,
+        };
+
+        let chain_statuses = to_chain_statuses(vec![
+            contract_address(1, 200, 150),
+            contract_address(1, 300, 120),
+            contract_address(137, 400, 400),
+        ]);
+
+        assert_eq!(chain_statuses.len(), 2);
+        assert_eq!(chain_statuses[0].last_ingested_block_number, 299);
+        assert_eq!(chain_statuses[0].last_handled_block_number, 119);
+        assert_eq!(chain_statuses[1].chain_id, 137);
+    }
+}
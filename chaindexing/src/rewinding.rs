@@ -0,0 +1,147 @@
+use std::fmt::Debug;
+
+use serde::Deserialize;
+
+use crate::{
+    contracts, states, ChainId, ChaindexingRepo, ChaindexingRepoTxnClient, Contract,
+    ExecutesWithRawQuery, LoadsDataWithRawQuery, SqlParams,
+};
+
+/// Rewinds that cannot apply to what got indexed
+pub enum RewindError {
+    UnknownContractAddress(String),
+}
+
+impl Debug for RewindError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RewindError::UnknownContractAddress(address) => {
+                write!(
+                    f,
+                    "Contract address {address} is not indexed on the rewound chain"
+                )
+            }
+        }
+    }
+}
+
+/// Specifies what gets rewound to a given block: a whole chain, or only some of
+/// its contract addresses. All state tables of the rewound contracts get rewound.
+/// Already-ingested events are kept by default so they get handled again
+/// without re-downloading them. SideEffectHandlers do not run again.
+///
+/// # Example
+/// ```
+/// use chaindexing::{ChainId, Rewind};
+///
+/// Rewind::new(&ChainId::Mainnet, 17_800_000)
+///     .for_contract_address("0xBC4CA0EdA7647A8aB7C2061c2E118A18a936f13D");
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rewind {
+    pub(crate) chain_id: ChainId,
+    pub(crate) block_number: u64,
+    pub(crate) contract_addresses: Vec<String>,
+    pub(crate) keep_events: bool,
+}
+
+impl Rewind {
+    /// Rewinds every contract address of the chain from `block_number` onwards
+    pub fn new(chain_id: &ChainId, block_number: u64) -> Self {
+        Self {
+            chain_id: *chain_id,
+            block_number,
+            contract_addresses: vec![],
+            keep_events: true,
+        }
+    }
+
+    /// Limits rewinding to the given contract address. Can be called repeatedly.
+    pub fn for_contract_address(mut self, address: &str) -> Self {
+        self.contract_addresses.push(address.to_lowercase());
+
+        self
+    }
+
+    /// Deletes the rewound events so they get ingested again
+    pub fn discard_events(mut self) -> Self {
+        self.keep_events = false;
+
+        self
+    }
+
+    async fn validate<'a>(&self, client: &ChaindexingRepoTxnClient<'a>) -> Result<(), RewindError> {
+        #[derive(Deserialize)]
+        struct IndexedContractAddress {
+            address: String,
+        }
+
+        if self.contract_addresses.is_empty() {
+            return Ok(());
+        }
+
+        let mut params = SqlParams::new();
+        let query = format!(
+            "SELECT address FROM chaindexing_contract_addresses WHERE chain_id = {}",
+            params.add(self.chain_id as i64)
+        );
+        let indexed_addresses: Vec<_> = ChaindexingRepo::load_data_list_in_txn_with_params::<
+            IndexedContractAddress,
+        >(client, &query, params.get_values())
+        .await
+        .into_iter()
+        .map(|contract_address| contract_address.address.to_lowercase())
+        .collect();
+
+        match self.contract_addresses.iter().find(|a| !indexed_addresses.contains(a)) {
+            Some(address) => Err(RewindError::UnknownContractAddress(address.clone())),
+            None => Ok(()),
+        }
+    }
+}
+
+pub async fn run<'a, S: Send + Sync + Clone>(
+    client: &ChaindexingRepoTxnClient<'a>,
+    rewind: &Rewind,
+    contracts: &[Contract<S>],
+) -> Result<(), RewindError> {
+    rewind.validate(client).await?;
+
+    let chain_id = rewind.chain_id as u64;
+    let block_number = rewind.block_number;
+    let contract_addresses = &rewind.contract_addresses;
+
+    let state_migrations = contracts::get_state_migrations(contracts);
+    let all_state_table_names = states::get_all_table_names(&state_migrations);
+
+    states::backtrack_states(
+        &all_state_table_names,
+        chain_id as i64,
+        block_number as i64,
+        contract_addresses,
+        client,
+    )
+    .await;
+
+    ChaindexingRepo::rewind_next_block_numbers_to_handle_from(
+        client,
+        chain_id,
+        contract_addresses,
+        block_number,
+    )
+    .await;
+
+    if !rewind.keep_events {
+        ChaindexingRepo::delete_events_from(client, chain_id, contract_addresses, block_number)
+            .await;
+        ChaindexingRepo::rewind_next_block_numbers_to_ingest_from(
+            client,
+            chain_id,
+            contract_addresses,
+            block_number,
+        )
+        .await;
+    }
+
+    Ok(())
+}
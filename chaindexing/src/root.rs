@@ -1,12 +1,22 @@
 pub mod states {
+    use std::collections::{HashMap, HashSet};
+
     use serde::Deserialize;
 
     pub const MAX_COUNT: u64 = 1_000;
 
+    /// Versions keyed by handler or state table, e.g. `{contract_name}:{event_abi}`
+    /// and `{contract_name}:{table_name}`
+    pub type Versions = HashMap<String, u64>;
+
     #[derive(Clone, Deserialize)]
     pub struct State {
         pub reset_count: u64,
         pub reset_including_side_effects_count: u64,
+        pub reindex_count: u64,
+        // None until versions get recorded for the first time
+        pub handler_versions: Option<Versions>,
+        pub state_migration_versions: Option<Versions>,
     }
 
     impl Default for State {
@@ -20,6 +30,9 @@ pub mod states {
             Self {
                 reset_count: 0,
                 reset_including_side_effects_count: 0,
+                reindex_count: 0,
+                handler_versions: None,
+                state_migration_versions: None,
             }
         }
 
@@ -29,6 +42,156 @@ pub mod states {
         pub fn update_reset_including_side_effects_count(&mut self, count: u64) {
             self.reset_including_side_effects_count = count;
         }
+        pub fn update_reindex_count(&mut self, count: u64) {
+            self.reindex_count = count;
+        }
+
+        pub fn get_changed_handler_versions(&self, handler_versions: &Versions) -> Vec<String> {
+            get_changed_versions(&self.handler_versions, handler_versions)
+        }
+        pub fn get_changed_state_migration_versions(
+            &self,
+            state_migration_versions: &Versions,
+        ) -> Vec<String> {
+            get_changed_versions(&self.state_migration_versions, state_migration_versions)
+        }
+
+        /// Handlers added since versions got recorded
+        pub fn get_new_handler_versions(&self, handler_versions: &Versions) -> Vec<String> {
+            match &self.handler_versions {
+                None => vec![],
+                Some(recorded_handler_versions) => handler_versions
+                    .keys()
+                    .filter(|key| !recorded_handler_versions.contains_key(*key))
+                    .cloned()
+                    .collect(),
+            }
+        }
+
+        /// State tables added to contracts whose tables got recorded before. Their
+        /// contracts have to replay already handled events to fill them in.
+        pub fn get_added_state_migration_versions(
+            &self,
+            state_migration_versions: &Versions,
+        ) -> Vec<String> {
+            let Some(recorded_versions) = &self.state_migration_versions else {
+                return vec![];
+            };
+            let recorded_contract_names: HashSet<_> =
+                recorded_versions.keys().filter_map(|key| get_contract_name(key)).collect();
+
+            state_migration_versions
+                .keys()
+                .filter(|key| {
+                    !recorded_versions.contains_key(*key)
+                        && get_contract_name(key)
+                            .is_some_and(|name| recorded_contract_names.contains(name))
+                })
+                .cloned()
+                .collect()
+        }
+
+        pub fn has_same_versions(
+            &self,
+            handler_versions: &Versions,
+            state_migration_versions: &Versions,
+        ) -> bool {
+            self.handler_versions.as_ref() == Some(handler_versions)
+                && self.state_migration_versions.as_ref() == Some(state_migration_versions)
+        }
+
+        pub fn update_versions(
+            &mut self,
+            handler_versions: &Versions,
+            state_migration_versions: &Versions,
+        ) {
+            self.handler_versions = Some(handler_versions.clone());
+            self.state_migration_versions = Some(state_migration_versions.clone());
+        }
+    }
+
+    fn get_contract_name(version_key: &str) -> Option<&str> {
+        version_key.rsplit_once(':').map(|(contract_name, _)| contract_name)
+    }
+
+    /// Only versions recorded previously can change. Newly added handlers
+    /// and migrations are simply recorded.
+    fn get_changed_versions(
+        recorded_versions: &Option<Versions>,
+        versions: &Versions,
+    ) -> Vec<String> {
+        match recorded_versions {
+            None => vec![],
+            Some(recorded_versions) => versions
+                .iter()
+                .filter(|(key, version)| {
+                    recorded_versions
+                        .get(*key)
+                        .is_some_and(|recorded_version| recorded_version != *version)
+                })
+                .map(|(key, _version)| key.to_owned())
+                .collect(),
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn returns_no_changed_versions_when_none_was_recorded() {
+            let root_state = State::new();
+            let handler_versions = HashMap::from([("Erc20:Transfer".to_string(), 2)]);
+
+            assert!(root_state.get_changed_handler_versions(&handler_versions).is_empty());
+        }
+
+        #[test]
+        fn returns_only_versions_that_changed() {
+            let mut root_state = State::new();
+            root_state.update_versions(
+                &HashMap::from([
+                    ("Erc20:Transfer".to_string(), 1),
+                    ("Erc20:Approval".to_string(), 1),
+                ]),
+                &HashMap::new(),
+            );
+
+            let handler_versions = HashMap::from([
+                ("Erc20:Transfer".to_string(), 2),
+                ("Erc20:Approval".to_string(), 1),
+                ("Erc721:Transfer".to_string(), 3),
+            ]);
+
+            assert_eq!(
+                root_state.get_changed_handler_versions(&handler_versions),
+                vec!["Erc20:Transfer".to_string()]
+            );
+            assert_eq!(
+                root_state.get_new_handler_versions(&handler_versions),
+                vec!["Erc721:Transfer".to_string()]
+            );
+        }
+
+        #[test]
+        fn returns_state_tables_added_to_recorded_contracts() {
+            let mut root_state = State::new();
+            root_state.update_versions(
+                &HashMap::new(),
+                &HashMap::from([("Erc20:balances".to_string(), 1)]),
+            );
+
+            let state_migration_versions = HashMap::from([
+                ("Erc20:balances".to_string(), 1),
+                ("Erc20:allowances".to_string(), 1),
+                ("Erc721:nfts".to_string(), 1),
+            ]);
+
+            assert_eq!(
+                root_state.get_added_state_migration_versions(&state_migration_versions),
+                vec!["Erc20:allowances".to_string()]
+            );
+        }
     }
 }
 
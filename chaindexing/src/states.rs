@@ -32,6 +32,67 @@
 //!     }
 //! }
 //! ```
+//!
+//! The same can be derived, with `NftMigrations` generated from the struct's fields:
+//!
+//! ```rust,ignore
+//! #[derive(Clone, Debug, Serialize, Deserialize, ContractState)]
+//! pub struct Nft {
+//!     #[unique]
+//!     pub token_id: u32,
+//!     #[index]
+//!     pub owner_address: String,
+//! }
+//! ```
+//!
+//! ## Column Types
+//! `U256` and `I256` fields map to `NUMERIC(78, 0)` columns and read back exactly.
+//! See [`i256`](crate::states::i256) for storing `I256` fields signed, which
+//! derived states require. Integers, booleans, nullable fields and JSONB columns
+//! round-trip as their serde types.
+//!
+//! ## Chaindexing Columns
+//! State tables also get the chain, contract address and event of each state's
+//! latest version, such as `block_number` and `block_timestamp`, along with the
+//! `created_at_block` and `created_at_timestamp` of its first version. States
+//! can declare fields with the same names to read them.
+//!
+//! ## Evolving States
+//! Migrations run once each, so states can evolve by appending ALTER TABLE
+//! migrations that add, drop or rename columns, or CREATE INDEX ones. They get
+//! applied to the state versions too, without resetting anything:
+//!
+//! ```rust,ignore
+//! &[
+//!     "CREATE TABLE IF NOT EXISTS nfts (token_id INTEGER NOT NULL)",
+//!     "ALTER TABLE nfts ADD COLUMN owner_address TEXT",
+//!     "CREATE INDEX IF NOT EXISTS nfts_owner_address_index ON nfts(owner_address)",
+//! ]
+//! ```
+//!
+//! ## Reading Outside Handlers
+//! API servers can read states with a
+//! [`StateQueryClient`](crate::states::StateQueryClient), whose session is
+//! read-only, scoping filters to chains and contracts explicitly:
+//!
+//! ```rust,ignore
+//! let query_client = StateQueryClient::new(&ChaindexingRepo::new(&database_url)).await;
+//! let filters = Filters::new("token_id", 9).for_contract_address(&ChainId::Mainnet, address);
+//! let nft: Option<Nft> = query_client.read_one(Nft::table_name(), &filters).await;
+//! ```
+//!
+//! ## Rollups
+//! Hourly or daily buckets, such as volumes or OHLC prices, can be declared as a
+//! [`Rollup`](crate::states::Rollup) instead of maintained by hand. Handlers
+//! record into the bucket of their event's block timestamp, and reorgs backtrack
+//! buckets like other states:
+//!
+//! ```rust,ignore
+//! let pool_volumes = Rollup::new("pool_volumes", 24 * 60 * 60)
+//!     .group_by("pool_address", "TEXT")
+//!     .sum("volume", "NUMERIC(78, 0)");
+//! pool_volumes.record(&RollupEntry::new().group("pool_address", pool).value("volume", amount), &context).await;
+//! ```
 pub use migrations::StateMigrations;
 
 use std::collections::HashMap;
@@ -41,20 +102,43 @@ mod migrations;
 mod state_versions;
 mod state_views;
 
+mod aggregates;
 mod chain_state;
 mod contract_state;
+mod fields;
 mod filters;
+mod history;
+pub mod i256;
+mod mcs_mutations;
 mod multi_chain_state;
+mod query_client;
+mod rollups;
 mod state;
+mod state_cache;
+mod state_changes;
 mod updates;
 
-pub use filters::Filters;
+pub use aggregates::{aggregate, aggregate_by, Aggregate};
+#[doc(hidden)]
+pub use fields::{has_column_for_every_field, has_compatible_field};
+pub use fields::{StateField, StateFieldKind, StateFields};
+pub use filters::{Filters, Order};
+pub use history::{read_history, read_many_at, read_one_at, AtBlock, VersionedState};
+pub use query_client::StateQueryClient;
+pub use rollups::{Rollup, RollupEntry};
+pub use state_cache::StateCache;
+pub use state_changes::{StateChange, StateChangeOp, StateChanges, STATE_CHANGES_CHANNEL};
 pub use updates::Updates;
 
+use serde::Deserialize;
+
 use crate::{
     ChaindexingRepo, ChaindexingRepoClient, ChaindexingRepoTxnClient, ExecutesWithRawQuery,
+    LoadsDataWithRawQuery, Migratable, SqlParams, SqlValue,
 };
 
+pub use chaindexing_macros::{ChainState, ContractState, MultiChainState, StateFields};
+
 pub use chain_state::ChainState;
 pub use contract_state::ContractState;
 pub use multi_chain_state::MultiChainState;
@@ -62,20 +146,98 @@ pub use multi_chain_state::MultiChainState;
 use state_versions::{StateVersion, StateVersions, STATE_VERSIONS_TABLE_PREFIX};
 use state_views::StateViews;
 
+/// Runs the user migrations not yet applied in the current schema, recording
+/// each in the ledger once its expanded migrations succeed.
+pub(crate) async fn run_migrations(
+    client: &ChaindexingRepoClient,
+    state_migrations: &[Arc<dyn StateMigrations>],
+) {
+    let applied_migrations = ChaindexingRepo::load_applied_state_migrations(client).await;
+
+    for state_migration in state_migrations {
+        for (user_migration, migrations) in
+            migrations::expand_migrations(state_migration.migrations())
+        {
+            if applied_migrations.iter().any(|m| m == user_migration) {
+                continue;
+            }
+
+            ChaindexingRepo::migrate(client, migrations).await;
+            ChaindexingRepo::append_applied_state_migration(client, user_migration).await;
+        }
+
+        ChaindexingRepo::migrate(
+            client,
+            migrations::get_default_columns_migrations(state_migration.migrations()),
+        )
+        .await;
+        ChaindexingRepo::migrate(
+            client,
+            migrations::get_default_indexes_migrations(state_migration.migrations()),
+        )
+        .await;
+    }
+}
+
+/// Applies the MultiChainState mutations queued in the client's current schema
+#[doc(hidden)]
+pub async fn apply_mcs_mutations(client: &mut ChaindexingRepoClient) {
+    mcs_mutations::apply(client).await;
+}
+
+/// Makes the client's session notify the state changes it commits
+pub(crate) async fn enable_state_changes(client: &ChaindexingRepoClient) {
+    state_changes::enable(client).await;
+}
+
+/// Drops the state tables and forgets their migrations, so they run again
+pub(crate) async fn reset_migrations(
+    client: &ChaindexingRepoClient,
+    state_migrations: &[Arc<dyn StateMigrations>],
+) {
+    for state_migration in state_migrations {
+        ChaindexingRepo::migrate(client, state_migration.get_reset_migrations()).await;
+        ChaindexingRepo::delete_applied_state_migrations(client, state_migration.migrations())
+            .await;
+    }
+}
+
+/// Backtracks states of the given contract addresses, or of every address
+/// in the chain when none is given.
 pub(crate) async fn backtrack_states<'a>(
-    table_names: &Vec<String>,
+    table_names: &[String],
     chain_id: i64,
     block_number: i64,
+    contract_addresses: &[String],
     client: &ChaindexingRepoTxnClient<'a>,
 ) {
+    mcs_mutations::discard(chain_id, block_number, contract_addresses, client).await;
+
     for table_name in table_names {
-        let state_versions = StateVersions::get(block_number, chain_id, table_name, client).await;
+        let state_versions = StateVersions::get(
+            block_number,
+            chain_id,
+            contract_addresses,
+            table_name,
+            client,
+        )
+        .await;
+
+        if state_versions.is_empty() {
+            continue;
+        }
 
         let state_version_ids = StateVersions::get_ids(&state_versions);
         StateVersions::delete_by_ids(&state_version_ids, table_name, client).await;
 
         let state_version_group_ids = StateVersions::get_group_ids(&state_versions);
-        StateViews::refresh(&state_version_group_ids, table_name, client).await;
+        StateViews::refresh(
+            &state_version_group_ids,
+            table_name,
+            (chain_id, block_number),
+            client,
+        )
+        .await;
     }
 }
 
@@ -109,25 +271,36 @@ pub(crate) fn get_all_table_names(state_migrations: &[Arc<dyn StateMigrations>])
         .collect()
 }
 
-pub(crate) fn to_columns_and_values(state: &HashMap<String, String>) -> (Vec<String>, Vec<String>) {
+pub(crate) fn get_state_versions_table_name(table_name: &str) -> String {
+    StateVersion::table_name(table_name)
+}
+
+pub(crate) fn to_columns_and_values(
+    state: &HashMap<String, SqlValue>,
+    params: &mut SqlParams,
+) -> (Vec<String>, Vec<String>) {
     state.iter().fold(
         (vec![], vec![]),
         |(mut columns, mut values), (column, value)| {
             columns.push(column.to_string());
-            values.push(format!("'{value}'"));
+            values.push(params.add(value.clone()));
 
             (columns, values)
         },
     )
 }
 
-pub(crate) fn to_and_filters(
-    state: &HashMap<impl ToString + Send, impl ToString + Send>,
-) -> String {
+pub(crate) fn to_and_filters(state: &HashMap<String, SqlValue>, params: &mut SqlParams) -> String {
     let filters = state.iter().fold(vec![], |mut filters, (column, value)| {
-        let column = column.to_string();
-        let value = value.to_string();
-        filters.push(format!("{column} = '{value}'"));
+        match value {
+            SqlValue::Null => filters.push(format!("{column} IS NULL")),
+            // JSON columns don't support equality, unlike JSONB ones
+            SqlValue::Json(_) => filters.push(format!(
+                "{column}::JSONB = {}::JSONB",
+                params.add(value.clone())
+            )),
+            _ => filters.push(format!("{column} = {}", params.add(value.clone()))),
+        }
 
         filters
     });
@@ -135,18 +308,135 @@ pub(crate) fn to_and_filters(
     filters.join(" AND ")
 }
 
-pub(crate) fn serde_map_to_string_map(
+pub(crate) fn serde_map_to_sql_map(
     serde_map: &HashMap<impl AsRef<str>, serde_json::Value>,
-) -> HashMap<String, String> {
-    serde_map.iter().fold(HashMap::new(), |mut map, (key, value)| {
-        if !value.is_null() {
-            if value.is_object() {
-                map.insert(key.as_ref().to_owned(), value.to_string());
-            } else {
-                map.insert(key.as_ref().to_owned(), value.to_string().replace('\"', ""));
-            }
-        }
+) -> HashMap<String, SqlValue> {
+    serde_map
+        .iter()
+        .map(|(key, value)| (key.as_ref().to_owned(), serde_value_to_sql_value(value)))
+        .collect()
+}
 
-        map
-    })
+fn serde_value_to_sql_value(value: &serde_json::Value) -> SqlValue {
+    match value {
+        serde_json::Value::Null => SqlValue::Null,
+        serde_json::Value::Bool(value) => SqlValue::Bool(*value),
+        serde_json::Value::Number(number) => match number.as_i64() {
+            Some(number) => SqlValue::Int(number),
+            None => SqlValue::Text(number.to_string()),
+        },
+        serde_json::Value::String(value) => SqlValue::Text(value.to_owned()),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => SqlValue::Json(value.clone()),
+    }
+}
+
+/// Selects the row as a JSON object, with numbers as strings for NUMERIC values,
+/// such as U256 ones, to get copied between state tables exactly
+pub(crate) fn to_exact_json(row: &str) -> String {
+    format!(
+        "(SELECT jsonb_object_agg(field.key, CASE jsonb_typeof(field.value)
+            WHEN 'number' THEN to_jsonb(field.value #>> '{{}}') ELSE field.value END)
+        FROM jsonb_each(to_jsonb({row})) field) AS state"
+    )
+}
+
+/// Selects the row as a JSON object, with NUMERIC(78, 0) values as the hex strings
+/// U256 and I256 fields deserialize from
+pub(crate) fn to_typed_json(row: &str, table_name: &str, params: &mut SqlParams) -> String {
+    format!("{} AS state", to_typed_json_value(row, table_name, params))
+}
+
+/// Same as `to_typed_json`, as an expression to use within others
+pub(crate) fn to_typed_json_value(row: &str, table_name: &str, params: &mut SqlParams) -> String {
+    format!(
+        "(SELECT jsonb_object_agg(field.key, CASE
+            WHEN attribute.atttypid = 'NUMERIC'::REGTYPE AND attribute.atttypmod = {U256_TYPMOD}
+            THEN to_jsonb(chaindexing_numeric_to_hex((field.value #>> '{{}}')::NUMERIC))
+            ELSE field.value END)
+        FROM jsonb_each(to_jsonb({row})) field
+        LEFT JOIN pg_attribute attribute
+        ON attribute.attrelid = {table_name}::REGCLASS AND attribute.attname = field.key)",
+        table_name = params.add(table_name)
+    )
+}
+
+/// Column of a state table, as read from the database
+#[cfg(any(feature = "graphql", feature = "rest"))]
+#[derive(Clone, Debug, Deserialize)]
+pub(crate) struct StateColumn {
+    pub name: String,
+    /// As formatted by Postgres, such as `integer` or `numeric(78,0)`
+    pub data_type: String,
+}
+
+/// Loads the columns of the state table, including Chaindexing's
+#[cfg(any(feature = "graphql", feature = "rest"))]
+pub(crate) async fn load_columns(
+    table_name: &str,
+    client: &ChaindexingRepoClient,
+) -> Vec<StateColumn> {
+    ChaindexingRepo::load_data_list_with_params(
+        client,
+        "SELECT attname AS name, format_type(atttypid, atttypmod) AS data_type
+        FROM pg_attribute
+        WHERE attrelid = $1::REGCLASS AND attnum > 0 AND NOT attisdropped
+        ORDER BY attnum",
+        &[table_name.into()],
+    )
+    .await
+}
+
+/// Type modifier of NUMERIC(78, 0), wide enough for every U256 and I256
+const U256_TYPMOD: i32 = (78 << 16) + 4;
+
+/// Row of a query selecting `to_exact_json` or `to_typed_json`
+#[derive(Deserialize)]
+pub(crate) struct StateRow<T> {
+    pub state: T,
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn keeps_the_types_of_serde_values() {
+        let serde_map = HashMap::from([
+            ("balance", json!("0x1bc16d674ec80000")),
+            ("token_id", json!(3)),
+            ("price", json!(1.5)),
+            ("is_listed", json!(true)),
+            ("owner", json!(null)),
+            ("metadata", json!({"name": "Ape"})),
+        ]);
+
+        assert_eq!(
+            serde_map_to_sql_map(&serde_map),
+            HashMap::from([
+                (
+                    "balance".to_string(),
+                    SqlValue::Text("0x1bc16d674ec80000".to_string())
+                ),
+                ("token_id".to_string(), SqlValue::Int(3)),
+                ("price".to_string(), SqlValue::Text("1.5".to_string())),
+                ("is_listed".to_string(), SqlValue::Bool(true)),
+                ("owner".to_string(), SqlValue::Null),
+                (
+                    "metadata".to_string(),
+                    SqlValue::Json(json!({"name": "Ape"}))
+                ),
+            ])
+        );
+    }
+
+    #[test]
+    fn filters_null_values_by_nullness() {
+        let mut params = SqlParams::new();
+        let state = HashMap::from([("owner".to_string(), SqlValue::Null)]);
+
+        assert_eq!(to_and_filters(&state, &mut params), "owner IS NULL");
+        assert!(params.get_values().is_empty());
+    }
 }
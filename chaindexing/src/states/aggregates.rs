@@ -0,0 +1,344 @@
+use crate::handlers::HandlerContext;
+use crate::{ChaindexingRepo, ChaindexingRepoClient, LoadsDataWithRawQuery, SqlParams};
+
+use super::filters::{Filters, Order};
+use super::U256_TYPMOD;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Aggregate computed in SQL over the states matching filters. Sums, minimums
+/// and maximums of NUMERIC(78, 0) fields read back as U256 values.
+///
+/// # Example
+///
+/// ```ignore
+/// let supply: U256 = TokenBalance::aggregate(&Aggregate::sum("amount"), &filters, &context).await;
+/// let max_tick: Option<i32> = Pool::aggregate(&Aggregate::max("tick"), &filters, &context).await;
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Aggregate {
+    /// Number of states, as an integer
+    Count,
+    /// Sum of the field, 0 when no state matches
+    Sum(String),
+    /// Smallest value of the field, None when no state matches
+    Min(String),
+    /// Largest value of the field, None when no state matches
+    Max(String),
+    /// Average of the field, as a float, None when no state matches
+    Avg(String),
+}
+
+impl Aggregate {
+    pub fn count() -> Self {
+        Aggregate::Count
+    }
+
+    pub fn sum(field: impl ToString) -> Self {
+        Aggregate::Sum(field.to_string())
+    }
+
+    pub fn min(field: impl ToString) -> Self {
+        Aggregate::Min(field.to_string())
+    }
+
+    pub fn max(field: impl ToString) -> Self {
+        Aggregate::Max(field.to_string())
+    }
+
+    pub fn avg(field: impl ToString) -> Self {
+        Aggregate::Avg(field.to_string())
+    }
+
+    fn to_sql(&self) -> String {
+        match self {
+            Aggregate::Count => "COUNT(*)".to_string(),
+            Aggregate::Sum(field) => format!("COALESCE(SUM({field}), 0)"),
+            Aggregate::Min(field) => format!("MIN({field})"),
+            Aggregate::Max(field) => format!("MAX({field})"),
+            Aggregate::Avg(field) => format!("AVG({field})::FLOAT8"),
+        }
+    }
+
+    fn to_json_sql(&self, value: &str, table_name: &str, params: &mut SqlParams) -> String {
+        match self {
+            Aggregate::Count | Aggregate::Avg(_) => format!("to_jsonb({value})"),
+            Aggregate::Sum(field) | Aggregate::Min(field) | Aggregate::Max(field) => {
+                to_typed_value_json(value, field, table_name, params)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct AggregateRow<V> {
+    chaindexing_value: V,
+}
+
+#[derive(Deserialize)]
+struct GroupedAggregateRow<K, V> {
+    chaindexing_key: K,
+    chaindexing_value: V,
+}
+
+pub(super) async fn aggregate_in_context<'a, C: HandlerContext<'a>, V: Send + DeserializeOwned>(
+    aggregate: &Aggregate,
+    filters: &Filters,
+    context: &C,
+    table_name: &str,
+) -> V {
+    let client = context.get_client();
+
+    if let Some(state_cache) = context.get_state_cache() {
+        state_cache.flush_table(table_name, client).await;
+    }
+
+    let mut params = SqlParams::new();
+    let filters = filters.to_where_sql(context.get_event(), &mut params);
+    let query = aggregate_query(aggregate, &filters, table_name, &mut params);
+
+    ChaindexingRepo::load_data_in_txn_with_params::<AggregateRow<V>>(
+        client,
+        &query,
+        params.get_values(),
+    )
+    .await
+    .unwrap()
+    .chaindexing_value
+}
+
+pub(super) async fn aggregate_by_in_context<
+    'a,
+    C: HandlerContext<'a>,
+    K: Send + DeserializeOwned,
+    V: Send + DeserializeOwned,
+>(
+    group_field: &str,
+    aggregate: &Aggregate,
+    filters: &Filters,
+    context: &C,
+    table_name: &str,
+) -> Vec<(K, V)> {
+    let client = context.get_client();
+
+    if let Some(state_cache) = context.get_state_cache() {
+        state_cache.flush_table(table_name, client).await;
+    }
+
+    let mut params = SqlParams::new();
+    let where_sql = filters.to_where_sql(context.get_event(), &mut params);
+    let query = aggregate_by_query(
+        group_field,
+        aggregate,
+        &where_sql,
+        filters,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_in_txn_with_params::<GroupedAggregateRow<K, V>>(
+        client,
+        &query,
+        params.get_values(),
+    )
+    .await
+    .into_iter()
+    .map(|row| (row.chaindexing_key, row.chaindexing_value))
+    .collect()
+}
+
+/// Returns the aggregate over the table's states matching filters, outside of handlers.
+/// Like `read_many_at`, the filters aren't scoped to any chain or contract.
+///
+/// # Example
+///
+/// ```ignore
+/// let holders: i64 = states::aggregate(
+///     TokenBalance::table_name(),
+///     &Aggregate::count(),
+///     &Filters::new("chain_id", 1).gt("amount", 0),
+///     &client,
+/// )
+/// .await;
+/// ```
+pub async fn aggregate<V: Send + DeserializeOwned>(
+    table_name: &str,
+    aggregate: &Aggregate,
+    filters: &Filters,
+    client: &ChaindexingRepoClient,
+) -> V {
+    let mut params = SqlParams::new();
+    let filters = filters.to_unscoped_where_sql(&mut params);
+    let query = aggregate_query(aggregate, &filters, table_name, &mut params);
+
+    ChaindexingRepo::load_data_with_params::<AggregateRow<V>>(client, &query, params.get_values())
+        .await
+        .unwrap()
+        .chaindexing_value
+}
+
+/// Returns the aggregate over the table's states matching filters for each value
+/// of the group field, outside of handlers. The filters' orderings and limits apply
+/// to the groups, by the group field or by `value`, and ties get ordered by the group field.
+///
+/// # Example
+///
+/// ```ignore
+/// // Top 10 holders
+/// let filters = Filters::new("chain_id", 1).order_by("value", Order::Desc).limit(10);
+/// let holders: Vec<(String, U256)> = states::aggregate_by(
+///     TokenBalance::table_name(),
+///     "owner",
+///     &Aggregate::sum("amount"),
+///     &filters,
+///     &client,
+/// )
+/// .await;
+/// ```
+pub async fn aggregate_by<K: Send + DeserializeOwned, V: Send + DeserializeOwned>(
+    table_name: &str,
+    group_field: &str,
+    aggregate: &Aggregate,
+    filters: &Filters,
+    client: &ChaindexingRepoClient,
+) -> Vec<(K, V)> {
+    let mut params = SqlParams::new();
+    let where_sql = filters.to_unscoped_where_sql(&mut params);
+    let query = aggregate_by_query(
+        group_field,
+        aggregate,
+        &where_sql,
+        filters,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_with_params::<GroupedAggregateRow<K, V>>(
+        client,
+        &query,
+        params.get_values(),
+    )
+    .await
+    .into_iter()
+    .map(|row| (row.chaindexing_key, row.chaindexing_value))
+    .collect()
+}
+
+/// Aggregates the raw values before converting them to JSON, so NUMERIC ones
+/// don't lose precision
+fn aggregate_query(
+    aggregate: &Aggregate,
+    filters: &str,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    format!(
+        "SELECT {value} AS chaindexing_value FROM (
+            SELECT {aggregate} AS value FROM {table_name} WHERE {filters}
+        ) chaindexing_aggregate",
+        value = aggregate.to_json_sql("value", table_name, params),
+        aggregate = aggregate.to_sql(),
+    )
+}
+
+/// Orders the groups by their raw values, as the JSON ones of U256 fields
+/// wouldn't order numerically
+fn aggregate_by_query(
+    group_field: &str,
+    aggregate: &Aggregate,
+    where_sql: &str,
+    filters: &Filters,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    let orderings = filters.clone().order_by(group_field, Order::Asc).to_ordering_sql();
+
+    format!(
+        "SELECT {key} AS chaindexing_key, {value} AS chaindexing_value FROM (
+            SELECT {group_field}, {aggregate} AS value FROM {table_name}
+            WHERE {where_sql}
+            GROUP BY {group_field}
+        ) chaindexing_aggregate{orderings}",
+        key = to_typed_value_json(group_field, group_field, table_name, params),
+        value = aggregate.to_json_sql("value", table_name, params),
+        aggregate = aggregate.to_sql(),
+    )
+}
+
+/// Converts the value to JSON, as the hex string U256 and I256 deserialize from
+/// when the field is NUMERIC(78, 0), like `to_typed_json`
+fn to_typed_value_json(
+    value: &str,
+    field: &str,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    format!(
+        "CASE WHEN EXISTS (
+            SELECT 1 FROM pg_attribute attribute
+            WHERE attribute.attrelid = {table_name}::REGCLASS
+            AND attribute.attname = {field}
+            AND attribute.atttypid = 'NUMERIC'::REGTYPE AND attribute.atttypmod = {U256_TYPMOD}
+        )
+        THEN to_jsonb(chaindexing_numeric_to_hex({value}::TEXT::NUMERIC))
+        ELSE to_jsonb({value}) END",
+        table_name = params.add(table_name),
+        field = params.add(field)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn aggregates_states_matching_filters() {
+        let mut params = SqlParams::new();
+        let filters = Filters::new("owner", "a").order_by("amount", Order::Desc).limit(1);
+        let where_sql = filters.to_unscoped_where_sql(&mut params);
+        let query = aggregate_query(
+            &Aggregate::sum("amount"),
+            &where_sql,
+            "token_balances",
+            &mut params,
+        );
+
+        assert!(query.contains(
+            "SELECT COALESCE(SUM(amount), 0) AS value FROM token_balances WHERE owner = $1"
+        ));
+        assert!(!query.contains("LIMIT"));
+        assert_eq!(
+            params.get_values(),
+            vec!["a".into(), "token_balances".into(), "amount".into()]
+        );
+    }
+
+    #[test]
+    fn orders_groups_by_their_raw_values() {
+        let mut params = SqlParams::new();
+        let filters = Filters::all().order_by("value", Order::Desc).limit(10);
+        let where_sql = filters.to_unscoped_where_sql(&mut params);
+        let query = aggregate_by_query(
+            "owner",
+            &Aggregate::count(),
+            &where_sql,
+            &filters,
+            "token_balances",
+            &mut params,
+        );
+
+        assert!(query.contains("SELECT owner, COUNT(*) AS value FROM token_balances"));
+        assert!(query.contains("GROUP BY owner"));
+        assert!(query.ends_with(") chaindexing_aggregate ORDER BY value DESC, owner ASC LIMIT 10"));
+        assert!(query.starts_with("SELECT CASE WHEN EXISTS"));
+    }
+
+    #[test]
+    fn reads_averages_as_floats() {
+        assert_eq!(Aggregate::avg("tick").to_sql(), "AVG(tick)::FLOAT8");
+        assert_eq!(
+            Aggregate::avg("tick").to_json_sql("value", "pools", &mut SqlParams::new()),
+            "to_jsonb(value)"
+        );
+    }
+}
@@ -2,9 +2,11 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 
 use crate::handlers::{HandlerContext, PureHandlerContext};
-use crate::{ChaindexingRepoTxnClient, Event};
+use crate::{ChaindexingRepoTxnClient, Event, SqlValue};
 
+use super::aggregates::{self, Aggregate};
 use super::filters::Filters;
+use super::history::{self, AtBlock, VersionedState};
 use super::state;
 use super::state::read_many;
 use super::state_versions::StateVersion;
@@ -21,9 +23,23 @@ pub trait ContractState:
     /// Table of the state as specified in StateMigrations
     fn table_name() -> &'static str;
 
+    /// Fields identifying the state, such as `&["token_id"]`. Updates and deletes
+    /// find the state by them, within the event's contract address, instead of by all of its fields,
+    /// so stale copies still update the right state. Defaults to none.
+    fn identity_fields() -> &'static [&'static str] {
+        &[]
+    }
+
     /// Inserts state in the state's table
     async fn create<'a, 'b>(&self, context: &PureHandlerContext<'a, 'b>) {
-        state::create(Self::table_name(), &state::to_view(self), context).await;
+        let table_name = Self::table_name();
+        let group_id = state::get_group_id(
+            table_name,
+            Self::identity_fields(),
+            &self.to_identity_view(&context.event),
+        );
+
+        state::create(table_name, &state::to_view(self), group_id, context).await;
     }
 
     /// Returns a single state matching filters. Panics if there are multiple.
@@ -36,17 +52,106 @@ pub trait ContractState:
         read_many(filters, context, Self::table_name()).await
     }
 
+    /// Returns a single state matching filters as it was at the given block
+    async fn read_one_at<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        at: &AtBlock,
+        context: &C,
+    ) -> Option<Self> {
+        Self::read_many_at(filters, at, context).await.first().cloned()
+    }
+
+    /// Returns states matching filters as they were at the given block,
+    /// rebuilt from their versions
+    async fn read_many_at<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        at: &AtBlock,
+        context: &C,
+    ) -> Vec<Self> {
+        history::read_many_at_in_context(filters, at, context, Self::table_name()).await
+    }
+
+    /// Returns every version of the states matching filters, along with the events
+    /// that produced them. Filters match versions, ordered as their events happened.
+    async fn read_history<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        context: &C,
+    ) -> Vec<VersionedState<Self>> {
+        history::read_history_in_context(filters, context, Self::table_name()).await
+    }
+
+    /// Returns the number of states matching filters
+    async fn count<'a, C: HandlerContext<'a>>(filters: &Filters, context: &C) -> i64 {
+        aggregates::aggregate_in_context(&Aggregate::Count, filters, context, Self::table_name())
+            .await
+    }
+
+    /// Returns the aggregate over the states matching filters, computed in SQL.
+    /// Their orderings and limits get ignored.
+    async fn aggregate<'a, C: HandlerContext<'a>, V: Send + DeserializeOwned>(
+        aggregate: &Aggregate,
+        filters: &Filters,
+        context: &C,
+    ) -> V {
+        aggregates::aggregate_in_context(aggregate, filters, context, Self::table_name()).await
+    }
+
+    /// Returns the aggregate over the states matching filters for each value of the
+    /// group field. Their orderings and limits apply to the groups, by the group field
+    /// or by `value`.
+    async fn aggregate_by<
+        'a,
+        C: HandlerContext<'a>,
+        K: Send + DeserializeOwned,
+        V: Send + DeserializeOwned,
+    >(
+        group_field: &str,
+        aggregate: &Aggregate,
+        filters: &Filters,
+        context: &C,
+    ) -> Vec<(K, V)> {
+        aggregates::aggregate_by_in_context(
+            group_field,
+            aggregate,
+            filters,
+            context,
+            Self::table_name(),
+        )
+        .await
+    }
+
     /// Updates state with the specified updates
     async fn update<'a, 'b>(&self, updates: &Updates, context: &PureHandlerContext<'a, 'b>) {
         let event = &context.event;
         let client = context.repo_client;
 
         let table_name = Self::table_name();
-        let state_view = self.to_complete_view(table_name, client, event).await;
+        let state_view = self.get_complete_view(context).await;
 
         let latest_state_version =
-            StateVersion::update(&state_view, &updates.values, table_name, event, client).await;
-        StateView::refresh(&latest_state_version, table_name, client).await;
+            StateVersion::update(&state_view, updates, table_name, event, client).await;
+        state::refresh_view(&latest_state_version, table_name, context).await;
+    }
+
+    /// Creates the state, or applies the updates to it if it already exists.
+    /// Finds it by its identity fields, which it should declare.
+    async fn create_or_update<'a, 'b>(
+        &self,
+        updates: &Updates,
+        context: &PureHandlerContext<'a, 'b>,
+    ) {
+        let event = &context.event;
+        let client = context.repo_client;
+        let table_name = Self::table_name();
+
+        match self.find_complete_view(context).await {
+            Some(state_view) => {
+                let latest_state_version =
+                    StateVersion::update(&state_view, updates, table_name, event, client).await;
+                state::refresh_view(&latest_state_version, table_name, context).await;
+            }
+            None => self.create(context).await,
+        }
     }
 
     /// Deletes state from the state's table
@@ -55,29 +160,53 @@ pub trait ContractState:
         let client = context.repo_client;
 
         let table_name = Self::table_name();
-        let state_view = self.to_complete_view(table_name, client, event).await;
+        let state_view = self.get_complete_view(context).await;
 
         let latest_state_version =
             StateVersion::delete(&state_view, table_name, event, client).await;
-        StateView::refresh(&latest_state_version, table_name, client).await;
+        state::refresh_view(&latest_state_version, table_name, context).await;
     }
 
-    fn to_view(&self) -> HashMap<String, String> {
+    fn to_view(&self) -> HashMap<String, SqlValue> {
         state::to_view(self)
     }
 
+    fn to_identity_view(&self, event: &Event) -> HashMap<String, SqlValue> {
+        let mut identity_view = state::to_identity_view(&self.to_view(), Self::identity_fields());
+        identity_view.insert("chain_id".to_string(), event.chain_id.into());
+        identity_view.insert(
+            "contract_address".to_string(),
+            event.contract_address.as_str().into(),
+        );
+        identity_view
+    }
+
+    async fn find_complete_view<'a, 'b>(
+        &self,
+        context: &PureHandlerContext<'a, 'b>,
+    ) -> Option<HashMap<String, SqlValue>> {
+        state::find_complete_view(
+            Self::table_name(),
+            Self::identity_fields(),
+            &self.to_identity_view(&context.event),
+            context,
+        )
+        .await
+    }
+
+    async fn get_complete_view<'a, 'b>(
+        &self,
+        context: &PureHandlerContext<'a, 'b>,
+    ) -> HashMap<String, SqlValue> {
+        self.find_complete_view(context).await.unwrap()
+    }
+
     async fn to_complete_view<'a>(
         &self,
         table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
         event: &Event,
-    ) -> HashMap<String, String> {
-        let mut state_view = self.to_view();
-        state_view.insert("chain_id".to_string(), event.chain_id.to_string());
-        state_view.insert(
-            "contract_address".to_string(),
-            event.contract_address.to_owned(),
-        );
-        StateView::get_complete(&state_view, table_name, client).await
+    ) -> HashMap<String, SqlValue> {
+        StateView::get_complete(&self.to_identity_view(event), table_name, client).await
     }
 }
@@ -0,0 +1,196 @@
+/// Fields of a state, for `state_migrations!(State, [...])` to check the state's
+/// CREATE TABLE migration against at compile time. Derive it with
+/// `#[derive(StateFields)]`.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// #[derive(Clone, Debug, Serialize, Deserialize, StateFields)]
+/// pub struct Nft {
+///     pub token_id: u32,
+///     pub owner_address: String,
+/// }
+///
+/// const NFT_MIGRATIONS: &[&str] = state_migrations!(Nft, [
+///     "CREATE TABLE IF NOT EXISTS nfts (
+///         token_id BIGINT NOT NULL,
+///         owner_address TEXT NOT NULL
+///     )"
+/// ]);
+/// ```
+pub trait StateFields {
+    /// Fields as they get serialized, in declaration order
+    const FIELDS: &'static [StateField];
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StateField {
+    pub name: &'static str,
+    pub kind: StateFieldKind,
+    /// Whether the field is an `Option`
+    pub is_nullable: bool,
+}
+
+/// Kinds of values fields serialize to, and columns store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateFieldKind {
+    Bool,
+    Integer,
+    /// Integers wider than 64 bits, such as U256 and I256
+    Numeric,
+    Float,
+    Text,
+    Json,
+    /// Not checked for compatibility, e.g. custom types or UUID columns
+    Other,
+}
+
+impl StateFieldKind {
+    const fn is_compatible_with(self, column_kind: StateFieldKind) -> bool {
+        match (self, column_kind) {
+            (StateFieldKind::Other, _) | (_, StateFieldKind::Other) => true,
+            (StateFieldKind::Integer, StateFieldKind::Numeric) => true,
+            (StateFieldKind::Float, StateFieldKind::Numeric) => true,
+            // U256 and I256 fields serialize as hex
+            (StateFieldKind::Numeric, StateFieldKind::Text) => true,
+            (field_kind, column_kind) => field_kind as u8 == column_kind as u8,
+        }
+    }
+}
+
+/// Whether the column has a compatible field. Columns filled by Chaindexing
+/// or the database don't need one.
+#[doc(hidden)]
+pub const fn has_compatible_field(
+    fields: &[StateField],
+    column_name: &str,
+    column_kind: StateFieldKind,
+    is_column_nullable: bool,
+    is_column_required: bool,
+) -> bool {
+    match find_field(fields, column_name) {
+        Some(field) => {
+            field.kind.is_compatible_with(column_kind) && (!field.is_nullable || is_column_nullable)
+        }
+        None => !is_column_required,
+    }
+}
+
+/// Whether every field has a column
+#[doc(hidden)]
+pub const fn has_column_for_every_field(fields: &[StateField], column_names: &[&str]) -> bool {
+    let mut field_index = 0;
+
+    while field_index < fields.len() {
+        let mut has_column = false;
+        let mut column_index = 0;
+
+        while column_index < column_names.len() {
+            if str_eq(fields[field_index].name, column_names[column_index]) {
+                has_column = true;
+            }
+            column_index += 1;
+        }
+
+        if !has_column {
+            return false;
+        }
+        field_index += 1;
+    }
+
+    true
+}
+
+const fn find_field(fields: &[StateField], name: &str) -> Option<StateField> {
+    let mut index = 0;
+
+    while index < fields.len() {
+        if str_eq(fields[index].name, name) {
+            return Some(fields[index]);
+        }
+        index += 1;
+    }
+
+    None
+}
+
+const fn str_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+
+    if a.len() != b.len() {
+        return false;
+    }
+
+    let mut index = 0;
+    while index < a.len() {
+        if a[index] != b[index] {
+            return false;
+        }
+        index += 1;
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIELDS: &[StateField] = &[
+        StateField {
+            name: "token_id",
+            kind: StateFieldKind::Integer,
+            is_nullable: false,
+        },
+        StateField {
+            name: "note",
+            kind: StateFieldKind::Text,
+            is_nullable: true,
+        },
+    ];
+
+    #[test]
+    fn checks_columns_against_fields() {
+        assert!(has_compatible_field(
+            FIELDS,
+            "token_id",
+            StateFieldKind::Numeric,
+            false,
+            true
+        ));
+        assert!(!has_compatible_field(
+            FIELDS,
+            "token_id",
+            StateFieldKind::Bool,
+            false,
+            true
+        ));
+        assert!(!has_compatible_field(
+            FIELDS,
+            "note",
+            StateFieldKind::Text,
+            false,
+            true
+        ));
+        assert!(!has_compatible_field(
+            FIELDS,
+            "owner",
+            StateFieldKind::Text,
+            false,
+            true
+        ));
+        assert!(has_compatible_field(
+            FIELDS,
+            "chain_id",
+            StateFieldKind::Integer,
+            false,
+            false
+        ));
+    }
+
+    #[test]
+    fn checks_fields_against_columns() {
+        assert!(has_column_for_every_field(FIELDS, &["token_id", "note"]));
+        assert!(!has_column_for_every_field(FIELDS, &["token_id"]));
+    }
+}
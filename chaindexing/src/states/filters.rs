@@ -1,6 +1,6 @@
-use std::{collections::HashMap, fmt::Debug};
+use std::fmt::Debug;
 
-use crate::Event;
+use crate::{ChainId, Event, SqlParams};
 
 #[derive(Clone, Debug)]
 enum FiltersContext {
@@ -9,11 +9,79 @@ enum FiltersContext {
     MultiChain,
 }
 
+#[derive(Clone, Debug)]
+enum Filter {
+    Compare {
+        field: String,
+        operator: &'static str,
+        value: String,
+    },
+    In {
+        field: String,
+        values: Vec<String>,
+        is_negated: bool,
+    },
+    IsNull {
+        field: String,
+        is_negated: bool,
+    },
+    Any(Vec<Vec<Filter>>),
+}
+
+impl Filter {
+    fn to_sql(&self, params: &mut SqlParams) -> String {
+        match self {
+            Filter::Compare {
+                field,
+                operator,
+                value,
+            } => format!("{field} {operator} {}", params.add(value)),
+            Filter::In {
+                values, is_negated, ..
+            } if values.is_empty() => {
+                // Nothing is IN an empty list
+                (if *is_negated { "TRUE" } else { "FALSE" }).to_string()
+            }
+            Filter::In {
+                field,
+                values,
+                is_negated,
+            } => format!(
+                "{field} {operator} ({values})",
+                operator = if *is_negated { "NOT IN" } else { "IN" },
+                values = params.add_list(values)
+            ),
+            Filter::IsNull { field, is_negated } => format!(
+                "{field} IS {not}NULL",
+                not = if *is_negated { "NOT " } else { "" }
+            ),
+            Filter::Any(filter_groups) => format!(
+                "({})",
+                filter_groups
+                    .iter()
+                    .map(|filters| format!("({})", to_and_sql(filters, params)))
+                    .collect::<Vec<_>>()
+                    .join(" OR ")
+            ),
+        }
+    }
+}
+
+/// Sort order of states returned with Filters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Order {
+    Asc,
+    Desc,
+}
+
 /// Represents a set of filters used for querying data.
 #[derive(Clone, Debug)]
 pub struct Filters {
-    values: HashMap<String, String>, // A map of filter field names to their values.
-    context: FiltersContext,         // The context in which the filters are applied.
+    values: Vec<Filter>,     // Filters that all have to match.
+    context: FiltersContext, // The context in which the filters are applied.
+    orderings: Vec<(String, Order)>,
+    limit: Option<u64>,
+    offset: Option<u64>,
 }
 
 impl Filters {
@@ -31,9 +99,24 @@ impl Filters {
     /// Nft::read_one(&filters, &context);
     /// ```
     pub fn new(field: impl ToString, value: impl ToString) -> Self {
+        Self::all().add(field, value)
+    }
+
+    /// Creates a Filters instance matching every state within its context.
+    /// Typically narrowed down with orderings and limits.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let filters = Filters::all().order_by("block_number", Order::Desc).limit(10);
+    /// ```
+    pub fn all() -> Self {
         Self {
-            values: HashMap::from([(field.to_string(), value.to_string())]),
+            values: vec![],
             context: FiltersContext::Contract,
+            orderings: vec![],
+            limit: None,
+            offset: None,
         }
     }
 
@@ -71,7 +154,124 @@ impl Filters {
     /// filters.add_mut("token_id", token_id); // filters not moved
     /// ```
     pub fn add_mut(&mut self, field: impl ToString, value: impl ToString) {
-        self.values.insert(field.to_string(), value.to_string());
+        self.add_comparison(field, "=", value);
+    }
+
+    /// Matches states whose field is not equal to the value
+    pub fn not_eq(self, field: impl ToString, value: impl ToString) -> Self {
+        self.compare(field, "<>", value)
+    }
+
+    /// Matches states whose field is greater than the value
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Filters::new("owner", owner).gt("balance", 0);
+    /// ```
+    pub fn gt(self, field: impl ToString, value: impl ToString) -> Self {
+        self.compare(field, ">", value)
+    }
+
+    /// Matches states whose field is greater than or equal to the value
+    pub fn gte(self, field: impl ToString, value: impl ToString) -> Self {
+        self.compare(field, ">=", value)
+    }
+
+    /// Matches states whose field is less than the value
+    pub fn lt(self, field: impl ToString, value: impl ToString) -> Self {
+        self.compare(field, "<", value)
+    }
+
+    /// Matches states whose field is less than or equal to the value
+    pub fn lte(self, field: impl ToString, value: impl ToString) -> Self {
+        self.compare(field, "<=", value)
+    }
+
+    /// Matches states whose field matches the SQL LIKE pattern
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Filters::all().like("name", "Bored%");
+    /// ```
+    pub fn like(self, field: impl ToString, pattern: impl ToString) -> Self {
+        self.compare(field, "LIKE", pattern)
+    }
+
+    /// Matches states whose field is any of the values
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Filters::all().is_in("token_id", [1, 2, 3]);
+    /// ```
+    pub fn is_in<V: ToString>(
+        self,
+        field: impl ToString,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.add_in(field, values, false)
+    }
+
+    /// Matches states whose field is none of the values
+    pub fn not_in<V: ToString>(
+        self,
+        field: impl ToString,
+        values: impl IntoIterator<Item = V>,
+    ) -> Self {
+        self.add_in(field, values, true)
+    }
+
+    /// Matches states whose field is NULL
+    pub fn is_null(mut self, field: impl ToString) -> Self {
+        self.values.push(Filter::IsNull {
+            field: field.to_string(),
+            is_negated: false,
+        });
+        self
+    }
+
+    /// Matches states whose field is not NULL
+    pub fn is_not_null(mut self, field: impl ToString) -> Self {
+        self.values.push(Filter::IsNull {
+            field: field.to_string(),
+            is_negated: true,
+        });
+        self
+    }
+
+    /// Matches states matching either these filters or the other ones.
+    /// The context, orderings and limits of the other filters are ignored.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// // (owner = 'a' AND balance > 0) OR (operator = 'a')
+    /// Filters::new("owner", a).gt("balance", 0).or(Filters::new("operator", a));
+    /// ```
+    pub fn or(mut self, other: Filters) -> Self {
+        let filters = std::mem::take(&mut self.values);
+        self.values = vec![Filter::Any(vec![filters, other.values])];
+        self
+    }
+
+    /// Orders states by the field. Can be called multiple times to break ties.
+    pub fn order_by(mut self, field: impl ToString, order: Order) -> Self {
+        self.orderings.push((field.to_string(), order));
+        self
+    }
+
+    /// Limits the number of states returned
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Skips the first states returned, typically for pagination with `limit`
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
     }
 
     /// Sets the context of the filters to Contract
@@ -91,23 +291,254 @@ impl Filters {
         self.context = FiltersContext::MultiChain;
         self
     }
-    pub(super) fn get(&self, event: &Event) -> HashMap<String, String> {
-        let mut filters = self.values.clone();
 
-        match self.context {
-            FiltersContext::Contract => {
-                filters.insert("chain_id".to_string(), event.chain_id.to_string());
-                filters.insert(
-                    "contract_address".to_string(),
-                    event.contract_address.to_owned(),
-                );
-            }
-            FiltersContext::Chain => {
-                filters.insert("chain_id".to_string(), event.chain_id.to_string());
-            }
-            FiltersContext::MultiChain => {}
+    /// Matches states of the chain. For reads outside handlers, which have no
+    /// event to scope them to.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let filters = Filters::new("owner", owner).for_chain(&ChainId::Mainnet);
+    /// ```
+    pub fn for_chain(self, chain_id: &ChainId) -> Self {
+        self.add("chain_id", *chain_id as u64)
+    }
+
+    /// Matches states of the contract address on the chain. For reads outside handlers.
+    pub fn for_contract_address(self, chain_id: &ChainId, contract_address: &str) -> Self {
+        self.for_chain(chain_id)
+            .add("contract_address", contract_address.to_lowercase())
+    }
+
+    fn compare(
+        mut self,
+        field: impl ToString,
+        operator: &'static str,
+        value: impl ToString,
+    ) -> Self {
+        self.add_comparison(field, operator, value);
+        self
+    }
+
+    fn add_comparison(
+        &mut self,
+        field: impl ToString,
+        operator: &'static str,
+        value: impl ToString,
+    ) {
+        self.values.push(Filter::Compare {
+            field: field.to_string(),
+            operator,
+            value: value.to_string(),
+        });
+    }
+
+    fn add_in<V: ToString>(
+        mut self,
+        field: impl ToString,
+        values: impl IntoIterator<Item = V>,
+        is_negated: bool,
+    ) -> Self {
+        self.values.push(Filter::In {
+            field: field.to_string(),
+            values: values.into_iter().map(|v| v.to_string()).collect(),
+            is_negated,
+        });
+        self
+    }
+
+    /// Returns the WHERE clause, scoped to the event's context, along with
+    /// the ordering and limits
+    pub(super) fn to_sql(&self, event: &Event, params: &mut SqlParams) -> String {
+        self.get_sql(event.chain_id, &event.contract_address, params)
+    }
+
+    /// Returns the WHERE clause without scoping it to any chain or contract,
+    /// for reads outside handlers
+    pub(super) fn to_unscoped_sql(&self, params: &mut SqlParams) -> String {
+        self.clone().within_multi_chain().get_sql(0, "", params)
+    }
+
+    /// Returns the WHERE clause alone, scoped to the event's context
+    pub(super) fn to_where_sql(&self, event: &Event, params: &mut SqlParams) -> String {
+        self.get_where_sql(event.chain_id, &event.contract_address, params)
+    }
+
+    /// Returns the WHERE clause alone, without scoping it to any chain or contract
+    pub(super) fn to_unscoped_where_sql(&self, params: &mut SqlParams) -> String {
+        self.clone().within_multi_chain().get_where_sql(0, "", params)
+    }
+
+    fn get_sql(&self, chain_id: i64, contract_address: &str, params: &mut SqlParams) -> String {
+        format!(
+            "{}{}",
+            self.get_where_sql(chain_id, contract_address, params),
+            self.to_ordering_sql()
+        )
+    }
+
+    fn get_where_sql(
+        &self,
+        chain_id: i64,
+        contract_address: &str,
+        params: &mut SqlParams,
+    ) -> String {
+        let mut filters = match self.context {
+            FiltersContext::Contract => vec![
+                Filter::Compare {
+                    field: "chain_id".to_string(),
+                    operator: "=",
+                    value: chain_id.to_string(),
+                },
+                Filter::Compare {
+                    field: "contract_address".to_string(),
+                    operator: "=",
+                    value: contract_address.to_owned(),
+                },
+            ],
+            FiltersContext::Chain => vec![Filter::Compare {
+                field: "chain_id".to_string(),
+                operator: "=",
+                value: chain_id.to_string(),
+            }],
+            FiltersContext::MultiChain => vec![],
+        };
+        filters.extend(self.values.clone());
+
+        if filters.is_empty() {
+            "TRUE".to_string()
+        } else {
+            to_and_sql(&filters, params)
         }
+    }
+
+    /// Returns the ORDER BY, LIMIT and OFFSET clauses, each with a leading space
+    pub(super) fn to_ordering_sql(&self) -> String {
+        let mut sql = String::new();
+
+        if !self.orderings.is_empty() {
+            let orderings: Vec<_> = self
+                .orderings
+                .iter()
+                .map(|(field, order)| match order {
+                    Order::Asc => format!("{field} ASC"),
+                    Order::Desc => format!("{field} DESC"),
+                })
+                .collect();
+
+            sql.push_str(&format!(" ORDER BY {}", orderings.join(", ")));
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+        if let Some(offset) = self.offset {
+            sql.push_str(&format!(" OFFSET {offset}"));
+        }
+
+        sql
+    }
+}
+
+fn to_and_sql(filters: &[Filter], params: &mut SqlParams) -> String {
+    if filters.is_empty() {
+        return "TRUE".to_string();
+    }
+
+    filters.iter().map(|f| f.to_sql(params)).collect::<Vec<_>>().join(" AND ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SqlValue;
+
+    const CONTRACT_ADDRESS: &str = "0xbc4ca0eda7647a8ab7c2061c2e118a18a936f13d";
+
+    fn texts(values: &[&str]) -> Vec<SqlValue> {
+        values.iter().map(|v| SqlValue::Text(v.to_string())).collect()
+    }
+
+    #[test]
+    fn scopes_equality_filters_within_contract() {
+        let mut params = SqlParams::new();
+        let filters = Filters::new("token_id", 7);
+
+        assert_eq!(
+            filters.get_sql(1, CONTRACT_ADDRESS, &mut params),
+            "chain_id = $1 AND contract_address = $2 AND token_id = $3"
+        );
+        assert_eq!(params.get_values(), texts(&["1", CONTRACT_ADDRESS, "7"]));
+    }
+
+    #[test]
+    fn supports_comparisons_lists_and_nulls() {
+        let mut params = SqlParams::new();
+        let filters = Filters::all()
+            .within_multi_chain()
+            .gt("balance", 0)
+            .is_in("token_id", [1, 2])
+            .not_in("owner", Vec::<String>::new())
+            .is_not_null("operator")
+            .like("name", "Bored%");
+
+        assert_eq!(
+            filters.get_sql(1, CONTRACT_ADDRESS, &mut params),
+            "balance > $1 AND token_id IN ($2,$3) AND TRUE AND operator IS NOT NULL AND name LIKE $4"
+        );
+        assert_eq!(params.get_values(), texts(&["0", "1", "2", "Bored%"]));
+    }
+
+    #[test]
+    fn binds_values_instead_of_quoting_them() {
+        let mut params = SqlParams::new();
+        let filters = Filters::new("name", "Ape' OR '1' = '1").within_multi_chain();
+
+        assert_eq!(
+            filters.get_sql(1, CONTRACT_ADDRESS, &mut params),
+            "name = $1"
+        );
+        assert_eq!(params.get_values(), texts(&["Ape' OR '1' = '1"]));
+    }
+
+    #[test]
+    fn groups_or_filters() {
+        let mut params = SqlParams::new();
+        let filters = Filters::new("owner", "a")
+            .gt("balance", 0)
+            .or(Filters::new("operator", "a"))
+            .within_chain();
+
+        assert_eq!(
+            filters.get_sql(1, CONTRACT_ADDRESS, &mut params),
+            "chain_id = $1 AND ((owner = $2 AND balance > $3) OR (operator = $4))"
+        );
+    }
+
+    #[test]
+    fn scopes_reads_outside_handlers_explicitly() {
+        let mut params = SqlParams::new();
+        let filters = Filters::new("token_id", 7)
+            .for_contract_address(&ChainId::Mainnet, &CONTRACT_ADDRESS.to_uppercase());
+
+        assert_eq!(
+            filters.to_unscoped_sql(&mut params),
+            "token_id = $1 AND chain_id = $2 AND contract_address = $3"
+        );
+        assert_eq!(params.get_values(), texts(&["7", "1", CONTRACT_ADDRESS]));
+    }
+
+    #[test]
+    fn orders_and_limits() {
+        let filters = Filters::all()
+            .within_multi_chain()
+            .order_by("block_number", Order::Desc)
+            .order_by("token_id", Order::Asc)
+            .limit(10)
+            .offset(20);
 
-        filters
+        assert_eq!(
+            filters.get_sql(1, CONTRACT_ADDRESS, &mut SqlParams::new()),
+            "TRUE ORDER BY block_number DESC, token_id ASC LIMIT 10 OFFSET 20"
+        );
     }
 }
@@ -0,0 +1,293 @@
+use crate::handlers::HandlerContext;
+use crate::{ChaindexingRepo, ChaindexingRepoClient, LoadsDataWithRawQuery, SqlParams};
+
+use super::filters::{Filters, Order};
+use super::state_versions::StateVersion;
+use super::{to_typed_json, StateRow};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+
+/// Point in the chain to read states at, from their versions. Versions pruned
+/// with `OptimizationConfig` can't be read anymore.
+///
+/// # Example
+///
+/// ```ignore
+/// // As of the end of block 17_000_000
+/// let at = AtBlock::new(17_000_000);
+/// // Right after the event at log index 12 of the block
+/// let at = AtBlock::new(17_000_000).log_index(12);
+///
+/// let owner = Nft::read_one_at(&Filters::new("token_id", 9), &at, &context).await;
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct AtBlock {
+    block_number: u64,
+    log_index: Option<u32>,
+}
+
+impl AtBlock {
+    /// States as of the end of the block
+    pub fn new(block_number: u64) -> Self {
+        Self {
+            block_number,
+            log_index: None,
+        }
+    }
+
+    /// States as of right after the event at the log index, within the block
+    pub fn log_index(mut self, log_index: u32) -> Self {
+        self.log_index = Some(log_index);
+        self
+    }
+
+    fn to_sql(self, params: &mut SqlParams) -> String {
+        let block_number = params.add(self.block_number);
+
+        match self.log_index {
+            Some(log_index) => format!(
+                "(block_number < {block_number} OR (block_number = {block_number} AND log_index <= {}))",
+                params.add(log_index as i64)
+            ),
+            None => format!("block_number <= {block_number}"),
+        }
+    }
+}
+
+/// Version of a state, along with the event that produced it
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct VersionedState<T> {
+    pub state: T,
+    pub block_hash: String,
+    pub block_number: u64,
+    /// None for versions from before block timestamps got tracked
+    pub block_timestamp: Option<u64>,
+    pub transaction_hash: String,
+    pub transaction_index: u32,
+    pub log_index: u32,
+    /// Whether the event deleted the state, which is then as it was last
+    pub is_deleted: bool,
+}
+
+pub(super) async fn read_many_at_in_context<
+    'a,
+    C: HandlerContext<'a>,
+    T: Send + DeserializeOwned,
+>(
+    filters: &Filters,
+    at: &AtBlock,
+    context: &C,
+    table_name: &str,
+) -> Vec<T> {
+    let mut params = SqlParams::new();
+    let query = read_many_at_query(
+        |params| filters.to_sql(context.get_event(), params),
+        at,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_in_txn_with_params::<StateRow<T>>(
+        context.get_client(),
+        &query,
+        params.get_values(),
+    )
+    .await
+    .into_iter()
+    .map(|row| row.state)
+    .collect()
+}
+
+/// Returns states of the table matching filters as they were at the given block,
+/// outside of handlers. The filters aren't scoped to any chain or contract, so
+/// filter by `chain_id` and `contract_address` as needed.
+///
+/// # Example
+///
+/// ```ignore
+/// let filters = Filters::new("chain_id", 1).add("token_id", 9);
+/// let nfts: Vec<Nft> =
+///     states::read_many_at(Nft::table_name(), &filters, &AtBlock::new(17_000_000), &client).await;
+/// ```
+pub async fn read_many_at<T: Send + DeserializeOwned>(
+    table_name: &str,
+    filters: &Filters,
+    at: &AtBlock,
+    client: &ChaindexingRepoClient,
+) -> Vec<T> {
+    let mut params = SqlParams::new();
+    let query = read_many_at_query(
+        |params| filters.to_unscoped_sql(params),
+        at,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_with_params::<StateRow<T>>(client, &query, params.get_values())
+        .await
+        .into_iter()
+        .map(|row| row.state)
+        .collect()
+}
+
+/// Returns a single state of the table matching filters as it was at the given block,
+/// outside of handlers
+pub async fn read_one_at<T: Send + DeserializeOwned>(
+    table_name: &str,
+    filters: &Filters,
+    at: &AtBlock,
+    client: &ChaindexingRepoClient,
+) -> Option<T> {
+    read_many_at(table_name, filters, at, client).await.into_iter().next()
+}
+
+pub(super) async fn read_history_in_context<
+    'a,
+    C: HandlerContext<'a>,
+    T: Send + DeserializeOwned,
+>(
+    filters: &Filters,
+    context: &C,
+    table_name: &str,
+) -> Vec<VersionedState<T>> {
+    let mut params = SqlParams::new();
+    let query = read_history_query(
+        |filters, params| filters.to_sql(context.get_event(), params),
+        filters,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_in_txn_with_params(
+        context.get_client(),
+        &query,
+        params.get_values(),
+    )
+    .await
+}
+
+/// Returns the versions of the table's states matching filters, outside of handlers.
+/// Like `read_many_at`, the filters aren't scoped to any chain or contract.
+///
+/// # Example
+///
+/// ```ignore
+/// let filters = Filters::new("chain_id", 1).add("token_id", 9);
+/// let versions: Vec<VersionedState<Nft>> =
+///     states::read_history(Nft::table_name(), &filters, &client).await;
+/// ```
+pub async fn read_history<T: Send + DeserializeOwned>(
+    table_name: &str,
+    filters: &Filters,
+    client: &ChaindexingRepoClient,
+) -> Vec<VersionedState<T>> {
+    let mut params = SqlParams::new();
+    let query = read_history_query(
+        |filters, params| filters.to_unscoped_sql(params),
+        filters,
+        table_name,
+        &mut params,
+    );
+
+    ChaindexingRepo::load_data_list_with_params(client, &query, params.get_values()).await
+}
+
+/// Filters the versions themselves, in the order their events happened unless
+/// ordered otherwise
+fn read_history_query(
+    get_filters_sql: impl FnOnce(&Filters, &mut SqlParams) -> String,
+    filters: &Filters,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    let state = to_typed_json("chaindexing_state", table_name, params);
+    let filters = filters
+        .clone()
+        .order_by("block_number", Order::Asc)
+        .order_by("log_index", Order::Asc);
+    let filters = get_filters_sql(&filters, params);
+
+    format!(
+        "SELECT {state}, block_hash, block_number, block_timestamp, transaction_hash,
+        transaction_index, log_index, state_version_is_deleted AS is_deleted
+        FROM {state_versions_table_name} chaindexing_state
+        WHERE {filters}",
+        state_versions_table_name = StateVersion::table_name(table_name),
+    )
+}
+
+/// Rebuilds the states from their latest versions up to the block, leaving out
+/// deleted ones, before filtering them
+fn read_many_at_query(
+    get_filters_sql: impl FnOnce(&mut SqlParams) -> String,
+    at: &AtBlock,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    let state = to_typed_json("chaindexing_state", table_name, params);
+    let at = at.to_sql(params);
+    let filters = get_filters_sql(params);
+
+    format!(
+        "SELECT {state} FROM (
+            SELECT DISTINCT ON (state_version_group_id) * FROM {state_versions_table_name}
+            WHERE {at}
+            ORDER BY state_version_group_id, block_number DESC, log_index DESC, state_version_id DESC
+        ) chaindexing_state
+        WHERE NOT state_version_is_deleted AND {filters}",
+        state_versions_table_name = StateVersion::table_name(table_name),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_up_to_the_end_of_the_block() {
+        let mut params = SqlParams::new();
+
+        assert_eq!(AtBlock::new(10).to_sql(&mut params), "block_number <= $1");
+    }
+
+    #[test]
+    fn reads_up_to_the_log_index_within_the_block() {
+        let mut params = SqlParams::new();
+
+        assert_eq!(
+            AtBlock::new(10).log_index(3).to_sql(&mut params),
+            "(block_number < $1 OR (block_number = $1 AND log_index <= $2))"
+        );
+        assert_eq!(params.get_values().len(), 2);
+    }
+
+    #[test]
+    fn filters_rebuilt_states() {
+        let mut params = SqlParams::new();
+        let query = read_many_at_query(
+            |params| Filters::new("token_id", 9).to_unscoped_sql(params),
+            &AtBlock::new(10),
+            "nfts",
+            &mut params,
+        );
+
+        assert!(query.contains("FROM chaindexing_state_versions_for_nfts"));
+        assert!(query.ends_with("WHERE NOT state_version_is_deleted AND token_id = $3"));
+    }
+
+    #[test]
+    fn orders_history_by_events_to_break_ties() {
+        let mut params = SqlParams::new();
+        let query = read_history_query(
+            |filters, params| filters.to_unscoped_sql(params),
+            &Filters::new("token_id", 9).order_by("amount", Order::Desc).limit(10),
+            "nfts",
+            &mut params,
+        );
+
+        assert!(query.contains("FROM chaindexing_state_versions_for_nfts chaindexing_state"));
+        assert!(query.ends_with(
+            "WHERE token_id = $2 ORDER BY amount DESC, block_number ASC, log_index ASC LIMIT 10"
+        ));
+    }
+}
@@ -0,0 +1,68 @@
+//! Stores I256 fields as signed NUMERIC(78, 0) values. Without it, they get
+//! stored as their two's complement, which round-trips but doesn't compare or
+//! sum correctly in SQL. U256 fields need no annotation.
+//!
+//! ## Example
+//!
+//! ```rust,no_run
+//! use chaindexing::states::i256;
+//! use ethers::types::I256;
+//! use serde::{Deserialize, Serialize};
+//!
+//! #[derive(Clone, Debug, Serialize, Deserialize)]
+//! pub struct Position {
+//!     #[serde(with = "i256")]
+//!     pub liquidity_delta: I256,
+//! }
+//! ```
+
+use ethers::types::{I256, U256};
+use serde::{de, Deserialize, Deserializer, Serializer};
+
+pub fn serialize<S: Serializer>(value: &I256, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&value.to_string())
+}
+
+/// Deserializes from decimal strings or the hex NUMERIC(78, 0) values get read as
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<I256, D::Error> {
+    let value = String::deserialize(deserializer)?;
+
+    match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).map(I256::from_raw).map_err(de::Error::custom),
+        None => I256::from_dec_str(&value).map_err(de::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::{Deserialize, Serialize};
+
+    use super::*;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Position {
+        #[serde(with = "super")]
+        liquidity_delta: I256,
+    }
+
+    #[test]
+    fn serializes_as_signed_decimal() {
+        let position = Position {
+            liquidity_delta: I256::from(-5),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&position).unwrap(),
+            serde_json::json!({"liquidity_delta": "-5"})
+        );
+    }
+
+    #[test]
+    fn deserializes_twos_complement_hex() {
+        let hex = format!("{:#x}", I256::from(-5).into_raw());
+        let position: Position =
+            serde_json::from_value(serde_json::json!({ "liquidity_delta": hex })).unwrap();
+
+        assert_eq!(position.liquidity_delta, I256::from(-5));
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+
+use futures_util::FutureExt;
+use serde::{Deserialize, Serialize};
+
+use crate::handlers::PureHandlerContext;
+use crate::{ChaindexingRepo, ChaindexingRepoClient, ChaindexingRepoTxnClient, Event};
+use crate::{ExecutesWithRawQuery, HasRawQueryClient, LoadsDataWithRawQuery, SqlParams, SqlValue};
+
+use super::state_versions::StateVersion;
+use super::state_views::StateView;
+use super::updates::Updates;
+
+/// Attempts at applying a queued mutation, after which it stays queued, with
+/// its last error, for inspection
+pub const MAX_MCS_MUTATION_ATTEMPTS: i32 = 5;
+
+const MCS_MUTATIONS_BATCH_SIZE: i64 = 500;
+
+/// Mutation of a MultiChainState, queued within the handler's transaction and
+/// applied after it commits, in an order-agnostic fashion
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(crate) enum McsMutation {
+    Update {
+        state_view: HashMap<String, SqlValue>,
+        updates: Updates,
+    },
+    CreateOrUpdate {
+        state_view: HashMap<String, SqlValue>,
+        identity_view: HashMap<String, SqlValue>,
+        group_id: Option<String>,
+        updates: Updates,
+    },
+    Delete {
+        state_view: HashMap<String, SqlValue>,
+    },
+}
+
+#[derive(Deserialize)]
+struct QueuedMcsMutation {
+    id: i64,
+    table_name: String,
+    mutation: McsMutation,
+    event: Event,
+}
+
+/// Queues the mutation in the current schema, so it only gets applied if the
+/// handler's transaction commits
+pub(crate) async fn enqueue<'a, 'b>(
+    mutation: &McsMutation,
+    table_name: &str,
+    context: &PureHandlerContext<'a, 'b>,
+) {
+    let event = &context.event;
+
+    let mut params = SqlParams::new();
+    let query = format!(
+        "INSERT INTO chaindexing_mcs_mutations
+        (schema_name, table_name, chain_id, contract_address, block_number, mutation, event)
+        VALUES (current_schema(), {}, {}, {}, {}, {}::JSONB, {}::JSONB)",
+        params.add(table_name),
+        params.add(event.chain_id),
+        params.add(event.contract_address.as_str()),
+        params.add(event.block_number),
+        params.add(serde_json::to_value(mutation).unwrap()),
+        params.add(serde_json::to_value(event).unwrap()),
+    );
+
+    ChaindexingRepo::execute_in_txn_with_params(context.repo_client, &query, params.get_values())
+        .await;
+}
+
+/// Applies the mutations queued in the client's current schema, in the order they
+/// got queued. Each gets locked and dequeued in the transaction applying it, so none
+/// gets applied twice. Failed ones get retried on the next run until they run out of
+/// attempts, with their last error recorded.
+pub(crate) async fn apply(client: &mut ChaindexingRepoClient) {
+    loop {
+        let queued_mutations: Vec<QueuedMcsMutation> = ChaindexingRepo::load_data_list_with_params(
+            client,
+            "SELECT id, table_name, mutation, event FROM chaindexing_mcs_mutations
+            WHERE schema_name = current_schema() AND attempts < $1
+            ORDER BY id LIMIT $2",
+            &[
+                MAX_MCS_MUTATION_ATTEMPTS.into(),
+                MCS_MUTATIONS_BATCH_SIZE.into(),
+            ],
+        )
+        .await;
+
+        let mut has_applied_any = false;
+
+        for queued_mutation in &queued_mutations {
+            match AssertUnwindSafe(apply_one(queued_mutation, client)).catch_unwind().await {
+                Ok(()) => has_applied_any = true,
+                Err(panic) => {
+                    let error = panic
+                        .downcast_ref::<String>()
+                        .cloned()
+                        .or_else(|| panic.downcast_ref::<&str>().map(|error| error.to_string()))
+                        .unwrap_or_else(|| "unknown error".to_string());
+
+                    eprintln!(
+                        "MultiChainState Mutation Error: {error} (mutation {})",
+                        queued_mutation.id
+                    );
+
+                    ChaindexingRepo::execute_with_params(
+                        client,
+                        "UPDATE chaindexing_mcs_mutations
+                        SET attempts = attempts + 1, last_error = $1
+                        WHERE id = $2",
+                        &[error.into(), queued_mutation.id.into()],
+                    )
+                    .await;
+                }
+            }
+        }
+
+        if (queued_mutations.len() as i64) < MCS_MUTATIONS_BATCH_SIZE || !has_applied_any {
+            break;
+        }
+    }
+}
+
+async fn apply_one(
+    QueuedMcsMutation {
+        id,
+        table_name,
+        mutation,
+        event,
+    }: &QueuedMcsMutation,
+    client: &mut ChaindexingRepoClient,
+) {
+    let txn_client = ChaindexingRepo::get_txn_client(client).await;
+
+    // Concurrent consumers skip the mutation until it gets applied or fails
+    let queued_mutation: Option<serde_json::Value> = ChaindexingRepo::load_data_in_txn_with_params(
+        &txn_client,
+        "SELECT id FROM chaindexing_mcs_mutations WHERE id = $1 FOR UPDATE SKIP LOCKED",
+        &[(*id).into()],
+    )
+    .await;
+    if queued_mutation.is_none() {
+        return;
+    }
+
+    let latest_state_version = match mutation {
+        McsMutation::Update {
+            state_view,
+            updates,
+        } => StateVersion::update(state_view, updates, table_name, event, &txn_client).await,
+        McsMutation::CreateOrUpdate {
+            state_view,
+            identity_view,
+            group_id,
+            updates,
+        } => match StateView::find_complete(identity_view, table_name, &txn_client).await {
+            Some(current_state_view) => {
+                StateVersion::update(&current_state_view, updates, table_name, event, &txn_client)
+                    .await
+            }
+            None => {
+                StateVersion::create(state_view, group_id.clone(), table_name, event, &txn_client)
+                    .await
+            }
+        },
+        McsMutation::Delete { state_view } => {
+            StateVersion::delete(state_view, table_name, event, &txn_client).await
+        }
+    };
+    StateView::refresh(&latest_state_version, table_name, &txn_client).await;
+
+    ChaindexingRepo::execute_in_txn_with_params(
+        &txn_client,
+        "DELETE FROM chaindexing_mcs_mutations WHERE id = $1",
+        &[(*id).into()],
+    )
+    .await;
+
+    ChaindexingRepo::commit_txns(txn_client).await;
+}
+
+/// Discards the mutations queued for events from the block on, in every schema,
+/// as their events no longer exist
+pub(crate) async fn discard<'a>(
+    chain_id: i64,
+    block_number: i64,
+    contract_addresses: &[String],
+    client: &ChaindexingRepoTxnClient<'a>,
+) {
+    let mut params = SqlParams::new();
+    let contract_addresses_filter = if contract_addresses.is_empty() {
+        "".to_string()
+    } else {
+        format!(
+            "AND contract_address IN ({})",
+            params.add_list(contract_addresses)
+        )
+    };
+
+    let query = format!(
+        "DELETE FROM chaindexing_mcs_mutations
+        WHERE chain_id = {chain_id}
+        AND block_number >= {block_number}
+        {contract_addresses_filter}"
+    );
+
+    ChaindexingRepo::execute_in_txn_with_params(client, &query, params.get_values()).await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_mutations_through_json() {
+        let mutation = McsMutation::CreateOrUpdate {
+            state_view: HashMap::from([
+                ("token_id".to_string(), 3.into()),
+                ("amount".to_string(), "0x1bc16d674ec80000".into()),
+                (
+                    "metadata".to_string(),
+                    serde_json::json!({"name": "Ape"}).into(),
+                ),
+                ("memo".to_string(), SqlValue::Null),
+            ]),
+            identity_view: HashMap::from([("token_id".to_string(), 3.into())]),
+            group_id: Some("group".to_string()),
+            updates: Updates::increment("amount", 10).add("is_locked", true),
+        };
+
+        let json = serde_json::to_value(&mutation).unwrap();
+
+        assert_eq!(
+            serde_json::from_value::<McsMutation>(json).unwrap(),
+            mutation
+        );
+    }
+}
@@ -1,8 +1,12 @@
 use std::collections::HashMap;
 
+use super::state_versions::StateVersion;
 use super::STATE_VERSIONS_TABLE_PREFIX;
 
-use sqlparser::ast::{CreateTable, DataType, Statement};
+use sqlparser::ast::{
+    AlterTableOperation, ColumnDef, CreateIndex, CreateTable, DataType, Ident, ObjectName,
+    Statement,
+};
 use sqlparser::dialect::PostgreSqlDialect;
 use sqlparser::parser::Parser;
 
@@ -39,25 +43,19 @@ fn extract_table_fields(migration: &str, remove_json_fields: bool) -> Vec<String
         })
         .expect("CREATE TABLE statement not found")
         .iter()
-        .filter_map(|col| {
-            if remove_json_fields {
-                match &col.data_type {
-                    DataType::JSON | DataType::JSONB => return None,
-                    DataType::Custom(name, _) => {
-                        let name_str = name.to_string().to_uppercase();
-                        if name_str == "JSONB" {
-                            return None;
-                        }
-                    }
-                    _ => {}
-                }
-            }
-
-            Some(col.name.value.clone())
-        })
+        .filter(|col| !remove_json_fields || !is_json_column(col))
+        .map(|col| col.name.value.clone())
         .collect()
 }
 
+fn is_json_column(column: &ColumnDef) -> bool {
+    match &column.data_type {
+        DataType::JSON | DataType::JSONB => true,
+        DataType::Custom(name, _) => name.to_string().to_uppercase() == "JSONB",
+        _ => false,
+    }
+}
+
 fn get_unique_index_migration_for_state_versions(
     table_name: &str,
     table_fields: Vec<String>,
@@ -198,7 +196,10 @@ impl DefaultMigration {
         block_number BIGINT NOT NULL,
         transaction_hash VARCHAR NOT NULL,
         transaction_index INTEGER NOT NULL,
-        log_index INTEGER NOT NULL"
+        log_index INTEGER NOT NULL,
+        block_timestamp BIGINT,
+        created_at_block BIGINT,
+        created_at_timestamp BIGINT"
             .to_string()
     }
 
@@ -211,6 +212,9 @@ impl DefaultMigration {
             "transaction_hash",
             "transaction_index",
             "log_index",
+            "block_timestamp",
+            "created_at_block",
+            "created_at_timestamp",
         ]
     }
 
@@ -258,6 +262,233 @@ impl DefaultMigration {
     }
 }
 
+/// Pairs each user migration with the migrations it expands into. CREATE TABLE
+/// migrations get the state versions twin and ALTER TABLE ones that add, drop or
+/// rename columns, like CREATE INDEX ones, get applied to it too.
+pub(crate) fn expand_migrations(
+    user_migrations: &[&'static str],
+) -> Vec<(&'static str, Vec<String>)> {
+    // Non-JSON fields of each state table, to keep the state versions' unique index on
+    let mut user_fields_by_table: HashMap<String, Vec<String>> = HashMap::new();
+
+    user_migrations
+        .iter()
+        .map(|user_migration| {
+            let dialect = PostgreSqlDialect {};
+            let statement = Parser::parse_sql(&dialect, user_migration)
+                .ok()
+                .and_then(|mut statements| statements.pop());
+
+            let migrations = match statement {
+                Some(Statement::CreateTable(ct)) => {
+                    let user_fields = extract_table_fields(user_migration, true);
+                    user_fields_by_table.insert(ct.name.to_string(), user_fields.clone());
+
+                    expand_create_table_migration(user_migration, &user_fields)
+                }
+                Some(Statement::AlterTable {
+                    name, operations, ..
+                }) if user_fields_by_table.contains_key(&name.to_string()) => {
+                    let user_fields = user_fields_by_table.get_mut(&name.to_string()).unwrap();
+
+                    expand_alter_table_migration(user_migration, &name, &operations, user_fields)
+                }
+                Some(Statement::CreateIndex(ci))
+                    if user_fields_by_table.contains_key(&ci.table_name.to_string()) =>
+                {
+                    expand_create_index_migration(user_migration, ci)
+                }
+                _ => vec![user_migration.to_string()],
+            };
+
+            (*user_migration, migrations)
+        })
+        .collect()
+}
+
+/// Adds the default columns missing from state tables created by older versions of
+/// Chaindexing. Being idempotent, these run on every boot, unlike user migrations.
+pub(crate) fn get_default_columns_migrations(user_migrations: &[&str]) -> Vec<String> {
+    user_migrations
+        .iter()
+        .filter_map(|user_migration| parse_create_table(user_migration))
+        .flat_map(|create_table| get_default_columns_migrations_for(&create_table.name.to_string()))
+        .collect()
+}
+
+/// Indexes the state versions by group, for appending versions that increment
+/// or decrement the latest one. Idempotent too, so they run on every boot.
+pub(crate) fn get_default_indexes_migrations(user_migrations: &[&str]) -> Vec<String> {
+    user_migrations
+        .iter()
+        .filter_map(|user_migration| parse_create_table(user_migration))
+        .map(|create_table| {
+            let state_versions_table_name =
+                StateVersion::table_name(&create_table.name.to_string());
+
+            format!(
+                "CREATE INDEX IF NOT EXISTS {state_versions_table_name}_group_index
+                ON {state_versions_table_name}(state_version_group_id, state_version_id)"
+            )
+        })
+        .collect()
+}
+
+fn get_default_columns_migrations_for(state_views_table_name: &str) -> Vec<String> {
+    let mut migrations =
+        get_alter_table_migrations(state_views_table_name, &DefaultMigration::get());
+    migrations.extend(get_alter_table_migrations(
+        &StateVersion::table_name(state_views_table_name),
+        &format!(
+            "state_version_id BIGSERIAL PRIMARY KEY, state_version_is_deleted BOOL NOT NULL default false, {}",
+            DefaultMigration::get()
+        ),
+    ));
+
+    migrations
+}
+
+fn expand_create_table_migration(user_migration: &str, user_fields: &[String]) -> Vec<String> {
+    let create_state_views_table_migration =
+        append_migration(user_migration, &get_remaining_state_views_migration());
+    let create_state_views_table_migration =
+        DefaultMigration::remove_repeating_occurrences(&create_state_views_table_migration);
+
+    let create_state_versions_table_migration =
+        append_migration(user_migration, &get_remaining_state_versions_migration());
+    let create_state_versions_table_migration =
+        set_state_versions_table_name(&create_state_versions_table_migration);
+    let create_state_versions_table_migration =
+        DefaultMigration::remove_repeating_occurrences(&create_state_versions_table_migration);
+
+    let state_views_table_name = extract_table_name(user_migration);
+    let state_versions_table_name = StateVersion::table_name(&state_views_table_name);
+
+    let create_state_versions_table_migration =
+        maybe_normalize_user_primary_key_column(&create_state_versions_table_migration);
+
+    let mut migrations = vec![
+        create_state_views_table_migration,
+        create_state_versions_table_migration,
+    ];
+
+    migrations.extend(get_default_columns_migrations_for(&state_views_table_name));
+
+    migrations.extend(get_unique_index_migrations_for_state_versions(
+        &state_versions_table_name,
+        user_fields,
+    ));
+
+    migrations
+}
+
+fn expand_alter_table_migration(
+    user_migration: &str,
+    table_name: &ObjectName,
+    operations: &[AlterTableOperation],
+    user_fields: &mut Vec<String>,
+) -> Vec<String> {
+    let changes_columns_only = operations.iter().all(|operation| {
+        matches!(
+            operation,
+            AlterTableOperation::AddColumn { .. }
+                | AlterTableOperation::DropColumn { .. }
+                | AlterTableOperation::RenameColumn { .. }
+        )
+    });
+
+    if !changes_columns_only {
+        return vec![user_migration.to_string()];
+    }
+
+    for operation in operations {
+        match operation {
+            AlterTableOperation::AddColumn { column_def, .. } if !is_json_column(column_def) => {
+                user_fields.push(column_def.name.value.clone());
+            }
+            AlterTableOperation::DropColumn { column_names, .. } => {
+                user_fields.retain(|field| !column_names.iter().any(|c| c.value == *field));
+            }
+            AlterTableOperation::RenameColumn {
+                old_column_name,
+                new_column_name,
+            } => {
+                for field in user_fields.iter_mut() {
+                    if *field == old_column_name.value {
+                        *field = new_column_name.value.clone();
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let state_versions_table_name = StateVersion::table_name(&table_name.to_string());
+    let alter_state_versions_table_migration =
+        replace_table_name(user_migration, &state_versions_table_name);
+
+    let mut migrations = vec![
+        user_migration.to_string(),
+        alter_state_versions_table_migration,
+    ];
+    // Dropped columns take their indexes along, so the unique index gets rebuilt
+    migrations.extend(get_unique_index_migrations_for_state_versions(
+        &state_versions_table_name,
+        user_fields,
+    ));
+
+    migrations
+}
+
+fn expand_create_index_migration(
+    user_migration: &str,
+    mut create_index: CreateIndex,
+) -> Vec<String> {
+    // State versions repeat the unique values of states
+    if create_index.unique {
+        return vec![user_migration.to_string()];
+    }
+
+    create_index.table_name = ObjectName::from(vec![Ident::new(StateVersion::table_name(
+        &create_index.table_name.to_string(),
+    ))]);
+    create_index.name = create_index
+        .name
+        .map(|name| ObjectName::from(vec![Ident::new(format!("{name}_versions"))]));
+
+    vec![user_migration.to_string(), create_index.to_string()]
+}
+
+fn replace_table_name(migration: &str, new_table_name: &str) -> String {
+    let dialect = PostgreSqlDialect {};
+    let mut statement = Parser::parse_sql(&dialect, migration).unwrap().pop().unwrap();
+
+    if let Statement::AlterTable { ref mut name, .. } = statement {
+        *name = ObjectName::from(vec![Ident::new(new_table_name)]);
+    }
+
+    statement.to_string()
+}
+
+fn get_unique_index_migrations_for_state_versions(
+    state_versions_table_name: &str,
+    user_fields: &[String],
+) -> Vec<String> {
+    // Combine user fields with essential blockchain fields for proper uniqueness
+    let mut fields_for_index = user_fields.to_vec();
+    for field in ["chain_id", "block_number", "transaction_hash", "log_index"] {
+        if !fields_for_index.contains(&field.to_string()) {
+            fields_for_index.push(field.to_string());
+        }
+    }
+
+    vec![
+        // Drop any existing unique index that might have incorrect fields
+        format!("DROP INDEX IF EXISTS unique_{state_versions_table_name}"),
+        get_unique_index_migration_for_state_versions(state_versions_table_name, fields_for_index),
+    ]
+}
+
 /// Represents the idempotent database migrations required before
 /// indexing a state.
 pub trait StateMigrations: Send + Sync {
@@ -265,6 +496,12 @@ pub trait StateMigrations: Send + Sync {
     /// and should include the `IF NOT EXISTS` check to keep them safe on repeated runs.
     fn migrations(&self) -> &'static [&'static str];
 
+    /// Version of the migrations. Bumping it recreates the state tables on the next
+    /// boot and replays the events of the contracts they were added to.
+    fn version(&self) -> u64 {
+        1
+    }
+
     /// All table names created by the user's migrations (derived via SQL parsing).
     fn get_table_names(&self) -> Vec<String> {
         self.migrations().iter().fold(Vec::new(), |mut names, mig| {
@@ -278,119 +515,9 @@ pub trait StateMigrations: Send + Sync {
     /// Expands the user's migrations with Chaindexing-specific additions
     /// (`state_views` and `state_versions` tables, unique index, etc.).
     fn get_migrations(&self) -> Vec<String> {
-        self.migrations()
-            .iter()
-            .flat_map(|user_migration| {
-                if let Some(_ct) = parse_create_table(user_migration) {
-                    // Build companion migrations deterministically.
-                    let create_state_views_table_migration =
-                        append_migration(user_migration, &get_remaining_state_views_migration());
-                    let create_state_views_table_migration =
-                        DefaultMigration::remove_repeating_occurrences(
-                            &create_state_views_table_migration,
-                        );
-
-                    let create_state_versions_table_migration =
-                        append_migration(user_migration, &get_remaining_state_versions_migration());
-                    let create_state_versions_table_migration =
-                        set_state_versions_table_name(&create_state_versions_table_migration);
-                    let create_state_versions_table_migration =
-                        DefaultMigration::remove_repeating_occurrences(
-                            &create_state_versions_table_migration,
-                        );
-
-                    // Determine state_views and state_versions table names and columns via AST for reliability.
-                    let (state_views_table_name, user_fields) =
-                        if let Some(ct) = parse_create_table(user_migration) {
-                            (
-                                ct.name.to_string(),
-                                extract_table_fields(user_migration, true),
-                            )
-                        } else {
-                            (
-                                extract_table_name(user_migration),
-                                extract_table_fields(user_migration, true),
-                            )
-                        };
-
-                    let (state_versions_table_name, state_versions_fields) =
-                        if let Some(ct) = parse_create_table(&create_state_versions_table_migration) {
-                            (
-                                ct.name.to_string(),
-                                extract_table_fields(&create_state_versions_table_migration, true),
-                            )
-                        } else {
-                            (
-                                extract_table_name(&create_state_versions_table_migration),
-                                extract_table_fields(&create_state_versions_table_migration, true),
-                            )
-                        };
-
-                    let create_state_versions_table_migration =
-                        maybe_normalize_user_primary_key_column(
-                            &create_state_versions_table_migration,
-                        );
-
-                    let mut migrations = vec![
-                        create_state_views_table_migration.clone(),
-                        create_state_versions_table_migration.clone(),
-                    ];
-
-                    // Ensure legacy installations gain missing default columns (idempotent ADD COLUMN IF NOT EXISTS)
-                    migrations.extend(get_alter_table_migrations(
-                        &state_views_table_name,
-                        &DefaultMigration::get(),
-                    ));
-                    migrations.extend(get_alter_table_migrations(
-                        &state_versions_table_name,
-                        &format!(
-                            "state_version_id BIGSERIAL PRIMARY KEY, state_version_is_deleted BOOL NOT NULL default false, {}",
-                            DefaultMigration::get()
-                        ),
-                    ));
-
-                    // Append deterministic UNIQUE INDEX if fields are available.
-                    let mut fields_for_index = if state_versions_fields.is_empty() {
-                        user_fields.clone()
-                    } else {
-                        // Combine user fields with essential blockchain fields for proper uniqueness
-                        let mut combined_fields = user_fields.clone();
-
-                        // Add essential blockchain fields that ensure each state version is unique
-                        let essential_fields = ["chain_id", "block_number", "transaction_hash", "log_index"];
-                        for field in essential_fields {
-                            if !combined_fields.contains(&field.to_string()) {
-                                combined_fields.push(field.to_string());
-                            }
-                        }
-
-                        combined_fields
-                    };
-
-                    if fields_for_index.is_empty() {
-                        fields_for_index = DefaultMigration::get_fields()
-                            .iter()
-                            .map(|s| s.to_string())
-                            .collect();
-                    }
-
-                    if !fields_for_index.is_empty() {
-                        // Drop any existing unique index that might have incorrect fields
-                        migrations.push(format!(
-                            "DROP INDEX IF EXISTS unique_{state_versions_table_name}"
-                        ));
-
-                        migrations.push(get_unique_index_migration_for_state_versions(
-                            &state_versions_table_name,
-                            fields_for_index,
-                        ));
-                    }
-
-                    migrations
-                } else {
-                    vec![user_migration.to_string()]
-                }
-            })
+        expand_migrations(self.migrations())
+            .into_iter()
+            .flat_map(|(_user_migration, migrations)| migrations)
             .collect()
     }
 
@@ -544,6 +671,84 @@ mod contract_state_migrations_get_migration_test {
         }
     }
 
+    #[test]
+    fn applies_alter_table_migrations_to_state_versions() {
+        let migrations = expand_migrations(TestStateWithAlterTable.migrations());
+        let (_, alter_table_migrations) = &migrations[1];
+
+        assert_eq!(
+            alter_table_migrations[0],
+            TestStateWithAlterTable.migrations()[1]
+        );
+        assert_eq!(
+            alter_table_migrations[1],
+            "ALTER TABLE chaindexing_state_versions_for_nft_states ADD COLUMN price NUMERIC(78,0)"
+        );
+        assert!(alter_table_migrations[2].starts_with("DROP INDEX IF EXISTS"));
+        assert!(alter_table_migrations[3].contains("token_id,owner,price,chain_id"));
+    }
+
+    #[test]
+    fn keeps_unique_index_of_state_versions_on_renamed_columns() {
+        let migrations = expand_migrations(TestStateWithAlterTable.migrations());
+        let (_, rename_column_migrations) = &migrations[2];
+
+        assert_eq!(
+            rename_column_migrations[1],
+            "ALTER TABLE chaindexing_state_versions_for_nft_states RENAME COLUMN owner TO owner_address"
+        );
+        assert!(rename_column_migrations[3].contains("token_id,owner_address,price"));
+    }
+
+    #[test]
+    fn applies_create_index_migrations_to_state_versions() {
+        let migrations = expand_migrations(TestStateWithAlterTable.migrations());
+        let (_, create_index_migrations) = &migrations[3];
+
+        assert_eq!(
+            create_index_migrations[1],
+            "CREATE INDEX IF NOT EXISTS nft_states_price_index_versions ON chaindexing_state_versions_for_nft_states(price)"
+        );
+    }
+
+    #[test]
+    fn returns_alter_table_migrations_of_other_tables_untouched() {
+        let migrations = expand_migrations(&["ALTER TABLE other_states ADD COLUMN price BIGINT"]);
+
+        assert_eq!(
+            migrations[0].1,
+            vec!["ALTER TABLE other_states ADD COLUMN price BIGINT"]
+        );
+    }
+
+    #[test]
+    fn adds_missing_default_columns_to_existing_state_tables() {
+        let migrations = get_default_columns_migrations(TestState.migrations());
+
+        assert!(migrations.contains(
+            &"ALTER TABLE IF EXISTS nft_states ADD COLUMN IF NOT EXISTS block_timestamp BIGINT"
+                .to_string()
+        ));
+        assert!(migrations.contains(
+            &"ALTER TABLE IF EXISTS chaindexing_state_versions_for_nft_states ADD COLUMN IF NOT EXISTS created_at_block BIGINT"
+                .to_string()
+        ));
+        assert!(migrations.iter().all(|m| m.starts_with("ALTER TABLE IF EXISTS")));
+    }
+
+    #[test]
+    fn indexes_state_versions_by_group() {
+        let migrations = get_default_indexes_migrations(TestState.migrations());
+
+        assert_eq!(migrations.len(), 1);
+        assert!(migrations[0].starts_with(
+            "CREATE INDEX IF NOT EXISTS chaindexing_state_versions_for_nft_states_group_index"
+        ));
+        assert!(migrations[0].ends_with(
+            "ON chaindexing_state_versions_for_nft_states(state_version_group_id, state_version_id)"
+        ));
+    }
+
     struct TestState;
 
     impl StateMigrations for TestState {
@@ -586,4 +791,20 @@ mod contract_state_migrations_get_migration_test {
                   )"]
         }
     }
+
+    struct TestStateWithAlterTable;
+
+    impl StateMigrations for TestStateWithAlterTable {
+        fn migrations(&self) -> &'static [&'static str] {
+            &[
+                "CREATE TABLE IF NOT EXISTS nft_states (
+                      token_id INTEGER NOT NULL,
+                      owner TEXT NOT NULL
+                  )",
+                "ALTER TABLE nft_states ADD COLUMN price NUMERIC(78, 0)",
+                "ALTER TABLE nft_states RENAME COLUMN owner TO owner_address",
+                "CREATE INDEX IF NOT EXISTS nft_states_price_index ON nft_states(price)",
+            ]
+        }
+    }
 }
@@ -2,11 +2,13 @@ use std::collections::HashMap;
 use std::fmt::Debug;
 
 use crate::handlers::{HandlerContext, PureHandlerContext};
-use crate::ChaindexingRepoTxnClient;
+use crate::{ChaindexingRepoTxnClient, SqlValue};
 
+use super::aggregates::{self, Aggregate};
 use super::filters::Filters;
+use super::history::{self, AtBlock, VersionedState};
+use super::mcs_mutations::{self, McsMutation};
 use super::state::{self, read_many};
-use super::state_versions::StateVersion;
 use super::state_views::StateView;
 use super::updates::Updates;
 use serde::de::DeserializeOwned;
@@ -21,9 +23,23 @@ pub trait MultiChainState:
     /// Table of the state as specified in StateMigrations
     fn table_name() -> &'static str;
 
+    /// Fields identifying the state, such as `&["token_id"]`. Updates and deletes
+    /// find the state by them, across chains, instead of by all of its fields,
+    /// so stale copies still update the right state. Defaults to none.
+    fn identity_fields() -> &'static [&'static str] {
+        &[]
+    }
+
     /// Inserts state in the state's table
     async fn create<'a, 'b>(&self, context: &PureHandlerContext<'a, 'b>) {
-        state::create(Self::table_name(), &state::to_view(self), context).await;
+        let table_name = Self::table_name();
+        let group_id = state::get_group_id(
+            table_name,
+            Self::identity_fields(),
+            &self.to_identity_view(),
+        );
+
+        state::create(table_name, &state::to_view(self), group_id, context).await;
     }
 
     /// Returns a single state matching filters. Panics if there are multiple.
@@ -36,63 +52,149 @@ pub trait MultiChainState:
         read_many(filters, context, Self::table_name()).await
     }
 
-    /// Updates state with the specified updates
+    /// Returns a single state matching filters as it was at the given block
+    async fn read_one_at<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        at: &AtBlock,
+        context: &C,
+    ) -> Option<Self> {
+        Self::read_many_at(filters, at, context).await.first().cloned()
+    }
+
+    /// Returns states matching filters as they were at the given block,
+    /// rebuilt from their versions
+    async fn read_many_at<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        at: &AtBlock,
+        context: &C,
+    ) -> Vec<Self> {
+        history::read_many_at_in_context(filters, at, context, Self::table_name()).await
+    }
+
+    /// Returns every version of the states matching filters, along with the events
+    /// that produced them. Filters match versions, ordered as their events happened.
+    async fn read_history<'a, C: HandlerContext<'a>>(
+        filters: &Filters,
+        context: &C,
+    ) -> Vec<VersionedState<Self>> {
+        history::read_history_in_context(filters, context, Self::table_name()).await
+    }
+
+    /// Returns the number of states matching filters
+    async fn count<'a, C: HandlerContext<'a>>(filters: &Filters, context: &C) -> i64 {
+        aggregates::aggregate_in_context(&Aggregate::Count, filters, context, Self::table_name())
+            .await
+    }
+
+    /// Returns the aggregate over the states matching filters, computed in SQL.
+    /// Their orderings and limits get ignored.
+    async fn aggregate<'a, C: HandlerContext<'a>, V: Send + DeserializeOwned>(
+        aggregate: &Aggregate,
+        filters: &Filters,
+        context: &C,
+    ) -> V {
+        aggregates::aggregate_in_context(aggregate, filters, context, Self::table_name()).await
+    }
+
+    /// Returns the aggregate over the states matching filters for each value of the
+    /// group field. Their orderings and limits apply to the groups, by the group field
+    /// or by `value`.
+    async fn aggregate_by<
+        'a,
+        C: HandlerContext<'a>,
+        K: Send + DeserializeOwned,
+        V: Send + DeserializeOwned,
+    >(
+        group_field: &str,
+        aggregate: &Aggregate,
+        filters: &Filters,
+        context: &C,
+    ) -> Vec<(K, V)> {
+        aggregates::aggregate_by_in_context(
+            group_field,
+            aggregate,
+            filters,
+            context,
+            Self::table_name(),
+        )
+        .await
+    }
+
+    /// Updates state with the specified updates.
+    /// Queued along with the handler's transaction, to get applied after it commits.
     async fn update<'a, 'b>(&self, updates: &Updates, context: &PureHandlerContext<'a, 'b>) {
-        let event = context.event.clone();
-        let client = context.repo_client;
         let table_name = Self::table_name();
-        let state_view = self.to_complete_view(table_name, client).await;
-        let updates = updates.clone();
-        let client = context.repo_client_for_mcs.clone();
-
-        context
-            .deferred_mutations_for_mcs
-            .add(async move {
-                let mut client = client.lock().await;
-
-                let latest_state_version = StateVersion::update_without_txn(
-                    &state_view,
-                    &updates.values,
-                    table_name,
-                    &event,
-                    &mut client,
-                )
-                .await;
-                StateView::refresh_without_txn(&latest_state_version, table_name, &client).await;
-            })
-            .await;
-    }
-
-    /// Deletes state from the state's table
-    async fn delete<'a, 'b>(&self, context: &PureHandlerContext<'a, 'b>) {
-        let event = context.event.clone();
-        let client = context.repo_client;
+        let state_view = self.get_complete_view(context).await;
+
+        let mutation = McsMutation::Update {
+            state_view,
+            updates: updates.clone(),
+        };
+        mcs_mutations::enqueue(&mutation, table_name, context).await;
+    }
+
+    /// Creates the state, or applies the updates to it if it already exists.
+    /// Finds it by its identity fields, which it should declare.
+    /// Applied along with other MultiChainState mutations, so concurrent handlers don't race.
+    async fn create_or_update<'a, 'b>(
+        &self,
+        updates: &Updates,
+        context: &PureHandlerContext<'a, 'b>,
+    ) {
         let table_name = Self::table_name();
-        let state_view = self.to_complete_view(table_name, client).await;
-        let client = context.repo_client_for_mcs.clone();
+        let identity_view = self.to_identity_view();
 
-        context
-            .deferred_mutations_for_mcs
-            .add(async move {
-                let client = client.lock().await;
+        let mutation = McsMutation::CreateOrUpdate {
+            state_view: self.to_view(),
+            group_id: state::get_group_id(table_name, Self::identity_fields(), &identity_view),
+            identity_view,
+            updates: updates.clone(),
+        };
+        mcs_mutations::enqueue(&mutation, table_name, context).await;
+    }
+
+    /// Deletes state from the state's table.
+    /// Queued along with the handler's transaction, to get applied after it commits.
+    async fn delete<'a, 'b>(&self, context: &PureHandlerContext<'a, 'b>) {
+        let table_name = Self::table_name();
+        let state_view = self.get_complete_view(context).await;
 
-                let latest_state_version =
-                    StateVersion::delete_without_txn(&state_view, table_name, &event, &client)
-                        .await;
-                StateView::refresh_without_txn(&latest_state_version, table_name, &client).await;
-            })
-            .await;
+        mcs_mutations::enqueue(&McsMutation::Delete { state_view }, table_name, context).await;
     }
 
-    fn to_view(&self) -> HashMap<String, String> {
+    fn to_view(&self) -> HashMap<String, SqlValue> {
         state::to_view(self)
     }
 
+    fn to_identity_view(&self) -> HashMap<String, SqlValue> {
+        state::to_identity_view(&self.to_view(), Self::identity_fields())
+    }
+
+    async fn find_complete_view<'a, 'b>(
+        &self,
+        context: &PureHandlerContext<'a, 'b>,
+    ) -> Option<HashMap<String, SqlValue>> {
+        state::find_complete_view(
+            Self::table_name(),
+            Self::identity_fields(),
+            &self.to_identity_view(),
+            context,
+        )
+        .await
+    }
+
+    async fn get_complete_view<'a, 'b>(
+        &self,
+        context: &PureHandlerContext<'a, 'b>,
+    ) -> HashMap<String, SqlValue> {
+        self.find_complete_view(context).await.unwrap()
+    }
+
     async fn to_complete_view<'a>(
         &self,
         table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
-        StateView::get_complete(&self.to_view(), table_name, client).await
+    ) -> HashMap<String, SqlValue> {
+        StateView::get_complete(&self.to_identity_view(), table_name, client).await
     }
 }
@@ -0,0 +1,143 @@
+use crate::{
+    ChaindexingRepo, ChaindexingRepoClient, ExecutesWithRawQuery, HasRawQueryClient,
+    LoadsDataWithRawQuery, SqlParams,
+};
+
+use super::aggregates::{self, Aggregate};
+use super::filters::Filters;
+use super::history::{self, AtBlock, VersionedState};
+use super::{to_typed_json, StateRow};
+use serde::de::DeserializeOwned;
+
+/// Client for reading states outside of handlers, such as in API servers. Its
+/// session is read-only, so it can point at a replica or share the indexer's database.
+/// Filters aren't scoped to any chain or contract, so scope them explicitly with
+/// `Filters::for_chain` or `Filters::for_contract_address`.
+///
+/// # Example
+///
+/// ```ignore
+/// let query_client = StateQueryClient::new(&ChaindexingRepo::new(&replica_url)).await;
+///
+/// let filters = Filters::new("owner", owner)
+///     .for_contract_address(&ChainId::Mainnet, BAYC_ADDRESS)
+///     .order_by("token_id", Order::Asc)
+///     .limit(20)
+///     .offset(40);
+/// let nfts: Vec<Nft> = query_client.read_many(Nft::table_name(), &filters).await;
+/// ```
+pub struct StateQueryClient {
+    client: ChaindexingRepoClient,
+}
+
+impl StateQueryClient {
+    /// Connects to the repo's database with a read-only session
+    pub async fn new(repo: &ChaindexingRepo) -> Self {
+        Self::from_client(repo.get_client().await).await
+    }
+
+    /// Makes the client's session read-only before reading with it
+    pub async fn from_client(client: ChaindexingRepoClient) -> Self {
+        ChaindexingRepo::execute(
+            &client,
+            "SET SESSION CHARACTERISTICS AS TRANSACTION READ ONLY",
+        )
+        .await;
+
+        Self { client }
+    }
+
+    pub(crate) fn get_client(&self) -> &ChaindexingRepoClient {
+        &self.client
+    }
+
+    /// Returns a single state of the table matching filters
+    pub async fn read_one<T: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        filters: &Filters,
+    ) -> Option<T> {
+        self.read_many(table_name, filters).await.into_iter().next()
+    }
+
+    /// Returns states of the table matching filters
+    pub async fn read_many<T: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        filters: &Filters,
+    ) -> Vec<T> {
+        let mut params = SqlParams::new();
+        let query = format!(
+            "SELECT {state} FROM {table_name} chaindexing_state
+            WHERE {filters}",
+            state = to_typed_json("chaindexing_state", table_name, &mut params),
+            filters = filters.to_unscoped_sql(&mut params),
+        );
+
+        ChaindexingRepo::load_data_list_with_params::<StateRow<T>>(
+            &self.client,
+            &query,
+            params.get_values(),
+        )
+        .await
+        .into_iter()
+        .map(|row| row.state)
+        .collect()
+    }
+
+    /// Returns a single state of the table matching filters as it was at the given block
+    pub async fn read_one_at<T: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        filters: &Filters,
+        at: &AtBlock,
+    ) -> Option<T> {
+        history::read_one_at(table_name, filters, at, &self.client).await
+    }
+
+    /// Returns states of the table matching filters as they were at the given block
+    pub async fn read_many_at<T: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        filters: &Filters,
+        at: &AtBlock,
+    ) -> Vec<T> {
+        history::read_many_at(table_name, filters, at, &self.client).await
+    }
+
+    /// Returns the versions of the table's states matching filters
+    pub async fn read_history<T: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        filters: &Filters,
+    ) -> Vec<VersionedState<T>> {
+        history::read_history(table_name, filters, &self.client).await
+    }
+
+    /// Returns the number of the table's states matching filters
+    pub async fn count(&self, table_name: &str, filters: &Filters) -> i64 {
+        self.aggregate(table_name, &Aggregate::Count, filters).await
+    }
+
+    /// Returns the aggregate over the table's states matching filters
+    pub async fn aggregate<V: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        aggregate: &Aggregate,
+        filters: &Filters,
+    ) -> V {
+        aggregates::aggregate(table_name, aggregate, filters, &self.client).await
+    }
+
+    /// Returns the aggregate over the table's states matching filters for each
+    /// value of the group field
+    pub async fn aggregate_by<K: Send + DeserializeOwned, V: Send + DeserializeOwned>(
+        &self,
+        table_name: &str,
+        group_field: &str,
+        aggregate: &Aggregate,
+        filters: &Filters,
+    ) -> Vec<(K, V)> {
+        aggregates::aggregate_by(table_name, group_field, aggregate, filters, &self.client).await
+    }
+}
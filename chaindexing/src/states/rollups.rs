@@ -0,0 +1,582 @@
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+use crate::handlers::{HandlerContext, PureHandlerContext};
+use crate::{Event, SqlValue};
+
+use super::filters::Filters;
+use super::migrations::StateMigrations;
+use super::state;
+use super::state_versions::StateVersion;
+use super::updates::Updates;
+use serde::de::DeserializeOwned;
+
+const BUCKET_START_FIELD: &str = "bucket_start";
+const ROLLUP_COLUMN_FIELD: &str = "rollup_column";
+const DISTINCT_VALUE_FIELD: &str = "distinct_value";
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum RollupFunction {
+    Count,
+    Sum,
+    Min,
+    Max,
+    First,
+    Last,
+    CountDistinct,
+}
+
+#[derive(Clone, Debug)]
+struct RollupColumn {
+    name: String,
+    field: String,
+    function: RollupFunction,
+    data_type: String,
+}
+
+/// State aggregating events into time buckets, such as hourly volumes or daily
+/// OHLC prices, by their block timestamps. Its tables get generated, with a row
+/// per group and bucket, and backtracked like those of any other state.
+///
+/// # Example
+///
+/// ```ignore
+/// let pool_candles = Rollup::new("pool_candles", 60 * 60)
+///     .group_by("pool_address", "TEXT")
+///     .count()
+///     .sum("volume", "NUMERIC(78, 0)")
+///     .ohlc("price", "NUMERIC(78, 0)")
+///     .count_distinct("trader");
+///
+/// let contract = Contract::new("Pool").add_state_migrations(pool_candles.clone());
+///
+/// // In the handler
+/// let entry = RollupEntry::new()
+///     .group("pool_address", pool_address)
+///     .value("volume", amount)
+///     .value("price", price)
+///     .value("trader", trader);
+/// pool_candles.record(&entry, &context).await;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Rollup {
+    table_name: String,
+    bucket_size: u64,
+    group_fields: Vec<(String, String)>,
+    columns: Vec<RollupColumn>,
+    version: u64,
+    migrations: OnceLock<&'static [&'static str]>,
+}
+
+impl Rollup {
+    /// Creates a rollup stored in the given table, bucketing events every
+    /// `bucket_size` seconds since the Unix epoch
+    pub fn new(table_name: &str, bucket_size: u64) -> Self {
+        assert!(bucket_size > 0, "Rollup {table_name} needs a bucket size");
+
+        Self {
+            table_name: table_name.to_string(),
+            bucket_size,
+            group_fields: vec![],
+            columns: vec![],
+            version: 1,
+            migrations: OnceLock::new(),
+        }
+    }
+
+    /// Keeps separate buckets for each value of the field, such as a pool's address
+    pub fn group_by(mut self, field: &str, data_type: &str) -> Self {
+        self.group_fields.push((field.to_string(), data_type.to_string()));
+        self
+    }
+
+    /// Counts the bucket's events in a `count` column
+    pub fn count(self) -> Self {
+        self.add_column("count", "", RollupFunction::Count, "BIGINT")
+    }
+
+    /// Sums the field's values in a `{field}_sum` column
+    pub fn sum(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_sum"),
+            field,
+            RollupFunction::Sum,
+            data_type,
+        )
+    }
+
+    /// Keeps the field's lowest value in a `{field}_min` column
+    pub fn min(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_min"),
+            field,
+            RollupFunction::Min,
+            data_type,
+        )
+    }
+
+    /// Keeps the field's highest value in a `{field}_max` column
+    pub fn max(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_max"),
+            field,
+            RollupFunction::Max,
+            data_type,
+        )
+    }
+
+    /// Keeps the field's first value in the bucket in a `{field}_first` column
+    pub fn first(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_first"),
+            field,
+            RollupFunction::First,
+            data_type,
+        )
+    }
+
+    /// Keeps the field's last value in the bucket in a `{field}_last` column
+    pub fn last(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_last"),
+            field,
+            RollupFunction::Last,
+            data_type,
+        )
+    }
+
+    /// Keeps the field's open, high, low and close values in `{field}_open`,
+    /// `{field}_high`, `{field}_low` and `{field}_close` columns
+    pub fn ohlc(self, field: &str, data_type: &str) -> Self {
+        self.add_column(
+            &format!("{field}_open"),
+            field,
+            RollupFunction::First,
+            data_type,
+        )
+        .add_column(
+            &format!("{field}_high"),
+            field,
+            RollupFunction::Max,
+            data_type,
+        )
+        .add_column(
+            &format!("{field}_low"),
+            field,
+            RollupFunction::Min,
+            data_type,
+        )
+        .add_column(
+            &format!("{field}_close"),
+            field,
+            RollupFunction::Last,
+            data_type,
+        )
+    }
+
+    /// Counts the field's distinct values, such as unique users, in a
+    /// `{field}_distinct_count` column. The values seen get kept in the
+    /// rollup's `{table_name}_distinct_values` table.
+    pub fn count_distinct(self, field: &str) -> Self {
+        self.add_column(
+            &format!("{field}_distinct_count"),
+            field,
+            RollupFunction::CountDistinct,
+            "BIGINT",
+        )
+    }
+
+    /// Version of the rollup's migrations, to bump after changing its definition.
+    /// See [`StateMigrations::version`].
+    pub fn version(mut self, version: u64) -> Self {
+        self.version = version;
+        self
+    }
+
+    fn add_column(
+        mut self,
+        name: &str,
+        field: &str,
+        function: RollupFunction,
+        data_type: &str,
+    ) -> Self {
+        self.columns.push(RollupColumn {
+            name: name.to_string(),
+            field: field.to_string(),
+            function,
+            data_type: data_type.to_string(),
+        });
+        self
+    }
+
+    /// Table of the rollup's buckets
+    pub fn table_name(&self) -> &str {
+        &self.table_name
+    }
+
+    /// Records the entry's values in the bucket of the event's block timestamp,
+    /// creating the bucket on its first entry
+    pub async fn record<'a, 'b>(&self, entry: &RollupEntry, context: &PureHandlerContext<'a, 'b>) {
+        let event = &context.event;
+        let bucket_view = self.to_bucket_view(entry, event);
+        let new_distinct_columns = self.record_distinct_values(entry, &bucket_view, context).await;
+
+        let identity_fields = self.get_identity_fields();
+        let identity_view = to_identity_view(&bucket_view, event);
+
+        match state::find_complete_view(&self.table_name, &identity_fields, &identity_view, context)
+            .await
+        {
+            Some(state_view) => {
+                let updates = self.to_updates(entry, &new_distinct_columns);
+                let latest_state_version = StateVersion::update(
+                    &state_view,
+                    &updates,
+                    &self.table_name,
+                    event,
+                    context.repo_client,
+                )
+                .await;
+                state::refresh_view(&latest_state_version, &self.table_name, context).await;
+            }
+            None => {
+                let mut state_view = bucket_view;
+                state_view.extend(self.to_initial_values(entry));
+                let group_id =
+                    state::get_group_id(&self.table_name, &identity_fields, &identity_view);
+
+                state::create(&self.table_name, &state_view, group_id, context).await;
+            }
+        }
+    }
+
+    /// Returns the buckets matching filters
+    pub async fn read_many<'a, C: HandlerContext<'a>, T: Send + DeserializeOwned>(
+        &self,
+        filters: &Filters,
+        context: &C,
+    ) -> Vec<T> {
+        state::read_many(filters, context, &self.table_name).await
+    }
+
+    /// Records the entry's distinct values new to the bucket, returning the
+    /// columns they count towards
+    async fn record_distinct_values<'a, 'b>(
+        &self,
+        entry: &RollupEntry,
+        bucket_view: &HashMap<String, SqlValue>,
+        context: &PureHandlerContext<'a, 'b>,
+    ) -> Vec<String> {
+        let table_name = self.get_distinct_values_table_name();
+        let identity_fields = self.get_distinct_values_identity_fields();
+        let mut new_distinct_columns = vec![];
+
+        for column in self.get_columns(RollupFunction::CountDistinct) {
+            let mut distinct_value_view = bucket_view.clone();
+            distinct_value_view
+                .insert(ROLLUP_COLUMN_FIELD.to_string(), column.name.as_str().into());
+            distinct_value_view.insert(
+                DISTINCT_VALUE_FIELD.to_string(),
+                self.get_value(entry, &column.field).into(),
+            );
+            let identity_view = to_identity_view(&distinct_value_view, &context.event);
+
+            let distinct_value =
+                state::find_complete_view(&table_name, &identity_fields, &identity_view, context)
+                    .await;
+
+            if distinct_value.is_none() {
+                let group_id = state::get_group_id(&table_name, &identity_fields, &identity_view);
+                state::create(&table_name, &distinct_value_view, group_id, context).await;
+
+                new_distinct_columns.push(column.name.clone());
+            }
+        }
+
+        new_distinct_columns
+    }
+
+    fn get_bucket_start(&self, block_timestamp: u64) -> u64 {
+        block_timestamp - block_timestamp % self.bucket_size
+    }
+
+    fn to_bucket_view(&self, entry: &RollupEntry, event: &Event) -> HashMap<String, SqlValue> {
+        let mut bucket_view: HashMap<_, _> = self
+            .group_fields
+            .iter()
+            .map(|(field, _data_type)| {
+                let value = entry.groups.get(field).unwrap_or_else(|| {
+                    panic!("Rollup {} is missing group {field}", self.table_name)
+                });
+
+                (field.clone(), value.as_str().into())
+            })
+            .collect();
+
+        let bucket_start = self.get_bucket_start(event.get_block_timestamp()) as i64;
+        bucket_view.insert(BUCKET_START_FIELD.to_string(), bucket_start.into());
+
+        bucket_view
+    }
+
+    fn to_initial_values(&self, entry: &RollupEntry) -> HashMap<String, SqlValue> {
+        self.columns
+            .iter()
+            .map(|column| {
+                let value = match column.function {
+                    RollupFunction::Count | RollupFunction::CountDistinct => "1",
+                    _ => self.get_value(entry, &column.field),
+                };
+
+                (column.name.clone(), value.into())
+            })
+            .collect()
+    }
+
+    fn to_updates(&self, entry: &RollupEntry, new_distinct_columns: &[String]) -> Updates {
+        self.columns.iter().fold(Updates::default(), |updates, column| {
+            let name = &column.name;
+
+            match column.function {
+                RollupFunction::Count => updates.add_increment(name, 1),
+                RollupFunction::Sum => {
+                    updates.add_increment(name, self.get_value(entry, &column.field))
+                }
+                RollupFunction::Min => updates.add_min(name, self.get_value(entry, &column.field)),
+                RollupFunction::Max => updates.add_max(name, self.get_value(entry, &column.field)),
+                RollupFunction::First => updates,
+                RollupFunction::Last => updates.add(name, self.get_value(entry, &column.field)),
+                RollupFunction::CountDistinct if new_distinct_columns.contains(name) => {
+                    updates.add_increment(name, 1)
+                }
+                RollupFunction::CountDistinct => updates,
+            }
+        })
+    }
+
+    fn get_value<'e>(&self, entry: &'e RollupEntry, field: &str) -> &'e str {
+        entry
+            .values
+            .get(field)
+            .unwrap_or_else(|| panic!("Rollup {} is missing value {field}", self.table_name))
+    }
+
+    fn get_columns(&self, function: RollupFunction) -> impl Iterator<Item = &RollupColumn> {
+        self.columns.iter().filter(move |column| column.function == function)
+    }
+
+    fn get_identity_fields(&self) -> Vec<&str> {
+        self.group_fields
+            .iter()
+            .map(|(field, _data_type)| field.as_str())
+            .chain([BUCKET_START_FIELD])
+            .collect()
+    }
+
+    fn get_distinct_values_identity_fields(&self) -> Vec<&str> {
+        let mut identity_fields = self.get_identity_fields();
+        identity_fields.extend([ROLLUP_COLUMN_FIELD, DISTINCT_VALUE_FIELD]);
+        identity_fields
+    }
+
+    fn get_distinct_values_table_name(&self) -> String {
+        format!("{}_distinct_values", self.table_name)
+    }
+
+    fn get_user_migrations(&self) -> Vec<String> {
+        let table_name = &self.table_name;
+        let group_columns: Vec<_> = self
+            .group_fields
+            .iter()
+            .map(|(field, data_type)| format!("{field} {data_type} NOT NULL"))
+            .chain([format!("{BUCKET_START_FIELD} BIGINT NOT NULL")])
+            .collect();
+        let columns: Vec<_> = group_columns
+            .iter()
+            .cloned()
+            .chain(
+                self.columns
+                    .iter()
+                    .map(|column| format!("{} {} NOT NULL", column.name, column.data_type)),
+            )
+            .collect();
+
+        let mut migrations = vec![
+            format!(
+                "CREATE TABLE IF NOT EXISTS {table_name} ({})",
+                columns.join(", ")
+            ),
+            format!(
+                "CREATE INDEX IF NOT EXISTS {table_name}_{BUCKET_START_FIELD}_index
+                ON {table_name}({BUCKET_START_FIELD})"
+            ),
+        ];
+
+        if self.get_columns(RollupFunction::CountDistinct).next().is_some() {
+            let distinct_columns: Vec<_> = group_columns
+                .into_iter()
+                .chain([
+                    format!("{ROLLUP_COLUMN_FIELD} TEXT NOT NULL"),
+                    format!("{DISTINCT_VALUE_FIELD} TEXT NOT NULL"),
+                ])
+                .collect();
+
+            migrations.push(format!(
+                "CREATE TABLE IF NOT EXISTS {} ({})",
+                self.get_distinct_values_table_name(),
+                distinct_columns.join(", ")
+            ));
+        }
+
+        migrations
+    }
+}
+
+impl StateMigrations for Rollup {
+    fn migrations(&self) -> &'static [&'static str] {
+        // Generated once per rollup, since they get read on every boot and batch
+        self.migrations.get_or_init(|| {
+            let migrations: Vec<&'static str> = self
+                .get_user_migrations()
+                .into_iter()
+                .map(|migration| &*Box::leak(migration.into_boxed_str()))
+                .collect();
+
+            Box::leak(migrations.into_boxed_slice())
+        })
+    }
+
+    fn version(&self) -> u64 {
+        self.version
+    }
+}
+
+/// Groups and values of an event recorded in a [`Rollup`]
+#[derive(Clone, Debug, Default)]
+pub struct RollupEntry {
+    groups: HashMap<String, String>,
+    values: HashMap<String, String>,
+}
+
+impl RollupEntry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of a field the rollup groups by
+    pub fn group(mut self, field: impl ToString, value: impl ToString) -> Self {
+        self.groups.insert(field.to_string(), value.to_string());
+        self
+    }
+
+    /// Sets the value of a field the rollup aggregates
+    pub fn value(mut self, field: impl ToString, value: impl ToString) -> Self {
+        self.values.insert(field.to_string(), value.to_string());
+        self
+    }
+}
+
+fn to_identity_view(
+    bucket_view: &HashMap<String, SqlValue>,
+    event: &Event,
+) -> HashMap<String, SqlValue> {
+    let mut identity_view = bucket_view.clone();
+    identity_view.insert("chain_id".to_string(), event.chain_id.into());
+    identity_view.insert(
+        "contract_address".to_string(),
+        event.contract_address.as_str().into(),
+    );
+    identity_view
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pool_candles() -> Rollup {
+        Rollup::new("pool_candles", 3600)
+            .group_by("pool_address", "TEXT")
+            .count()
+            .sum("volume", "NUMERIC(78, 0)")
+            .ohlc("price", "NUMERIC(78, 0)")
+            .count_distinct("trader")
+    }
+
+    fn entry() -> RollupEntry {
+        RollupEntry::new()
+            .group("pool_address", "0x1")
+            .value("volume", 10)
+            .value("price", 5)
+            .value("trader", "0x2")
+    }
+
+    #[test]
+    fn generates_tables_of_buckets_and_distinct_values() {
+        let rollup = pool_candles();
+
+        assert_eq!(
+            rollup.get_table_names(),
+            vec!["pool_candles", "pool_candles_distinct_values"]
+        );
+        assert_eq!(
+            rollup.migrations()[0],
+            "CREATE TABLE IF NOT EXISTS pool_candles (pool_address TEXT NOT NULL, \
+            bucket_start BIGINT NOT NULL, count BIGINT NOT NULL, \
+            volume_sum NUMERIC(78, 0) NOT NULL, price_open NUMERIC(78, 0) NOT NULL, \
+            price_high NUMERIC(78, 0) NOT NULL, price_low NUMERIC(78, 0) NOT NULL, \
+            price_close NUMERIC(78, 0) NOT NULL, trader_distinct_count BIGINT NOT NULL)"
+        );
+        assert!(rollup.get_migrations().iter().any(|migration| {
+            migration.contains("chaindexing_state_versions_for_pool_candles_distinct_values")
+        }));
+
+        let rollup = Rollup::new("pool_volumes", 3600).sum("volume", "NUMERIC(78, 0)");
+        assert_eq!(rollup.get_table_names(), vec!["pool_volumes"]);
+    }
+
+    #[test]
+    fn starts_buckets_at_multiples_of_their_size() {
+        let rollup = pool_candles();
+
+        assert_eq!(rollup.get_bucket_start(0), 0);
+        assert_eq!(rollup.get_bucket_start(3599), 0);
+        assert_eq!(rollup.get_bucket_start(3600), 3600);
+        assert_eq!(rollup.get_bucket_start(7300), 7200);
+    }
+
+    #[test]
+    fn aggregates_values_into_existing_buckets() {
+        let rollup = pool_candles();
+
+        let initial_values = rollup.to_initial_values(&entry());
+        assert_eq!(initial_values["count"], "1".into());
+        assert_eq!(initial_values["price_open"], "5".into());
+        assert_eq!(initial_values["price_close"], "5".into());
+        assert_eq!(initial_values["trader_distinct_count"], "1".into());
+
+        let updates = rollup.to_updates(&entry(), &[]);
+        assert_eq!(
+            updates,
+            Updates::default()
+                .add_increment("count", 1)
+                .add_increment("volume_sum", 10)
+                .add_max("price_high", 5)
+                .add_min("price_low", 5)
+                .add("price_close", 5)
+        );
+
+        let updates = rollup.to_updates(&entry(), &["trader_distinct_count".to_string()]);
+        assert!(updates.deltas.contains_key("trader_distinct_count"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Rollup pool_candles is missing value volume")]
+    fn requires_values_of_every_aggregated_field() {
+        let entry = RollupEntry::new()
+            .group("pool_address", "0x1")
+            .value("price", 5)
+            .value("trader", "0x2");
+
+        pool_candles().to_updates(&entry, &[]);
+    }
+}
@@ -1,16 +1,16 @@
 use std::collections::HashMap;
 
 use crate::handlers::{HandlerContext, PureHandlerContext};
-use crate::{ChaindexingRepo, LoadsDataWithRawQuery};
+use crate::{ChaindexingRepo, LoadsDataWithRawQuery, SqlParams, SqlValue};
 
 use super::filters::Filters;
 use super::state_versions::StateVersion;
 use super::state_views::StateView;
-use super::{serde_map_to_string_map, to_and_filters};
+use super::{serde_map_to_sql_map, to_typed_json, StateRow};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
-pub fn to_view<T>(value: &T) -> HashMap<String, String>
+pub fn to_view<T>(value: &T) -> HashMap<String, SqlValue>
 where
     T: Serialize,
 {
@@ -18,7 +18,45 @@ where
 
     let map: HashMap<String, serde_json::Value> = serde_json::from_value(state).unwrap();
 
-    serde_map_to_string_map(&map)
+    serde_map_to_sql_map(&map)
+}
+
+/// Identity fields of the state view, or all of its fields when it declares none
+pub fn to_identity_view(
+    state_view: &HashMap<String, SqlValue>,
+    identity_fields: &[&str],
+) -> HashMap<String, SqlValue> {
+    if identity_fields.is_empty() {
+        return state_view.clone();
+    }
+
+    state_view
+        .iter()
+        .filter(|(field, _value)| identity_fields.contains(&field.as_str()))
+        .map(|(field, value)| (field.clone(), value.clone()))
+        .collect()
+}
+
+/// Derives the `state_version_group_id` of states declaring identity fields from
+/// their identity view, so every version of the same identity shares it
+pub fn get_group_id(
+    table_name: &str,
+    identity_fields: &[&str],
+    identity_view: &HashMap<String, SqlValue>,
+) -> Option<String> {
+    if identity_fields.is_empty() {
+        return None;
+    }
+
+    let mut identity: Vec<_> = identity_view
+        .iter()
+        .map(|(field, value)| format!("{field}={}", value.to_text().unwrap_or("NULL".to_string())))
+        .collect();
+    identity.sort();
+
+    let name = format!("{table_name}:{}", identity.join(","));
+
+    Some(uuid::Uuid::new_v5(&uuid::Uuid::NAMESPACE_OID, name.as_bytes()).to_string())
 }
 
 pub async fn read_many<'a, C: HandlerContext<'a>, T: Send + DeserializeOwned>(
@@ -28,24 +66,140 @@ pub async fn read_many<'a, C: HandlerContext<'a>, T: Send + DeserializeOwned>(
 ) -> Vec<T> {
     let client = context.get_client();
 
+    if let Some(state_cache) = context.get_state_cache() {
+        state_cache.flush_table(table_name, client).await;
+    }
+
+    let mut params = SqlParams::new();
     let query = format!(
-        "SELECT * FROM {table_name} 
+        "SELECT {state} FROM {table_name} chaindexing_state
         WHERE {filters}",
+        state = to_typed_json("chaindexing_state", table_name, &mut params),
         table_name = table_name,
-        filters = to_and_filters(&filters.get(context.get_event())),
+        filters = filters.to_sql(context.get_event(), &mut params),
     );
 
-    ChaindexingRepo::load_data_list_in_txn(client, &query).await
+    ChaindexingRepo::load_data_list_in_txn_with_params::<StateRow<T>>(
+        client,
+        &query,
+        params.get_values(),
+    )
+    .await
+    .into_iter()
+    .map(|row| row.state)
+    .collect()
 }
 
 pub async fn create<'a, 'b>(
     table_name: &str,
-    state_view: &HashMap<String, String>,
+    state_view: &HashMap<String, SqlValue>,
+    group_id: Option<String>,
     context: &PureHandlerContext<'a, 'b>,
 ) {
     let event = &context.event;
     let client = context.repo_client;
 
-    let latest_state_version = StateVersion::create(state_view, table_name, event, client).await;
-    StateView::refresh(&latest_state_version, table_name, client).await;
+    let latest_state_version =
+        StateVersion::create(state_view, group_id, table_name, event, client).await;
+    refresh_view(&latest_state_version, table_name, context).await;
+}
+
+/// Finds the complete view of the state by its identity view, from the batch's
+/// cache when the context has one
+pub async fn find_complete_view<'a, 'b>(
+    table_name: &str,
+    identity_fields: &[&str],
+    identity_view: &HashMap<String, SqlValue>,
+    context: &PureHandlerContext<'a, 'b>,
+) -> Option<HashMap<String, SqlValue>> {
+    let client = context.repo_client;
+
+    let Some(state_cache) = &context.state_cache else {
+        return StateView::find_complete(identity_view, table_name, client).await;
+    };
+
+    let group_id = get_group_id(table_name, identity_fields, identity_view);
+    if let Some(state_view) = state_cache.find(table_name, identity_view, group_id.as_deref()).await
+    {
+        return state_view;
+    }
+
+    // Views pending a refresh could otherwise be found stale
+    state_cache.flush_table(table_name, client).await;
+
+    let state_view = StateView::find_complete(identity_view, table_name, client).await;
+    if let Some(state_view) = &state_view {
+        state_cache.insert(table_name, state_view).await;
+    }
+
+    state_view
+}
+
+/// Refreshes the view of the latest version, or defers it to the batch's cache
+/// when the context has one
+pub async fn refresh_view<'a, 'b>(
+    latest_state_version: &HashMap<String, SqlValue>,
+    table_name: &str,
+    context: &PureHandlerContext<'a, 'b>,
+) {
+    match &context.state_cache {
+        Some(state_cache) => state_cache.refresh(latest_state_version, table_name).await,
+        None => StateView::refresh(latest_state_version, table_name, context.repo_client).await,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_view(token_id: i64, owner_address: &str) -> HashMap<String, SqlValue> {
+        HashMap::from([
+            ("token_id".to_string(), token_id.into()),
+            ("owner_address".to_string(), owner_address.into()),
+        ])
+    }
+
+    #[test]
+    fn keeps_identity_fields_only() {
+        let identity_view = to_identity_view(&state_view(1, "0x1"), &["token_id"]);
+
+        assert_eq!(
+            identity_view,
+            HashMap::from([("token_id".to_string(), 1.into())])
+        );
+        assert_eq!(
+            to_identity_view(&state_view(1, "0x1"), &[]),
+            state_view(1, "0x1")
+        );
+    }
+
+    #[test]
+    fn derives_the_same_group_id_for_the_same_identity() {
+        let group_id = |view| {
+            get_group_id(
+                "nfts",
+                &["token_id"],
+                &to_identity_view(&view, &["token_id"]),
+            )
+        };
+
+        assert!(group_id(state_view(1, "0x1")).is_some());
+        assert_eq!(
+            group_id(state_view(1, "0x1")),
+            group_id(state_view(1, "0x2"))
+        );
+        assert_ne!(
+            group_id(state_view(1, "0x1")),
+            group_id(state_view(2, "0x1"))
+        );
+        assert_ne!(
+            group_id(state_view(1, "0x1")),
+            get_group_id(
+                "listings",
+                &["token_id"],
+                &to_identity_view(&state_view(1, "0x1"), &["token_id"])
+            )
+        );
+        assert_eq!(get_group_id("nfts", &[], &state_view(1, "0x1")), None);
+    }
 }
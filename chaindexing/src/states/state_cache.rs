@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use tokio::sync::Mutex;
+
+use crate::{ChaindexingRepoTxnClient, SqlValue};
+
+use super::state_versions::StateVersion;
+use super::state_views::StateView;
+
+/// Write-through cache of the state views touched within a batch's transaction.
+/// Views found by identity get served from it, while the refreshes of written
+/// ones get deferred, so only the final view of each `state_version_group_id`
+/// gets written. A table's pending views get flushed before any filtered read
+/// of it, and every pending view before the transaction commits.
+#[derive(Clone, Default)]
+pub struct StateCache {
+    tables: Arc<Mutex<HashMap<String, CachedTable>>>,
+}
+
+#[derive(Default)]
+struct CachedTable {
+    /// Latest views by `state_version_group_id`. None for deleted states.
+    views: HashMap<String, Option<HashMap<String, SqlValue>>>,
+    /// Latest versions whose views are yet to be refreshed
+    pending_versions: HashMap<String, HashMap<String, SqlValue>>,
+}
+
+impl CachedTable {
+    /// Finds the view by its group id, when known, or else by matching its identity.
+    /// Values get compared as text, since cached views, read as exact JSON, have
+    /// their numbers as text.
+    fn find(
+        &self,
+        identity_view: &HashMap<String, SqlValue>,
+        group_id: Option<&str>,
+    ) -> Option<Option<HashMap<String, SqlValue>>> {
+        match group_id {
+            Some(group_id) => self.views.get(group_id).cloned(),
+            None => self
+                .views
+                .values()
+                .flatten()
+                .find(|view| {
+                    identity_view.iter().all(|(field, value)| {
+                        view.get(field).map(SqlValue::to_text) == Some(value.to_text())
+                    })
+                })
+                .map(|view| Some(view.clone())),
+        }
+    }
+}
+
+impl StateCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns None when the cache doesn't know about the state, and Some(None)
+    /// when it knows the state got deleted
+    pub(crate) async fn find(
+        &self,
+        table_name: &str,
+        identity_view: &HashMap<String, SqlValue>,
+        group_id: Option<&str>,
+    ) -> Option<Option<HashMap<String, SqlValue>>> {
+        let tables = self.tables.lock().await;
+
+        tables.get(table_name).and_then(|table| table.find(identity_view, group_id))
+    }
+
+    /// Caches a view read from its table, which has no pending refresh
+    pub(crate) async fn insert(&self, table_name: &str, state_view: &HashMap<String, SqlValue>) {
+        let mut tables = self.tables.lock().await;
+
+        tables.entry(table_name.to_string()).or_default().views.insert(
+            StateVersion::get_group_id(state_view),
+            Some(state_view.clone()),
+        );
+    }
+
+    /// Caches the view of the latest version, deferring its refresh
+    pub(crate) async fn refresh(
+        &self,
+        latest_state_version: &HashMap<String, SqlValue>,
+        table_name: &str,
+    ) {
+        let mut tables = self.tables.lock().await;
+        let table = tables.entry(table_name.to_string()).or_default();
+        let group_id = StateVersion::get_group_id(latest_state_version);
+
+        let state_view = if StateVersion::was_deleted(latest_state_version) {
+            None
+        } else {
+            Some(StateView::from_latest_state_version(latest_state_version))
+        };
+
+        table.views.insert(group_id.clone(), state_view);
+        table.pending_versions.insert(group_id, latest_state_version.clone());
+    }
+
+    /// Refreshes the pending views of the table
+    pub(crate) async fn flush_table<'a>(
+        &self,
+        table_name: &str,
+        client: &ChaindexingRepoTxnClient<'a>,
+    ) {
+        let pending_versions = {
+            let mut tables = self.tables.lock().await;
+
+            tables
+                .get_mut(table_name)
+                .map(|table| std::mem::take(&mut table.pending_versions))
+                .unwrap_or_default()
+        };
+
+        for latest_state_version in pending_versions.values() {
+            StateView::refresh(latest_state_version, table_name, client).await;
+        }
+    }
+
+    /// Refreshes every pending view. Must run before the transaction commits.
+    pub async fn flush<'a>(&self, client: &ChaindexingRepoTxnClient<'a>) {
+        let table_names: Vec<_> = self.tables.lock().await.keys().cloned().collect();
+
+        for table_name in table_names {
+            self.flush_table(&table_name, client).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state_version(group_id: &str, token_id: i64, is_deleted: bool) -> HashMap<String, SqlValue> {
+        HashMap::from([
+            ("state_version_id".to_string(), 1.into()),
+            ("state_version_is_deleted".to_string(), is_deleted.into()),
+            ("state_version_group_id".to_string(), group_id.into()),
+            ("token_id".to_string(), token_id.into()),
+        ])
+    }
+
+    fn identity_view(token_id: i64) -> HashMap<String, SqlValue> {
+        HashMap::from([("token_id".to_string(), token_id.into())])
+    }
+
+    #[tokio::test]
+    async fn finds_refreshed_views_by_group_id() {
+        let cache = StateCache::new();
+        cache.refresh(&state_version("g1", 1, false), "nfts").await;
+
+        let state_view = cache.find("nfts", &identity_view(2), Some("g1")).await;
+
+        assert_eq!(
+            state_view,
+            Some(Some(HashMap::from([
+                ("state_version_group_id".to_string(), "g1".into()),
+                ("token_id".to_string(), 1.into()),
+            ])))
+        );
+        assert_eq!(
+            cache.find("nfts", &identity_view(1), Some("g2")).await,
+            None
+        );
+        assert_eq!(
+            cache.find("listings", &identity_view(1), Some("g1")).await,
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn finds_views_by_identity_without_group_id() {
+        let cache = StateCache::new();
+        cache.refresh(&state_version("g1", 1, false), "nfts").await;
+        cache.refresh(&state_version("g2", 2, false), "nfts").await;
+
+        let state_view = cache.find("nfts", &identity_view(2), None).await.flatten().unwrap();
+
+        assert_eq!(state_view.get("state_version_group_id"), Some(&"g2".into()));
+        assert_eq!(cache.find("nfts", &identity_view(3), None).await, None);
+    }
+
+    #[tokio::test]
+    async fn finds_views_read_as_exact_json_by_identity() {
+        let cache = StateCache::new();
+        let state_view = HashMap::from([
+            ("state_version_group_id".to_string(), "g1".into()),
+            ("token_id".to_string(), "1".into()),
+        ]);
+        cache.insert("nfts", &state_view).await;
+
+        assert_eq!(
+            cache.find("nfts", &identity_view(1), None).await,
+            Some(Some(state_view))
+        );
+    }
+
+    #[tokio::test]
+    async fn remembers_deleted_states() {
+        let cache = StateCache::new();
+        cache.refresh(&state_version("g1", 1, false), "nfts").await;
+        cache.refresh(&state_version("g1", 1, true), "nfts").await;
+
+        assert_eq!(
+            cache.find("nfts", &identity_view(1), Some("g1")).await,
+            Some(None)
+        );
+        assert_eq!(cache.find("nfts", &identity_view(1), None).await, None);
+    }
+
+    #[tokio::test]
+    async fn keeps_only_the_latest_pending_version_per_group() {
+        let cache = StateCache::new();
+        cache.refresh(&state_version("g1", 1, false), "nfts").await;
+        cache.refresh(&state_version("g1", 2, false), "nfts").await;
+
+        let tables = cache.tables.lock().await;
+        let pending_versions = &tables.get("nfts").unwrap().pending_versions;
+
+        assert_eq!(pending_versions.len(), 1);
+        assert_eq!(
+            pending_versions.get("g1").unwrap().get("token_id"),
+            Some(&2.into())
+        );
+    }
+}
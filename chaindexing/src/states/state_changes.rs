@@ -0,0 +1,228 @@
+use std::collections::HashMap;
+
+use tokio::sync::broadcast;
+
+use crate::{ChaindexingRepo, ChaindexingRepoClient, ExecutesWithRawQuery, HasRawQueryClient};
+use crate::{SqlParams, SqlValue};
+
+use super::to_typed_json_value;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Postgres channel state changes get notified on, for listening across processes
+pub const STATE_CHANGES_CHANNEL: &str = "chaindexing_state_changes";
+
+const STATE_CHANGES_CAPACITY: usize = 1_024;
+
+/// Kind of change of a state's view
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StateChangeOp {
+    Create,
+    Update,
+    Delete,
+    /// Backtracked by a chain reorg, to the state's previous view if any
+    Retract,
+}
+
+/// Committed change of a state's view. Changes of the same state within a
+/// handlers' batch get coalesced into one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StateChange {
+    pub table_name: String,
+    pub op: StateChangeOp,
+    /// None for created states
+    pub old_view: Option<serde_json::Value>,
+    /// None for deleted states
+    pub new_view: Option<serde_json::Value>,
+    pub chain_id: u64,
+    /// Block of the event, or the block backtracked from for retractions
+    pub block_number: u64,
+    /// The event's, None for retractions
+    pub contract_address: Option<String>,
+    pub block_hash: Option<String>,
+    pub transaction_hash: Option<String>,
+    pub log_index: Option<u32>,
+    /// Whether the views got left out, for exceeding the size of notifications.
+    /// Read the state again in that case.
+    #[serde(default)]
+    pub is_truncated: bool,
+}
+
+impl StateChange {
+    /// Returns the state as it was before the change
+    pub fn old_state<T: DeserializeOwned>(&self) -> Option<T> {
+        self.old_view.clone().map(|view| serde_json::from_value(view).unwrap())
+    }
+
+    /// Returns the state as it is after the change
+    pub fn new_state<T: DeserializeOwned>(&self) -> Option<T> {
+        self.new_view.clone().map(|view| serde_json::from_value(view).unwrap())
+    }
+}
+
+/// In-process feed of the state changes committed by every indexing process
+/// sharing the database, with `Config::with_state_changes`. It listens to them
+/// on a dedicated connection, so subscribers only get changes once committed,
+/// and stops once every clone gets dropped.
+///
+/// # Example
+///
+/// ```ignore
+/// let state_changes = StateChanges::listen(&ChaindexingRepo::new(&database_url)).await;
+/// let mut subscription = state_changes.subscribe();
+///
+/// while let Ok(change) = subscription.recv().await {
+///     if change.table_name == Nft::table_name() {
+///         let nft: Option<Nft> = change.new_state();
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct StateChanges {
+    sender: broadcast::Sender<StateChange>,
+}
+
+impl StateChanges {
+    pub async fn listen(repo: &ChaindexingRepo) -> Self {
+        let mut payloads = repo.listen(STATE_CHANGES_CHANNEL).await;
+        let (sender, _receiver) = broadcast::channel(STATE_CHANGES_CAPACITY);
+
+        tokio::spawn({
+            let sender = sender.downgrade();
+
+            async move {
+                while let Some(payload) = payloads.recv().await {
+                    let Some(sender) = sender.upgrade() else {
+                        break;
+                    };
+
+                    let change = match serde_json::from_str(&payload) {
+                        Ok(change) => change,
+                        Err(error) => {
+                            eprintln!("State Change Error: {error} (payload {payload})");
+                            continue;
+                        }
+                    };
+
+                    // Changes without subscribers get dropped
+                    let _ = sender.send(change);
+                }
+            }
+        });
+
+        Self { sender }
+    }
+
+    /// Receives the changes committed from now on. Lagging subscribers miss
+    /// the oldest ones, as with any broadcast channel.
+    pub fn subscribe(&self) -> broadcast::Receiver<StateChange> {
+        self.sender.subscribe()
+    }
+}
+
+/// Makes the client's session notify the state changes it commits
+pub(crate) async fn enable(client: &ChaindexingRepoClient) {
+    ChaindexingRepo::execute(client, "SET chaindexing.state_changes TO 'on'").await;
+}
+
+/// Change, without its views nor op, caused by the latest version of a state
+pub(super) fn from_latest_state_version(
+    table_name: &str,
+    latest_state_version: &HashMap<String, SqlValue>,
+) -> serde_json::Value {
+    let get_text = |field: &str| latest_state_version.get(field).and_then(SqlValue::to_text);
+    // Versions copied exactly have their numbers as text
+    let get_number = |field: &str| get_text(field).map(|number| number.parse::<i64>().unwrap());
+
+    json!({
+        "table_name": table_name,
+        "chain_id": get_number("chain_id"),
+        "block_number": get_number("block_number"),
+        "contract_address": get_text("contract_address"),
+        "block_hash": get_text("block_hash"),
+        "transaction_hash": get_text("transaction_hash"),
+        "log_index": get_number("log_index"),
+    })
+}
+
+/// Sets the op of the change, unless it is a retraction
+pub(super) fn set_op(change: &mut serde_json::Value, op: StateChangeOp) {
+    if change["op"].is_null() {
+        change["op"] = json!(op);
+    }
+}
+
+/// Retraction, without its views, of a state backtracked from the block
+pub(super) fn retraction(table_name: &str, chain_id: i64, block_number: i64) -> serde_json::Value {
+    json!({
+        "table_name": table_name,
+        "op": StateChangeOp::Retract,
+        "chain_id": chain_id,
+        "block_number": block_number,
+    })
+}
+
+/// Selects the notification of the change, adding the view of the row under the
+/// field, in sessions with state changes enabled
+pub(super) fn notify_query(
+    row_query: &str,
+    change: serde_json::Value,
+    view_field: &str,
+    table_name: &str,
+    params: &mut SqlParams,
+) -> String {
+    format!(
+        "WITH chaindexing_state AS ({row_query})
+        SELECT chaindexing_notify_state_change({change}::JSONB || jsonb_build_object({view_field}::TEXT, {view}))
+        FROM chaindexing_state
+        WHERE current_setting('chaindexing.state_changes', TRUE) = 'on'",
+        change = params.add(change),
+        view_field = params.add(view_field),
+        view = to_typed_json_value("chaindexing_state", table_name, params),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_changes_from_notifications() {
+        let latest_state_version = HashMap::from([
+            ("chain_id".to_string(), 1.into()),
+            ("block_number".to_string(), "17000000".into()),
+            ("contract_address".to_string(), "0xbc4c".into()),
+            ("block_hash".to_string(), "0xb1".into()),
+            ("transaction_hash".to_string(), "0xt1".into()),
+            ("log_index".to_string(), 3.into()),
+            ("token_id".to_string(), 9.into()),
+        ]);
+
+        let mut change = from_latest_state_version("nfts", &latest_state_version);
+        set_op(&mut change, StateChangeOp::Update);
+        change["new_view"] = json!({"token_id": 9});
+
+        let change: StateChange = serde_json::from_value(change).unwrap();
+
+        assert_eq!(change.op, StateChangeOp::Update);
+        assert_eq!(change.block_number, 17_000_000);
+        assert_eq!(change.log_index, Some(3));
+        assert_eq!(change.old_view, None);
+        assert_eq!(change.new_view, Some(json!({"token_id": 9})));
+        assert!(!change.is_truncated);
+    }
+
+    #[test]
+    fn reads_retractions_without_events() {
+        let mut change = retraction("nfts", 1, 17_000_000);
+        set_op(&mut change, StateChangeOp::Delete);
+
+        let change: StateChange = serde_json::from_value(change).unwrap();
+
+        assert_eq!(change.op, StateChangeOp::Retract);
+        assert_eq!(change.contract_address, None);
+        assert_eq!(change.transaction_hash, None);
+    }
+}
@@ -3,9 +3,10 @@ use std::collections::HashMap;
 use crate::{
     ChaindexingRepo, ChaindexingRepoTxnClient, ExecutesWithRawQuery, LoadsDataWithRawQuery,
 };
-use crate::{ChaindexingRepoClient, Event};
+use crate::{Event, SqlParams, SqlValue};
 
-use super::{serde_map_to_string_map, to_columns_and_values};
+use super::updates::{Delta, Updates};
+use super::{serde_map_to_sql_map, to_columns_and_values, to_exact_json, StateRow};
 
 pub const STATE_VERSIONS_TABLE_PREFIX: &str = "chaindexing_state_versions_for_";
 pub const STATE_VERSIONS_UNIQUE_FIELDS: [&str; 2] =
@@ -17,37 +18,47 @@ impl StateVersions {
     pub async fn get<'a>(
         from_block_number: i64,
         chain_id: i64,
+        contract_addresses: &[String],
         state_table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> Vec<HashMap<String, String>> {
+    ) -> Vec<HashMap<String, SqlValue>> {
+        let mut params = SqlParams::new();
+        let contract_addresses_filter = if contract_addresses.is_empty() {
+            "".to_string()
+        } else {
+            format!(
+                "AND contract_address IN ({})",
+                params.add_list(contract_addresses)
+            )
+        };
+
         let query = format!(
-            "SELECT * FROM {table_name} 
+            "SELECT {state} FROM {table_name} chaindexing_state
             WHERE chain_id = {chain_id}
-            AND block_number >= {from_block_number}",
+            AND block_number >= {from_block_number}
+            {contract_addresses_filter}",
+            state = to_exact_json("chaindexing_state"),
             table_name = StateVersion::table_name(state_table_name),
         );
 
-        ChaindexingRepo::load_data_list_in_txn::<HashMap<String, serde_json::Value>>(client, &query)
-            .await
-            .iter()
-            .map(serde_map_to_string_map)
-            .collect()
+        ChaindexingRepo::load_data_list_in_txn_with_params::<
+            StateRow<HashMap<String, serde_json::Value>>,
+        >(client, &query, params.get_values())
+        .await
+        .iter()
+        .map(|row| serde_map_to_sql_map(&row.state))
+        .collect()
     }
 
-    pub fn get_ids(state_versions: &[HashMap<String, String>]) -> Vec<String> {
+    pub fn get_ids(state_versions: &[HashMap<String, SqlValue>]) -> Vec<String> {
         state_versions
             .iter()
-            .map(|state_version| state_version.get("state_version_id").unwrap())
-            .cloned()
+            .map(|state_version| state_version.get("state_version_id").unwrap().to_text().unwrap())
             .collect()
     }
 
-    pub fn get_group_ids(state_versions: &[HashMap<String, String>]) -> Vec<String> {
-        state_versions
-            .iter()
-            .map(|state_version| state_version.get("state_version_group_id").unwrap())
-            .cloned()
-            .collect()
+    pub fn get_group_ids(state_versions: &[HashMap<String, SqlValue>]) -> Vec<String> {
+        state_versions.iter().map(StateVersion::get_group_id).collect()
     }
 
     pub async fn delete_by_ids<'a>(
@@ -55,34 +66,40 @@ impl StateVersions {
         state_table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
     ) {
+        let mut params = SqlParams::new();
         let query = format!(
             "DELETE FROM {table_name}
             WHERE state_version_id IN ({ids})",
             table_name = StateVersion::table_name(state_table_name),
-            ids = ids.join(",")
+            ids = params.add_list(ids)
         );
 
-        ChaindexingRepo::execute_in_txn(client, &query).await;
+        ChaindexingRepo::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
 
     pub async fn get_latest<'a>(
         group_ids: &[String],
         state_table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> Vec<HashMap<String, String>> {
+    ) -> Vec<HashMap<String, SqlValue>> {
+        let mut params = SqlParams::new();
         let query = format!(
-            "SELECT DISTINCT ON (state_version_group_id) * FROM {table_name} 
+            "SELECT DISTINCT ON (state_version_group_id) {state}
+            FROM {table_name} chaindexing_state
             WHERE state_version_group_id IN ({group_ids}) 
-            ORDER BY state_version_group_id, block_number, log_index DESC",
+            ORDER BY state_version_group_id, block_number DESC, log_index DESC, state_version_id DESC",
+            state = to_exact_json("chaindexing_state"),
             table_name = StateVersion::table_name(state_table_name),
-            group_ids = group_ids.iter().map(|id| format!("'{id}'")).collect::<Vec<_>>().join(",")
+            group_ids = params.add_list(group_ids)
         );
 
-        ChaindexingRepo::load_data_list_in_txn::<HashMap<String, serde_json::Value>>(client, &query)
-            .await
-            .iter()
-            .map(serde_map_to_string_map)
-            .collect()
+        ChaindexingRepo::load_data_list_in_txn_with_params::<
+            StateRow<HashMap<String, serde_json::Value>>,
+        >(client, &query, params.get_values())
+        .await
+        .iter()
+        .map(|row| serde_map_to_sql_map(&row.state))
+        .collect()
     }
 }
 
@@ -93,142 +110,175 @@ impl StateVersion {
         format!("{STATE_VERSIONS_TABLE_PREFIX}{state_table_name}")
     }
 
-    pub fn was_deleted(state_version: &HashMap<String, String>) -> bool {
-        state_version.get("state_version_is_deleted").unwrap() == "true"
+    pub fn was_deleted(state_version: &HashMap<String, SqlValue>) -> bool {
+        *state_version.get("state_version_is_deleted").unwrap() == SqlValue::Bool(true)
     }
 
-    pub fn get_group_id(state_version: &HashMap<String, String>) -> String {
-        state_version.get("state_version_group_id").unwrap().to_owned()
+    pub fn get_group_id(state_version: &HashMap<String, SqlValue>) -> String {
+        state_version.get("state_version_group_id").unwrap().to_text().unwrap()
     }
 
+    /// Starts a new group of versions, under the given group id or a random one
     pub async fn create<'a>(
-        state: &HashMap<String, String>,
+        state: &HashMap<String, SqlValue>,
+        group_id: Option<String>,
         state_table_name: &str,
         event: &Event,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
+    ) -> HashMap<String, SqlValue> {
+        let state_version = Self::start_group(state, group_id, event);
+
+        Self::append(
+            &state_version,
+            &HashMap::new(),
+            state_table_name,
+            event,
+            client,
+        )
+        .await
+    }
+
+    /// First version of a group, which the next ones keep the creation block of
+    fn start_group(
+        state: &HashMap<String, SqlValue>,
+        group_id: Option<String>,
+        event: &Event,
+    ) -> HashMap<String, SqlValue> {
         let mut state_version = state.clone();
         state_version.insert(
             "state_version_group_id".to_owned(),
-            uuid::Uuid::new_v4().to_string(),
+            group_id.unwrap_or_else(|| uuid::Uuid::new_v4().to_string()).into(),
+        );
+        state_version.insert("created_at_block".to_owned(), event.block_number.into());
+        state_version.insert(
+            "created_at_timestamp".to_owned(),
+            (event.get_block_timestamp() as i64).into(),
         );
 
-        Self::append(&state_version, state_table_name, event, client).await
+        state_version
     }
 
     pub async fn update<'a>(
-        state: &HashMap<String, String>,
-        updates: &HashMap<String, String>,
+        state: &HashMap<String, SqlValue>,
+        updates: &Updates,
         state_table_name: &str,
         event: &Event,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
-        let mut state_version = state.clone();
-        state_version.extend(updates.clone());
-        Self::append(&state_version, state_table_name, event, client).await
-    }
-    pub async fn update_without_txn(
-        state: &HashMap<String, String>,
-        updates: &HashMap<String, String>,
-        state_table_name: &str,
-        event: &Event,
-        client: &mut ChaindexingRepoClient,
-    ) -> HashMap<String, String> {
+    ) -> HashMap<String, SqlValue> {
         let mut state_version = state.clone();
-        state_version.extend(updates.clone());
-        Self::append_without_txn(&state_version, state_table_name, event, client).await
+        state_version.extend(updates.values.clone());
+        Self::append(
+            &state_version,
+            &updates.deltas,
+            state_table_name,
+            event,
+            client,
+        )
+        .await
     }
 
     pub async fn delete<'a>(
-        state: &HashMap<String, String>,
+        state: &HashMap<String, SqlValue>,
         state_table_name: &str,
         event: &Event,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
-        let mut state_version = state.clone();
-        state_version.insert("state_version_is_deleted".to_owned(), "true".to_owned());
-        Self::append(&state_version, state_table_name, event, client).await
-    }
-    pub async fn delete_without_txn(
-        state: &HashMap<String, String>,
-        state_table_name: &str,
-        event: &Event,
-        client: &ChaindexingRepoClient,
-    ) -> HashMap<String, String> {
+    ) -> HashMap<String, SqlValue> {
         let mut state_version = state.clone();
-        state_version.insert("state_version_is_deleted".to_owned(), "true".to_owned());
-        Self::append_without_txn(&state_version, state_table_name, event, client).await
+        state_version.insert("state_version_is_deleted".to_owned(), true.into());
+        Self::append(
+            &state_version,
+            &HashMap::new(),
+            state_table_name,
+            event,
+            client,
+        )
+        .await
     }
 
     async fn append<'a>(
-        partial_state_version: &HashMap<String, String>,
+        partial_state_version: &HashMap<String, SqlValue>,
+        deltas: &HashMap<String, Delta>,
         state_table_name: &str,
         event: &Event,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
-        let query = Self::append_query(partial_state_version, state_table_name, event);
+    ) -> HashMap<String, SqlValue> {
+        let (query, params) =
+            Self::append_query(partial_state_version, deltas, state_table_name, event);
 
-        serde_map_to_string_map(
-            &ChaindexingRepo::load_data_in_txn::<HashMap<String, serde_json::Value>>(
-                client, &query,
-            )
+        serde_map_to_sql_map(
+            &ChaindexingRepo::load_data_in_txn_with_params::<
+                StateRow<HashMap<String, serde_json::Value>>,
+            >(client, &query, params.get_values())
             .await
-            .unwrap(),
-        )
-    }
-
-    async fn append_without_txn(
-        partial_state_version: &HashMap<String, String>,
-        state_table_name: &str,
-        event: &Event,
-        client: &ChaindexingRepoClient,
-    ) -> HashMap<String, String> {
-        let query = Self::append_query(partial_state_version, state_table_name, event);
-
-        serde_map_to_string_map(
-            &ChaindexingRepo::load_data::<HashMap<String, serde_json::Value>>(client, &query)
-                .await
-                .unwrap(),
+            .unwrap()
+            .state,
         )
     }
 
+    /// Deltas get applied to the values of the latest version appended, which
+    /// the state's view may not hold yet while its refresh is deferred
     fn append_query(
-        partial_state_version: &HashMap<String, String>,
+        partial_state_version: &HashMap<String, SqlValue>,
+        deltas: &HashMap<String, Delta>,
         state_table_name: &str,
         event: &Event,
-    ) -> String {
+    ) -> (String, SqlParams) {
         let mut state_version = partial_state_version.clone();
         state_version.extend(Self::extract_part_from_event(event));
+        state_version.retain(|field, _value| !deltas.contains_key(field));
+
+        let mut params = SqlParams::new();
+        let (mut columns, mut values) = to_columns_and_values(&state_version, &mut params);
+
+        if !deltas.is_empty() {
+            let group_id = params.add(Self::get_group_id(&state_version));
 
-        let (columns, values) = to_columns_and_values(&state_version);
+            for (field, delta) in deltas {
+                let current_value = format!(
+                    "(SELECT {field} FROM {table_name} WHERE state_version_group_id = {group_id}
+                    ORDER BY state_version_id DESC LIMIT 1)",
+                    table_name = Self::table_name(state_table_name),
+                );
 
-        format!(
-            "INSERT INTO {table_name} ({columns}) VALUES ({values})
-            RETURNING *",
+                columns.push(field.to_owned());
+                values.push(delta.to_sql(&current_value, &mut params));
+            }
+        }
+
+        let query = format!(
+            "INSERT INTO {table_name} AS chaindexing_state ({columns}) VALUES ({values})
+            RETURNING {state}",
             table_name = Self::table_name(state_table_name),
+            state = to_exact_json("chaindexing_state"),
             columns = columns.join(","),
             values = values.join(",")
-        )
+        );
+
+        (query, params)
     }
 
-    fn extract_part_from_event(event: &Event) -> HashMap<String, String> {
+    fn extract_part_from_event(event: &Event) -> HashMap<String, SqlValue> {
         HashMap::from([
             (
                 "contract_address".to_string(),
-                event.contract_address.to_owned(),
+                event.contract_address.as_str().into(),
             ),
-            ("chain_id".to_string(), event.chain_id.to_string()),
+            ("chain_id".to_string(), event.chain_id.into()),
             (
                 "transaction_hash".to_string(),
-                event.transaction_hash.to_owned(),
+                event.transaction_hash.as_str().into(),
             ),
             (
                 "transaction_index".to_string(),
-                event.transaction_index.to_string(),
+                event.transaction_index.into(),
+            ),
+            ("log_index".to_string(), event.log_index.into()),
+            ("block_number".to_string(), event.block_number.into()),
+            (
+                "block_timestamp".to_string(),
+                (event.get_block_timestamp() as i64).into(),
             ),
-            ("log_index".to_string(), event.log_index.to_string()),
-            ("block_number".to_string(), event.block_number.to_string()),
-            ("block_hash".to_string(), event.block_hash.to_owned()),
+            ("block_hash".to_string(), event.block_hash.as_str().into()),
         ])
     }
 }
@@ -1,25 +1,54 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
-use crate::ChaindexingRepoClient;
 use crate::{ChaindexingRepo, ChaindexingRepoTxnClient};
-use crate::{ExecutesWithRawQuery, LoadsDataWithRawQuery};
+use crate::{ExecutesWithRawQuery, LoadsDataWithRawQuery, SqlParams, SqlValue};
 
+use super::state_changes::{self, StateChangeOp};
 use super::state_versions::{StateVersion, StateVersions, STATE_VERSIONS_UNIQUE_FIELDS};
-use super::{serde_map_to_string_map, to_and_filters, to_columns_and_values};
+use super::{serde_map_to_sql_map, to_and_filters, to_columns_and_values, to_exact_json};
+use super::{to_typed_json, StateRow};
 
 pub struct StateViews;
 
 impl StateViews {
+    /// Refreshes the views of the groups, backtracked from the block, to their
+    /// latest versions left, retracting their changes
     pub async fn refresh<'a>(
         state_version_group_ids: &[String],
         table_name: &str,
+        (chain_id, block_number): (i64, i64),
         client: &ChaindexingRepoTxnClient<'a>,
     ) {
         let latest_state_versions =
             StateVersions::get_latest(state_version_group_ids, table_name, client).await;
 
+        let refreshed_group_ids: HashSet<_> =
+            latest_state_versions.iter().map(StateVersion::get_group_id).collect();
+
+        let retraction = state_changes::retraction(table_name, chain_id, block_number);
+
         for latest_state_version in latest_state_versions {
-            StateView::refresh(&latest_state_version, table_name, client).await
+            StateView::replace(
+                &latest_state_version,
+                retraction.clone(),
+                table_name,
+                client,
+            )
+            .await;
+        }
+
+        // States created after the backtracked block no longer have any version
+        for state_version_group_id in state_version_group_ids
+            .iter()
+            .filter(|group_id| !refreshed_group_ids.contains(*group_id))
+        {
+            StateView::delete(
+                state_version_group_id,
+                retraction.clone(),
+                table_name,
+                client,
+            )
+            .await;
         }
     }
 }
@@ -28,61 +57,91 @@ pub struct StateView;
 
 impl StateView {
     pub async fn get_complete<'a>(
-        state_view: &HashMap<String, String>,
+        state_view: &HashMap<String, SqlValue>,
+        table_name: &str,
+        client: &ChaindexingRepoTxnClient<'a>,
+    ) -> HashMap<String, SqlValue> {
+        Self::find_complete(state_view, table_name, client).await.unwrap()
+    }
+
+    pub async fn find_complete<'a>(
+        state_view: &HashMap<String, SqlValue>,
         table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
-    ) -> HashMap<String, String> {
+    ) -> Option<HashMap<String, SqlValue>> {
+        let (query, params) = Self::find_complete_query(state_view, table_name);
+
+        ChaindexingRepo::load_data_in_txn_with_params::<
+            StateRow<HashMap<String, serde_json::Value>>,
+        >(client, &query, params.get_values())
+        .await
+        .map(|row| serde_map_to_sql_map(&row.state))
+    }
+    fn find_complete_query(
+        state_view: &HashMap<String, SqlValue>,
+        table_name: &str,
+    ) -> (String, SqlParams) {
+        let mut params = SqlParams::new();
         let query = format!(
-            "SELECT * FROM {table_name} WHERE {filters}",
-            filters = to_and_filters(state_view),
+            "SELECT {state} FROM {table_name} chaindexing_state WHERE {filters}",
+            state = to_exact_json("chaindexing_state"),
+            filters = to_and_filters(state_view, &mut params),
         );
 
-        serde_map_to_string_map(
-            &ChaindexingRepo::load_data_in_txn::<HashMap<String, serde_json::Value>>(
-                client, &query,
-            )
-            .await
-            .unwrap(),
-        )
+        (query, params)
     }
 
     pub async fn refresh<'a>(
-        latest_state_version: &HashMap<String, String>,
+        latest_state_version: &HashMap<String, SqlValue>,
         table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
     ) {
-        let state_version_group_id = StateVersion::get_group_id(latest_state_version);
+        let change = state_changes::from_latest_state_version(table_name, latest_state_version);
 
-        if StateVersion::was_deleted(latest_state_version) {
-            Self::delete(&state_version_group_id, table_name, client).await;
-        } else {
-            let new_state_view = Self::from_latest_state_version(latest_state_version);
-
-            Self::delete(&state_version_group_id, table_name, client).await;
-            Self::create(&new_state_view, table_name, client).await;
-        }
+        Self::replace(latest_state_version, change, table_name, client).await;
     }
 
-    pub async fn refresh_without_txn(
-        latest_state_version: &HashMap<String, String>,
+    /// Replaces the group's view with the latest version's, notifying the change
+    async fn replace<'a>(
+        latest_state_version: &HashMap<String, SqlValue>,
+        mut change: serde_json::Value,
         table_name: &str,
-        client: &ChaindexingRepoClient,
+        client: &ChaindexingRepoTxnClient<'a>,
     ) {
         let state_version_group_id = StateVersion::get_group_id(latest_state_version);
 
         if StateVersion::was_deleted(latest_state_version) {
-            Self::delete_without_txn(&state_version_group_id, table_name, client).await;
-        } else {
-            let new_state_view = Self::from_latest_state_version(latest_state_version);
-
-            Self::delete_without_txn(&state_version_group_id, table_name, client).await;
-            Self::create_without_txn(&new_state_view, table_name, client).await;
+            return Self::delete(&state_version_group_id, change, table_name, client).await;
         }
+
+        let old_state_view =
+            Self::delete_returning(&state_version_group_id, table_name, client).await;
+
+        state_changes::set_op(
+            &mut change,
+            match old_state_view {
+                Some(_) => StateChangeOp::Update,
+                None => StateChangeOp::Create,
+            },
+        );
+        change["old_view"] = old_state_view.unwrap_or_default();
+
+        let new_state_view = Self::from_latest_state_version(latest_state_version);
+        let (create_query, mut params) = Self::create_query(&new_state_view, table_name);
+        let query = state_changes::notify_query(
+            &format!("{create_query} RETURNING *"),
+            change,
+            "new_view",
+            table_name,
+            &mut params,
+        );
+
+        ChaindexingRepo::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
 
-    fn from_latest_state_version(
-        latest_state_version: &HashMap<String, String>,
-    ) -> HashMap<String, String> {
+    pub(super) fn from_latest_state_version(
+        latest_state_version: &HashMap<String, SqlValue>,
+    ) -> HashMap<String, SqlValue> {
         latest_state_version
             .clone()
             .into_iter()
@@ -90,55 +149,72 @@ impl StateView {
             .collect()
     }
 
+    /// Deletes the group's view, notifying the change
     async fn delete<'a>(
         state_version_group_id: &str,
+        mut change: serde_json::Value,
         table_name: &str,
         client: &ChaindexingRepoTxnClient<'a>,
     ) {
-        ChaindexingRepo::execute_in_txn(
-            client,
-            &Self::delete_query(state_version_group_id, table_name),
-        )
-        .await;
+        state_changes::set_op(&mut change, StateChangeOp::Delete);
+
+        let mut params = SqlParams::new();
+        let delete_query = Self::delete_query(state_version_group_id, table_name, &mut params);
+        let query = state_changes::notify_query(
+            &format!("{delete_query} RETURNING *"),
+            change,
+            "old_view",
+            table_name,
+            &mut params,
+        );
+
+        ChaindexingRepo::execute_in_txn_with_params(client, &query, params.get_values()).await;
     }
-    async fn delete_without_txn(
+
+    /// Deletes the group's view, returning it
+    async fn delete_returning<'a>(
         state_version_group_id: &str,
         table_name: &str,
-        client: &ChaindexingRepoClient,
-    ) {
-        ChaindexingRepo::execute(
+        client: &ChaindexingRepoTxnClient<'a>,
+    ) -> Option<serde_json::Value> {
+        let mut params = SqlParams::new();
+        let query = format!(
+            "{delete_query} RETURNING {state}",
+            delete_query = Self::delete_query(state_version_group_id, table_name, &mut params),
+            state = to_typed_json(table_name, table_name, &mut params),
+        );
+
+        ChaindexingRepo::load_data_in_txn_with_params::<StateRow<serde_json::Value>>(
             client,
-            &Self::delete_query(state_version_group_id, table_name),
+            &query,
+            params.get_values(),
         )
-        .await;
+        .await
+        .map(|row| row.state)
     }
-    fn delete_query(state_version_group_id: &str, table_name: &str) -> String {
+    fn delete_query(
+        state_version_group_id: &str,
+        table_name: &str,
+        params: &mut SqlParams,
+    ) -> String {
         format!(
-            "DELETE FROM {table_name} WHERE state_version_group_id = '{state_version_group_id}'",
+            "DELETE FROM {table_name} WHERE state_version_group_id = {}",
+            params.add(state_version_group_id)
         )
     }
 
-    async fn create<'a>(
-        new_state_view: &HashMap<String, String>,
+    fn create_query(
+        new_state_view: &HashMap<String, SqlValue>,
         table_name: &str,
-        client: &ChaindexingRepoTxnClient<'a>,
-    ) {
-        ChaindexingRepo::execute_in_txn(client, &Self::create_query(new_state_view, table_name))
-            .await;
-    }
-    async fn create_without_txn(
-        new_state_view: &HashMap<String, String>,
-        table_name: &str,
-        client: &ChaindexingRepoClient,
-    ) {
-        ChaindexingRepo::execute(client, &Self::create_query(new_state_view, table_name)).await;
-    }
-    fn create_query(new_state_view: &HashMap<String, String>, table_name: &str) -> String {
-        let (columns, values) = to_columns_and_values(new_state_view);
-        format!(
+    ) -> (String, SqlParams) {
+        let mut params = SqlParams::new();
+        let (columns, values) = to_columns_and_values(new_state_view, &mut params);
+        let query = format!(
             "INSERT INTO {table_name} ({columns}) VALUES ({values})",
             columns = columns.join(","),
             values = values.join(",")
-        )
+        );
+
+        (query, params)
     }
 }
@@ -1,9 +1,36 @@
 use std::{collections::HashMap, fmt::Debug};
 
+use crate::{SqlParams, SqlValue};
+use serde::{Deserialize, Serialize};
+
 /// Represents the fields to be updated in a state
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct Updates {
-    pub(super) values: HashMap<String, String>,
+    pub(super) values: HashMap<String, SqlValue>,
+    pub(super) deltas: HashMap<String, Delta>,
+}
+
+/// Change applied to a field's current value in the database, rather than to
+/// the value in memory, so concurrent changes don't get lost
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub(super) enum Delta {
+    Increment(SqlValue),
+    Decrement(SqlValue),
+    Max(SqlValue),
+    Min(SqlValue),
+}
+
+impl Delta {
+    pub(super) fn to_sql(&self, current_value: &str, params: &mut SqlParams) -> String {
+        match self {
+            Delta::Increment(value) => format!("{current_value} + {}", params.add(value.clone())),
+            Delta::Decrement(value) => format!("{current_value} - {}", params.add(value.clone())),
+            Delta::Max(value) => {
+                format!("GREATEST({current_value}, {})", params.add(value.clone()))
+            }
+            Delta::Min(value) => format!("LEAST({current_value}, {})", params.add(value.clone())),
+        }
+    }
 }
 
 impl Updates {
@@ -21,9 +48,29 @@ impl Updates {
     /// nft_state.update(&updates, &context).await;
     /// ```
     pub fn new(field: impl ToString, value: impl ToString) -> Self {
-        Self {
-            values: HashMap::from([(field.to_string(), value.to_string())]),
-        }
+        Self::default().add(field, value)
+    }
+    /// Creates a new Updates instance adding to a numeric field's current value,
+    /// such as `balance = balance + amount`.
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let updates = Updates::increment("balance", amount);
+    /// token_balance.update(&updates, &context).await;
+    /// ```
+    pub fn increment(field: impl ToString, by: impl ToString) -> Self {
+        Self::default().add_increment(field, by)
+    }
+    /// Creates a new Updates instance subtracting from a numeric field's current value
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// let updates = Updates::decrement("balance", amount);
+    /// ```
+    pub fn decrement(field: impl ToString, by: impl ToString) -> Self {
+        Self::default().add_decrement(field, by)
     }
     /// Adds a new update to the existing set of updates by moving the
     /// original updates
@@ -58,6 +105,100 @@ impl Updates {
     /// updates.add_mut("token_id", token_id);// updates not moved
     /// ```
     pub fn add_mut(&mut self, field: impl ToString, value: impl ToString) {
-        self.values.insert(field.to_string(), value.to_string());
+        self.deltas.remove(&field.to_string());
+        self.values.insert(field.to_string(), SqlValue::Text(value.to_string()));
+    }
+    /// Adds an increment to the existing set of updates by moving the
+    /// original updates
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Updates::new("owner_address", address).add_increment("transfer_count", 1);
+    /// ```
+    pub fn add_increment(mut self, field: impl ToString, by: impl ToString) -> Self {
+        self.add_delta(field, Delta::Increment(SqlValue::Text(by.to_string())));
+        self
+    }
+    /// Adds a decrement to the existing set of updates by moving the
+    /// original updates
+    pub fn add_decrement(mut self, field: impl ToString, by: impl ToString) -> Self {
+        self.add_delta(field, Delta::Decrement(SqlValue::Text(by.to_string())));
+        self
+    }
+    /// Adds keeping the greater of a field's current value and the given one
+    ///
+    /// # Example
+    ///
+    /// ```ignore
+    /// Updates::new("last_price", price).add_max("highest_price", price);
+    /// ```
+    pub fn add_max(mut self, field: impl ToString, value: impl ToString) -> Self {
+        self.add_delta(field, Delta::Max(SqlValue::Text(value.to_string())));
+        self
+    }
+    /// Adds keeping the lesser of a field's current value and the given one
+    pub fn add_min(mut self, field: impl ToString, value: impl ToString) -> Self {
+        self.add_delta(field, Delta::Min(SqlValue::Text(value.to_string())));
+        self
+    }
+    fn add_delta(&mut self, field: impl ToString, delta: Delta) {
+        self.values.remove(&field.to_string());
+        self.deltas.insert(field.to_string(), delta);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn applies_deltas_to_current_values() {
+        let updates = Updates::increment("balance", 5).add_decrement("supply", 2);
+        let mut params = SqlParams::new();
+
+        assert_eq!(
+            updates.deltas["balance"].to_sql("current_balance", &mut params),
+            "current_balance + $1"
+        );
+        assert_eq!(
+            updates.deltas["supply"].to_sql("current_supply", &mut params),
+            "current_supply - $2"
+        );
+        assert_eq!(
+            params.get_values(),
+            &[
+                SqlValue::Text("5".to_string()),
+                SqlValue::Text("2".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn keeps_extremes_of_current_values() {
+        let updates = Updates::default().add_max("high", 7).add_min("low", 3);
+        let mut params = SqlParams::new();
+
+        assert_eq!(
+            updates.deltas["high"].to_sql("current_high", &mut params),
+            "GREATEST(current_high, $1)"
+        );
+        assert_eq!(
+            updates.deltas["low"].to_sql("current_low", &mut params),
+            "LEAST(current_low, $2)"
+        );
+    }
+
+    #[test]
+    fn keeps_the_last_update_of_a_field() {
+        let updates = Updates::increment("balance", 5).add("balance", 10);
+
+        assert!(updates.deltas.is_empty());
+        assert_eq!(updates.values["balance"], SqlValue::Text("10".to_string()));
+
+        let updates = Updates::new("balance", 10).add_increment("balance", 5);
+
+        assert!(updates.values.is_empty());
+        assert!(updates.deltas.contains_key("balance"));
     }
 }